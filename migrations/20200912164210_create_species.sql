@@ -0,0 +1,23 @@
+-- Tree species available in a server
+CREATE TABLE species (
+    id Smallint NOT NULL -- Species identifier unique per server (see trees.species)
+  , guild_id Bigint NOT NULL -- Discord guild id
+  , emoji Varchar NOT NULL -- Discord emoji representation
+  , name Varchar NOT NULL -- Displayable name
+  , pallet_cost Int NOT NULL DEFAULT 0 -- Cost per unit in a pallet, 0 disables purchase
+  , default_qty Int NOT NULL DEFAULT 0 -- Seedlings given to new players, -1 for infinity
+  , coins Int NOT NULL DEFAULT 1 -- Coins given for each tree planted
+  , enabled Boolean NOT NULL DEFAULT TRUE -- Disabled species can't be planted nor bought
+  , PRIMARY KEY (guild_id, id)
+);
+
+-- Species used to be hardcoded, give them to existing servers
+INSERT INTO species (id, guild_id, emoji, name, pallet_cost, default_qty, coins)
+SELECT defaults.id, servers.id, defaults.emoji, defaults.name, defaults.pallet_cost, defaults.default_qty, defaults.coins
+FROM servers CROSS JOIN (VALUES
+    (1, '🌲', 'Evergreen Tree', 0, -1, 1)
+  , (2, '🌳', 'Deciduous Tree', 12, 50, 1)
+  , (3, '🌴', 'Palm Tree', 15, 30, 1)
+  , (4, '🌵', 'Cactus', 25, 20, 2)
+  , (5, '🎍', 'Bamboo', 50, 10, 3)
+) AS defaults (id, emoji, name, pallet_cost, default_qty, coins);
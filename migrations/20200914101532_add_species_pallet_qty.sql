@@ -0,0 +1,3 @@
+ALTER TABLE species
+    ADD COLUMN pallet_qty Int NOT NULL DEFAULT 10 -- Seedlings obtained by unpacking a pallet
+;
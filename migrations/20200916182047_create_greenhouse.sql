@@ -0,0 +1,18 @@
+ALTER TABLE players
+    ADD COLUMN greenhouse_upgrade Smallint NOT NULL DEFAULT 1 -- Greenhouse tier, gives the number of slots
+;
+
+ALTER TABLE species
+    ADD COLUMN growth_rate Int NOT NULL DEFAULT 6 -- Seedlings grown per hour in a greenhouse slot, 0 to disable
+;
+
+-- Player greenhouse slots, empty slots are not stored
+CREATE TABLE greenhouse_slots (
+    player_id Int NOT NULL -- Player identifier (see players.id)
+  , slot Smallint NOT NULL -- Slot number, starting at 1
+  , species Smallint NOT NULL -- Species growing in this slot
+  , remaining Int NOT NULL -- Seedlings still to grow
+  , grown Int NOT NULL DEFAULT 0 -- Grown seedlings waiting to be collected
+  , progress Real NOT NULL DEFAULT 0 -- Progress towards the next seedling, between 0 and 1
+  , PRIMARY KEY (player_id, slot)
+);
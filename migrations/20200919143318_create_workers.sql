@@ -0,0 +1,11 @@
+ALTER TABLE servers
+    ADD COLUMN max_workers Smallint NOT NULL DEFAULT 3 -- Maximum workers a player can hire
+;
+
+-- Workers hired by players to plant trees
+CREATE TABLE workers (
+    id Serial PRIMARY KEY
+  , player_id Int NOT NULL -- Employer (see players.id)
+  , channel_id Bigint -- Forest channel the worker plants in, NULL when unassigned
+  , progress Real NOT NULL DEFAULT 0 -- Progress towards the next planted tree, between 0 and 1
+);
@@ -0,0 +1,8 @@
+-- Planting cooldown overrides, the server-wide cooldown is servers.plant_cooldown
+CREATE TABLE cooldown_rules (
+    guild_id Bigint NOT NULL
+  , channel_id Bigint NOT NULL -- Channel the override applies to, 0 for every channel
+  , species Smallint NOT NULL -- Species the override applies to, 0 for every species
+  , cooldown Smallint NOT NULL -- Cooldown in seconds
+  , PRIMARY KEY (guild_id, channel_id, species)
+);
@@ -0,0 +1,11 @@
+-- Running planting cooldowns, shared by every bot process
+CREATE TABLE plant_cooldowns (
+    guild_id Bigint NOT NULL
+  , user_id Bigint NOT NULL
+  , channel_id Bigint NOT NULL -- Channel scope of the applied cooldown rule, 0 for every channel
+  , species Smallint NOT NULL -- Species scope of the applied cooldown rule, 0 for every species
+  , expires_at Timestamptz NOT NULL -- Time at which a tree can be planted again
+  , PRIMARY KEY (guild_id, user_id, channel_id, species)
+);
+
+CREATE INDEX plant_cooldowns_expires_at ON plant_cooldowns (expires_at);
@@ -0,0 +1,14 @@
+-- Trees planted per day, for time-windowed stats (see trees for all-time counts)
+-- Trees planted before this table existed only count in all-time stats
+CREATE TABLE tree_days (
+    day Date NOT NULL -- UTC day the trees have been planted on
+  , species Smallint NOT NULL
+  , count Int NOT NULL DEFAULT 1
+  , user_id Bigint NOT NULL
+  , channel_id Bigint NOT NULL
+  , guild_id Bigint NOT NULL
+  , PRIMARY KEY (day, species, user_id, channel_id)
+);
+
+CREATE INDEX tree_days_guild_id_day ON tree_days (guild_id, day);
+CREATE INDEX tree_days_channel_id_day ON tree_days (channel_id, day);
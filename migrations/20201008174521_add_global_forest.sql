@@ -0,0 +1,3 @@
+ALTER TABLE servers
+    ADD COLUMN global_forest Boolean NOT NULL DEFAULT TRUE -- Whether trees of this server count in global stats
+;
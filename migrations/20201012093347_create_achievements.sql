@@ -0,0 +1,7 @@
+-- Achievements unlocked by players (see achievements.rs for their definitions)
+CREATE TABLE achievements (
+    player_id Int NOT NULL -- See players.id
+  , achievement Varchar NOT NULL -- Achievement identifier
+  , unlocked_at Timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+  , PRIMARY KEY (player_id, achievement)
+);
@@ -0,0 +1,30 @@
+ALTER TABLE servers
+    ADD COLUMN milestones_channel Bigint -- Channel milestones are announced in, NULL to announce them where they are reached
+  , ADD COLUMN milestones Bigint[] NOT NULL DEFAULT '{100,1000,10000}' -- Tree totals celebrated when reached
+;
+
+-- Milestones reached by channel forests and server forests, each one is only announced once
+CREATE TABLE milestones (
+    guild_id Bigint NOT NULL
+  , channel_id Bigint NOT NULL -- Channel forest, 0 for the server forest
+  , threshold Bigint NOT NULL
+  , reached_at Timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+  , PRIMARY KEY (guild_id, channel_id, threshold)
+);
+
+-- forests which already passed the default milestones are not celebrated again
+INSERT INTO milestones (guild_id, channel_id, threshold)
+SELECT guild_id, channel_id, threshold
+FROM (SELECT guild_id, channel_id, SUM(count) AS total FROM trees GROUP BY guild_id, channel_id) forests
+CROSS JOIN unnest('{100,1000,10000}'::bigint[]) AS threshold
+WHERE total >= threshold;
+
+INSERT INTO milestones (guild_id, channel_id, threshold)
+SELECT guild_id, 0, threshold
+FROM (SELECT guild_id, SUM(count) AS total FROM trees GROUP BY guild_id) forests
+CROSS JOIN unnest('{100,1000,10000}'::bigint[]) AS threshold
+WHERE total >= threshold;
+
+-- totals are computed after each planted tree
+CREATE INDEX trees_channel_id ON trees (channel_id);
+CREATE INDEX trees_guild_id ON trees (guild_id);
@@ -0,0 +1,19 @@
+ALTER TABLE servers
+    ADD COLUMN daily_coin_gifts Int NOT NULL DEFAULT 500 -- Coins a player can give away per day, 0 to disable coin gifts
+  , ADD COLUMN daily_item_gifts Int NOT NULL DEFAULT 100 -- Seedlings and pallets a player can give away per day, 0 to disable item gifts
+;
+
+-- Ledger of coins and items given by players to other players
+CREATE TABLE transfers (
+    id Serial PRIMARY KEY
+  , guild_id Bigint NOT NULL
+  , giver_id Bigint NOT NULL -- Discord user id of the giver
+  , receiver_id Bigint NOT NULL -- Discord user id of the receiver
+  , item_type Smallint -- NULL for coins
+  , item_id Smallint -- Species of the item, NULL for coins
+  , amount Int NOT NULL
+  , created_at Timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX transfers_giver ON transfers (guild_id, giver_id, created_at);
+CREATE INDEX transfers_receiver ON transfers (guild_id, receiver_id, created_at);
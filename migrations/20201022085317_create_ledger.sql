@@ -0,0 +1,19 @@
+-- Append-only ledger of every change of the coins and items of players
+CREATE TABLE ledger (
+    id Bigserial PRIMARY KEY
+  , player_id Int NOT NULL -- See players.id
+  , item_type Smallint -- NULL for coins
+  , item_id Smallint -- Species of the item, NULL for coins
+  , delta Int NOT NULL
+  , reason Varchar(16) NOT NULL -- What the change comes from: plant, purchase, gift...
+  , created_at Timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX ledger_player ON ledger (player_id, id);
+
+-- balances from before the ledger, infinite (-1) amounts are not deltas and never recorded
+INSERT INTO ledger (player_id, delta, reason)
+SELECT id, coins, 'opening' FROM players WHERE coins <> 0;
+
+INSERT INTO ledger (player_id, item_type, item_id, delta, reason)
+SELECT player_id, item_type, item_id, amount, 'opening' FROM storage WHERE amount > 0;
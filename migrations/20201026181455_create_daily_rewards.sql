@@ -0,0 +1,27 @@
+ALTER TABLE players
+    ADD COLUMN daily_streak Int NOT NULL DEFAULT 0 -- Consecutive days the daily reward has been claimed, as of the last claim
+  , ADD COLUMN last_daily Date -- Day (UTC) of the last daily claim
+;
+
+-- Rewards of the daily command, the one of the closest streak day below the player's streak is given
+CREATE TABLE daily_rewards (
+    guild_id Bigint NOT NULL
+  , streak Smallint NOT NULL -- Streak day the reward is given from
+  , species Smallint NOT NULL -- Species of the given seedlings, 0 for coins
+  , amount Int NOT NULL
+  , PRIMARY KEY (guild_id, streak)
+);
+
+-- default rewards (see DEFAULT_DAILY_REWARDS), seedlings only where their species exists
+INSERT INTO daily_rewards (guild_id, streak, species, amount)
+SELECT servers.id, defaults.streak, defaults.species, defaults.amount
+FROM servers CROSS JOIN (VALUES
+    (1, 0, 10)
+  , (2, 0, 15)
+  , (3, 0, 20)
+  , (4, 0, 25)
+  , (5, 2, 5)
+  , (6, 0, 30)
+  , (7, 0, 50)
+) AS defaults (streak, species, amount)
+WHERE defaults.species = 0 OR EXISTS (SELECT 1 FROM species WHERE species.guild_id = servers.id AND species.id = defaults.species);
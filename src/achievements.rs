@@ -0,0 +1,274 @@
+//! Achievements, defined as data and checked against the trees and storage of a player.
+//! They are checked after each plant and purchase, unlocked once and rewarded with coins or seedlings.
+
+use std::collections::{HashMap, HashSet};
+
+use serenity::{
+    client::Context,
+    model::channel::Message,
+};
+
+use crate::error::Result;
+use crate::game::{self, Effects, StorageDelta};
+use crate::prelude::*;
+
+/// What has to be done to unlock an achievement
+/// Species are referred to by the emoji of a default species, their ids differ between servers
+pub enum Goal {
+    /// Plant trees of any species
+    Trees(i64),
+    /// Plant trees of a species
+    SpeciesTrees(&'static str, i64),
+    /// Plant trees in different channels
+    Channels(i64),
+    /// Own a pallet of a species
+    Pallet(&'static str),
+    /// Own coins at once
+    Coins(i32),
+}
+
+pub enum Reward {
+    Coins(i32),
+    /// Seedlings of a species, capped to the free space of the shed
+    Seedlings(&'static str, i32),
+}
+
+pub struct Achievement {
+    /// Unique identifier, stored in `achievements.achievement`
+    /// Its name is translated with the `achievements.name.<id>` key
+    pub id: &'static str,
+    pub goal: Goal,
+    pub reward: Reward,
+}
+
+pub const ACHIEVEMENTS: [Achievement; 10] = [
+    Achievement { id: "first_tree", goal: Goal::Trees(1), reward: Reward::Coins(5) },
+    Achievement { id: "grove", goal: Goal::Trees(100), reward: Reward::Coins(50) },
+    Achievement { id: "woodland", goal: Goal::Trees(1000), reward: Reward::Coins(300) },
+    Achievement { id: "pine_forest", goal: Goal::SpeciesTrees("🌲", 500), reward: Reward::Seedlings("🌳", 20) },
+    Achievement { id: "palm_beach", goal: Goal::SpeciesTrees("🌴", 100), reward: Reward::Seedlings("🌴", 20) },
+    Achievement { id: "desert", goal: Goal::SpeciesTrees("🌵", 100), reward: Reward::Seedlings("🌵", 20) },
+    Achievement { id: "wanderer", goal: Goal::Channels(3), reward: Reward::Coins(20) },
+    Achievement { id: "explorer", goal: Goal::Channels(10), reward: Reward::Coins(100) },
+    Achievement { id: "bamboo_owner", goal: Goal::Pallet("🎍"), reward: Reward::Coins(25) },
+    Achievement { id: "saver", goal: Goal::Coins(1000), reward: Reward::Seedlings("🎍", 10) },
+];
+
+/// Trees planted by a player, summed up for goals
+#[derive(Debug, Default)]
+pub struct PlantedTrees {
+    pub total: i64,
+    /// Mapped by species id
+    pub species: HashMap<i16, i64>,
+    pub channels: i64,
+}
+
+impl PlantedTrees {
+    /// Sum up trees as given by `Store::player_trees`
+    pub fn new(trees: &[(u64, i16, i64)]) -> PlantedTrees {
+        let mut planted = PlantedTrees::default();
+        let mut channels = HashSet::new();
+        for (channel_id, species_id, count) in trees.iter() {
+            planted.total += count;
+            *planted.species.entry(*species_id).or_insert(0) += count;
+            if *count > 0 {
+                channels.insert(*channel_id);
+            }
+        }
+        planted.channels = channels.len() as i64;
+        planted
+    }
+}
+
+fn species_id(server: &Server, emoji: &str) -> Option<i16> {
+    server.species_from_emojis.get(&EmojiKey::from(emoji)).copied()
+}
+
+impl Achievement {
+    /// Current and target values of the goal
+    /// None if the achievement is about a species the server doesn't have
+    pub fn progress(&self, server: &Server, player: &Player, trees: &PlantedTrees) -> Option<(i64, i64)> {
+        match self.goal {
+            Goal::Trees(target) => Some((trees.total, target)),
+            Goal::SpeciesTrees(emoji, target) => species_id(server, emoji)
+                .map(|species_id| (*trees.species.get(&species_id).unwrap_or(&0), target)),
+            Goal::Channels(target) => Some((trees.channels, target)),
+            Goal::Pallet(emoji) => species_id(server, emoji)
+                .map(|species_id| match player.storage.get(&(ItemType::Pallet, species_id)) {
+                    Some(amount) if *amount == -1 || *amount > 0 => (1, 1),
+                    _ => (0, 1),
+                }),
+            Goal::Coins(target) => Some((player.coins as i64, target as i64)),
+        }
+    }
+
+    /// Effects of the reward, nothing is given for a species the server doesn't have
+    pub fn reward(&self, server: &Server, player: &Player) -> Effects {
+        let mut effects = Effects::new(Reason::Reward);
+        match self.reward {
+            Reward::Coins(coins) => effects.coins = coins,
+            Reward::Seedlings(emoji, qty) => {
+                if let Some(species_id) = species_id(server, emoji) {
+                    let qty = qty.min(player.free_space(ItemType::Seedling));
+                    if qty > 0 {
+                        effects.storage.push(StorageDelta::Add { item_type: ItemType::Seedling, item_id: species_id, qty });
+                    }
+                }
+            }
+        }
+        effects
+    }
+
+    pub fn name(&self, lang: &str) -> String {
+        tr!(lang => &format!("achievements.name.{}", self.id))
+    }
+
+    pub fn describe_goal(&self, lang: &str) -> String {
+        match self.goal {
+            Goal::Trees(count) => tr!(lang => "achievements.goal.trees", count = count),
+            Goal::SpeciesTrees(emoji, count) => tr!(lang => "achievements.goal.species_trees", count = count, emoji = emoji),
+            Goal::Channels(count) => tr!(lang => "achievements.goal.channels", count = count),
+            Goal::Pallet(emoji) => tr!(lang => "achievements.goal.pallet", emoji = emoji),
+            Goal::Coins(count) => tr!(lang => "achievements.goal.coins", count = count),
+        }
+    }
+
+    /// Describe the reward, as given by `effects` if it has been given
+    pub fn describe_reward(&self, lang: &str, effects: Option<&Effects>) -> String {
+        match self.reward {
+            Reward::Coins(coins) => tr!(lang => "achievements.reward.coins", count = coins),
+            Reward::Seedlings(emoji, qty) => {
+                let qty = match effects {
+                    Some(effects) => effects.storage.iter()
+                        .map(|delta| match delta {
+                            StorageDelta::Add { qty, .. } => *qty,
+                            StorageDelta::Infinite { .. } | StorageDelta::Take { .. } => 0,
+                        })
+                        .sum(),
+                    None => qty,
+                };
+                tr!(lang => "achievements.reward.seedlings", count = qty, emoji = emoji)
+            }
+        }
+    }
+}
+
+/// Achievements the player reached but hasn't unlocked yet
+pub fn reached(server: &Server, player: &Player, trees: &PlantedTrees) -> Vec<&'static Achievement> {
+    ACHIEVEMENTS.iter()
+        .filter(|achievement| !player.achievements.contains(achievement.id))
+        .filter(|achievement| match achievement.progress(server, player, trees) {
+            Some((current, target)) => current >= target,
+            None => false,
+        })
+        .collect()
+}
+
+/// Unlock the achievements reached by the message author and announce them in the channel
+/// The player must already be cached
+pub async fn run(ctx: &Context, message: &Message) -> Result<()> {
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    if ACHIEVEMENTS.iter().all(|achievement| player.achievements.contains(achievement.id)) {
+        return Ok(());
+    }
+    let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+    std::mem::drop(data);
+
+    let trees = PlantedTrees::new(&store.player_trees(guild_id, user_id).await?);
+    quick_init!(ctx -> data; message => s:server [player]);
+    let reached = reached(server, player, &trees);
+    let lang = server.lang.clone();
+    std::mem::drop(data);
+
+    for achievement in reached {
+        // rewards are computed one at a time so that seedlings don't overflow the shed
+        quick_init!(ctx -> data; message => s:server [player]);
+        let reward = achievement.reward(server, player);
+        std::mem::drop(data);
+        if !store.unlock_achievement(guild_id, user_id, player_id, achievement.id, &reward).await? {
+            continue;
+        }
+
+        quick_init!(ctx -> mut data; message => s:server [player]);
+        game::apply(player, &reward);
+        player.achievements.insert(achievement.id.to_owned());
+        std::mem::drop(data);
+
+        message.channel_id.send_message(&ctx.http, |m| {
+            m.embed(|e| e
+                .title(tr!(lang => "achievements.unlocked"))
+                .color(0xFFC107)
+                .description(tr!(
+                    lang => "achievements.announcement",
+                    user_id = user_id,
+                    name = achievement.name(&lang),
+                    goal = achievement.describe_goal(&lang),
+                    reward = achievement.describe_reward(&lang, Some(&reward)),
+                ))
+            )
+        }).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{player, server, CHANNEL_ID};
+
+    fn achievement(id: &str) -> &'static Achievement {
+        ACHIEVEMENTS.iter().find(|achievement| achievement.id == id).unwrap()
+    }
+
+    #[test]
+    fn trees_are_summed_up_per_species_and_channel() {
+        let trees = PlantedTrees::new(&[(CHANNEL_ID, 1, 10), (CHANNEL_ID, 4, 5), (CHANNEL_ID + 1, 4, 2)]);
+        assert_eq!(trees.total, 17);
+        assert_eq!(trees.species[&4], 7);
+        assert_eq!(trees.channels, 2);
+    }
+
+    #[test]
+    fn reached_achievements_are_unlocked_once() {
+        let server = server();
+        let mut player = player(0, &[]);
+        let trees = PlantedTrees::new(&[(CHANNEL_ID, 4, 100)]);
+
+        let ids: Vec<&str> = reached(&server, &player, &trees).iter().map(|achievement| achievement.id).collect();
+        assert_eq!(ids, vec!["first_tree", "grove", "desert"]);
+
+        player.achievements.insert("grove".to_owned());
+        let ids: Vec<&str> = reached(&server, &player, &trees).iter().map(|achievement| achievement.id).collect();
+        assert_eq!(ids, vec!["first_tree", "desert"]);
+    }
+
+    #[test]
+    fn owning_a_pallet_is_a_goal() {
+        let server = server();
+        let trees = PlantedTrees::default();
+        let bamboo_owner = achievement("bamboo_owner");
+
+        assert_eq!(bamboo_owner.progress(&server, &player(0, &[]), &trees), Some((0, 1)));
+        assert_eq!(bamboo_owner.progress(&server, &player(0, &[((ItemType::Pallet, 5), 1)]), &trees), Some((1, 1)));
+    }
+
+    #[test]
+    fn missing_species_make_achievements_unavailable() {
+        let mut server = server();
+        server.species.remove(&4);
+        server.index_species();
+
+        assert_eq!(achievement("desert").progress(&server, &player(0, &[]), &PlantedTrees::default()), None);
+        assert!(achievement("desert").reward(&server, &player(0, &[])).is_noop());
+    }
+
+    #[test]
+    fn seedling_rewards_fit_in_the_shed() {
+        let server = server();
+        let free_space = player(0, &[]).free_space(ItemType::Seedling);
+        let player = player(0, &[((ItemType::Seedling, 2), free_space - 5)]);
+
+        let reward = achievement("palm_beach").reward(&server, &player);
+        assert_eq!(reward.storage, vec![StorageDelta::Add { item_type: ItemType::Seedling, item_id: 3, qty: 5 }]);
+    }
+}
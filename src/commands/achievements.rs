@@ -0,0 +1,55 @@
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        macros::command,
+        CommandResult,
+    },
+    builder::CreateEmbed,
+};
+
+use crate::prelude::*;
+use crate::achievements::{PlantedTrees, ACHIEVEMENTS};
+
+#[command("achievements")]
+#[aliases("achievement", "badges")]
+async fn cmd_achievements(ctx: &Context, message: &Message) -> CommandResult {
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let (guild_id, user_id) = (player.guild_id, player.user_id);
+    std::mem::drop(data);
+    let trees = PlantedTrees::new(&store.player_trees(guild_id, user_id).await?);
+
+    quick_init!(ctx; message => s:server [player]);
+    let mut lines = Vec::new();
+    let mut unlocked = 0;
+    for achievement in ACHIEVEMENTS.iter() {
+        // achievements about a species the server doesn't have are not listed
+        let (current, target) = match achievement.progress(server, player, &trees) {
+            Some(progress) => progress,
+            None => continue,
+        };
+        let done = player.achievements.contains(achievement.id);
+        if done {
+            unlocked += 1;
+        }
+        lines.push(tr!(
+            server.lang => if done {"achievements.entry.unlocked"} else {"achievements.entry.locked"},
+            name = achievement.name(&server.lang),
+            goal = achievement.describe_goal(&server.lang),
+            reward = achievement.describe_reward(&server.lang, None),
+            current = current.min(target), target = target,
+        ));
+    }
+
+    message.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e: &mut CreateEmbed| {
+            e.title(tr!(server.lang => "achievements.title"));
+            e.color(DEFAULT_COLOR);
+            e.description(lines.join("\n"));
+            e.footer(|f| f.text(tr!(server.lang => "achievements.count", unlocked = unlocked, total = lines.len())));
+            e
+        });
+        m
+    }).await?;
+    Ok(())
+}
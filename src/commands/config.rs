@@ -8,87 +8,334 @@ use serenity::{
     builder::CreateEmbed,
 };
 use crate::prelude::*;
-
-use regex::Regex;
+use super::parse_channel_mention;
 
 macro_rules! rules_summary {
-    ($rules:expr, $what:expr, $allowed:expr, $is_or_are:expr) => ({
+    ($lang:expr, $rules:expr, $kind:literal) => ({
         let exceptions = $rules.channels.iter()
             .filter(|(_, v)| **v != $rules.global)
             .map(|(k, _)| format!("<#{}>", k))
             .collect::<Vec<String>>();
-        format!(
-            "{what} {access}",
-            what = $what, access = if $rules.global {
-                if !exceptions.is_empty() {
-                    format!("{} in all channels except {}", $allowed, exceptions.join(", "))
-                }
-                else {
-                    format!("{} in all channels", $allowed)
-                }
-            } else {
-                if !exceptions.is_empty() {
-                    format!("{} restricted to the following channels: {}", $is_or_are, exceptions.join(", "))
-                }
-                else {
-                    format!("{} forbidden in every channel", $is_or_are)
-                }
-            }
-        )
+        let key = match ($rules.global, exceptions.is_empty()) {
+            (true, true) => concat!("settings.overview.", $kind, ".everywhere"),
+            (true, false) => concat!("settings.overview.", $kind, ".except"),
+            (false, false) => concat!("settings.overview.", $kind, ".restricted"),
+            (false, true) => concat!("settings.overview.", $kind, ".forbidden"),
+        };
+        tr!($lang => key, channels = exceptions.join(", "))
     });
 }
 
 #[command("settings")]
 #[aliases("config")]
 async fn cmd_settings(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
-    let permissions = message.member(&ctx.cache).await.unwrap().permissions(&ctx.cache).await?;
+    let permissions = message.member(&ctx.cache).await?.permissions(&ctx.cache).await?;
+    quick_init!(ctx -> mut data; message => s:server);
     if !permissions.manage_guild() && message.author.id.0 != 345259637513256960 {
-        error!(ctx, message.channel_id => "**Insufficient permissions!**\n
-        > You must have the permission `MANAGE_GUILD` or be granted the \"bot master\" permission to use this command.",);
+        error!(ctx, message.channel_id, server.lang => "settings.insufficient_permissions");
         return Ok(());
     }
 
-    quick_init!(ctx -> mut data; message => s:server);
     match args.current() {
         Some("prefix") => {
             if let Some(prefix) = args.current() {
                 server.prefix = prefix.to_string();
-                success!(ctx, message.channel_id => "Prefix changed to `{}`", server.prefix);
+                success!(ctx, message.channel_id, server.lang => "settings.prefix.changed", prefix = server.prefix);
 
-                quick_init!(ctx ~data~ => p:pool; message => s:server);
-                sqlx::query!("UPDATE servers SET prefix = $1 WHERE id = $2", server.prefix, server.id)
-                    .execute(pool).await?;
+                quick_init!(ctx ~data~ => st:store; message => s:server);
+                store.save_settings(server).await?;
             }
             else {
-                info!(ctx, message.channel_id => (":gear:") "Current prefix: `{}`", server.prefix);
+                info!(ctx, message.channel_id, server.lang => (":gear:") "settings.prefix.current", prefix = server.prefix);
             }
         }
         Some("lang" | "language") => {
-            info!(ctx, message.channel_id => "The language cannot be changed yet. This feature will be available soon!",);
+            args.advance();
+            let available = crate::i18n::LOCALES.iter()
+                .map(|(code, name, flag)| format!("`{}` {} {}", code, name, flag))
+                .collect::<Vec<String>>()
+                .join(", ");
+            match args.current().map(|code| code.to_lowercase()) {
+                Some(code) if crate::i18n::is_supported(&code) => {
+                    server.lang = code;
+                    let (name, flag) = crate::i18n::describe(&server.lang);
+                    success!(ctx, message.channel_id, server.lang => "settings.lang.changed", name = name, flag = flag);
+
+                    quick_init!(ctx ~data~ => st:store; message => s:server);
+                    store.save_settings(server).await?;
+                }
+                Some(code) => {
+                    error!(ctx, message.channel_id, server.lang => "settings.lang.invalid", code = code, available = available);
+                }
+                None => {
+                    let (name, flag) = crate::i18n::describe(&server.lang);
+                    info!(ctx, message.channel_id, server.lang => (":globe_with_meridians:") "settings.lang.current",
+                        name = name, flag = flag, available = available);
+                }
+            }
         }
         Some("cooldown") => {
             args.advance();
+            // optional scope: a channel and/or a species
+            let mut channel_id = 0;
+            if let Some(id) = args.current().and_then(parse_channel_mention) {
+                if let Ok(channels) = message.guild_id.unwrap().channels(&ctx.http).await {
+                    if channels.contains_key(&ChannelId::from(id)) {
+                        channel_id = id;
+                        args.advance();
+                    }
+                }
+            }
+            let mut species_id = 0;
+            if let Some(species) = args.current()
+                .filter(|query| query.parse::<i16>().is_err())
+                .and_then(|query| server.find_species(query))
+            {
+                species_id = species.id;
+                args.advance();
+            }
+            let scope = cooldown_scope(server, channel_id, species_id);
+            let lang = server.lang.clone();
+
             if args.current() == None {
-                info!(ctx, message.channel_id => (":hourglass:") 
-                    "The cooldown between each tree is set to **{} seconds** per member.", server.plant_cooldown);
+                if channel_id == 0 && species_id == 0 {
+                    info!(ctx, message.channel_id, lang => (":hourglass:") "settings.cooldown.current", count = server.plant_cooldown);
+                } else {
+                    info!(ctx, message.channel_id, lang => (":hourglass:") "settings.cooldown.scoped_current",
+                        scope = scope, count = server.cooldown_for(channel_id, species_id).seconds);
+                }
                 return Ok(());
             }
 
+            if channel_id != 0 || species_id != 0 {
+                if let Some("reset" | "inherit") = args.current() {
+                    server.cooldown_overrides.remove(&(channel_id, species_id));
+                    success!(ctx, message.channel_id, lang => "settings.cooldown.reset", scope = scope);
+
+                    quick_init!(ctx ~data~ => st:store);
+                    store.save_cooldown_override(message.guild_id.unwrap().0, channel_id, species_id, None).await?;
+                    return Ok(());
+                }
+            }
+
             if let Ok(cooldown) = args.single::<i16>() {
                 if cooldown >= 0 && cooldown <= 28800 {
-                    server.plant_cooldown = cooldown;
-                    success!(ctx, message.channel_id => "Trees cooldown has been set to **{} seconds**.", server.plant_cooldown);
+                    if channel_id == 0 && species_id == 0 {
+                        server.plant_cooldown = cooldown;
+                        success!(ctx, message.channel_id, lang => "settings.cooldown.set", count = server.plant_cooldown);
+
+                        quick_init!(ctx ~data~ => st:store; message => s:server);
+                        store.save_settings(server).await?;
+                    } else {
+                        server.cooldown_overrides.insert((channel_id, species_id), cooldown);
+                        success!(ctx, message.channel_id, lang => "settings.cooldown.scoped_set", scope = scope, count = cooldown);
+
+                        quick_init!(ctx ~data~ => st:store);
+                        store.save_cooldown_override(message.guild_id.unwrap().0, channel_id, species_id, Some(cooldown)).await?;
+                    }
+                    return Ok(());
+                }
+            }
+            error!(ctx, message.channel_id, lang => "settings.cooldown.invalid");
+        }
+        Some("workers" | "max_workers") => {
+            args.advance();
+            if args.current() == None {
+                info!(ctx, message.channel_id, server.lang => (":construction_worker:") "settings.workers.current", count = server.max_workers);
+                return Ok(());
+            }
+
+            if let Ok(max_workers) = args.single::<i16>() {
+                if max_workers >= 0 && max_workers <= 25 {
+                    server.max_workers = max_workers;
+                    success!(ctx, message.channel_id, server.lang => "settings.workers.changed", count = server.max_workers);
+
+                    quick_init!(ctx ~data~ => st:store; message => s:server);
+                    store.save_settings(server).await?;
+                    return Ok(());
+                }
+            }
+            error!(ctx, message.channel_id, server.lang => "settings.workers.invalid");
+        }
+        Some("global" | "global_forest") => {
+            args.advance();
+            let global_forest = match args.current().map(|value| value.to_lowercase()).as_deref() {
+                Some("on" | "true" | "enable" | "yes") => true,
+                Some("off" | "false" | "disable" | "no") => false,
+                Some(_) => {
+                    error!(ctx, message.channel_id, server.lang => "settings.global.invalid");
+                    return Ok(());
+                }
+                None => {
+                    let key = if server.global_forest {"settings.global.current_enabled"} else {"settings.global.current_disabled"};
+                    info!(ctx, message.channel_id, server.lang => (":earth_africa:") key);
+                    return Ok(());
+                }
+            };
+
+            server.global_forest = global_forest;
+            let key = if global_forest {"settings.global.enabled"} else {"settings.global.disabled"};
+            success!(ctx, message.channel_id, server.lang => key);
+
+            quick_init!(ctx ~data~ => st:store; message => s:server);
+            store.save_settings(server).await?;
+        }
+        Some("milestones" | "milestone") => {
+            args.advance();
+            let lang = server.lang.clone();
+            match args.current().map(|value| value.to_lowercase()).as_deref() {
+                None => {
+                    info!(ctx, message.channel_id, lang => (":tada:") "settings.milestones.current",
+                        thresholds = milestones_summary(server), channel = milestones_channel(server));
+                    return Ok(());
+                }
+                Some("channel") => {
+                    args.advance();
+                    let mut channel_id = None;
+                    match args.current() {
+                        Some("here" | "reset") => {}
+                        Some(thing) => {
+                            if let Some(id) = parse_channel_mention(thing) {
+                                if let Ok(channels) = message.guild_id.unwrap().channels(&ctx.http).await {
+                                    if channels.contains_key(&ChannelId::from(id)) {
+                                        channel_id = Some(id);
+                                    }
+                                }
+                            }
+                            if channel_id.is_none() {
+                                error!(ctx, message.channel_id, lang => "settings.milestones.invalid_channel");
+                                return Ok(());
+                            }
+                        }
+                        None => {
+                            error!(ctx, message.channel_id, lang => "settings.milestones.invalid_channel");
+                            return Ok(());
+                        }
+                    }
+                    server.milestones_channel = channel_id;
+                    success!(ctx, message.channel_id, lang => "settings.milestones.channel_changed", channel = milestones_channel(server));
+                }
+                Some("off" | "none" | "disable") => {
+                    server.milestones.clear();
+                    success!(ctx, message.channel_id, lang => "settings.milestones.disabled");
+                }
+                Some(_) => {
+                    let mut thresholds = Vec::new();
+                    for threshold in args.iter::<i64>() {
+                        match threshold {
+                            Ok(threshold) if threshold > 0 && threshold <= MAX_MILESTONE => thresholds.push(threshold),
+                            _ => {
+                                error!(ctx, message.channel_id, lang => "settings.milestones.invalid", max = MAX_MILESTONE);
+                                return Ok(());
+                            }
+                        }
+                    }
+                    thresholds.sort_unstable();
+                    thresholds.dedup();
+                    if thresholds.len() > MAX_MILESTONES {
+                        error!(ctx, message.channel_id, lang => "settings.milestones.too_many", count = MAX_MILESTONES);
+                        return Ok(());
+                    }
+                    server.milestones = thresholds;
+                    success!(ctx, message.channel_id, lang => "settings.milestones.changed", thresholds = milestones_summary(server));
+                }
+            }
 
-                    quick_init!(ctx ~data~ => p:pool; message => s:server);
-                    sqlx::query!("UPDATE servers SET plant_cooldown = $1 WHERE id = $2", server.plant_cooldown, server.id)
-                        .execute(pool).await?;
+            quick_init!(ctx ~data~ => st:store; message => s:server);
+            store.save_settings(server).await?;
+        }
+        Some("gifts" | "gift") => {
+            args.advance();
+            let lang = server.lang.clone();
+            let is_coins = match args.current().map(|kind| kind.to_lowercase()).as_deref() {
+                Some("coins" | "coin") => true,
+                Some("items" | "item") => false,
+                Some(_) => {
+                    error!(ctx, message.channel_id, lang => "settings.gifts.invalid_kind");
                     return Ok(());
                 }
+                None => {
+                    info!(ctx, message.channel_id, lang => (":gift:") "settings.gifts.current",
+                        coins = server.daily_coin_gifts, items = server.daily_item_gifts);
+                    return Ok(());
+                }
+            };
+            args.advance();
+
+            match args.single::<i32>() {
+                Ok(cap) if cap >= 0 && cap <= MAX_DAILY_GIFTS => {
+                    if is_coins {
+                        server.daily_coin_gifts = cap;
+                    } else {
+                        server.daily_item_gifts = cap;
+                    }
+                    let key = match (is_coins, cap) {
+                        (true, 0) => "settings.gifts.coins_disabled",
+                        (false, 0) => "settings.gifts.items_disabled",
+                        (true, _) => "settings.gifts.coins_changed",
+                        (false, _) => "settings.gifts.items_changed",
+                    };
+                    success!(ctx, message.channel_id, lang => key, count = cap);
+
+                    quick_init!(ctx ~data~ => st:store; message => s:server);
+                    store.save_settings(server).await?;
+                }
+                _ => error!(ctx, message.channel_id, lang => "settings.gifts.invalid", max = MAX_DAILY_GIFTS),
             }
-            error!(ctx, message.channel_id => "Please specify a valid time in seconds between 0 and 28800!",);
+        }
+        Some("daily" | "streak") => {
+            args.advance();
+            let lang = server.lang.clone();
+            let streak = match args.current() {
+                Some(streak) => match streak.parse::<i16>() {
+                    Ok(streak) if streak >= 1 && streak <= MAX_STREAK_DAY => streak,
+                    _ => {
+                        error!(ctx, message.channel_id, lang => "settings.daily.invalid_day", max = MAX_STREAK_DAY);
+                        return Ok(());
+                    }
+                },
+                None => {
+                    info!(ctx, message.channel_id, lang => (":calendar:") "settings.daily.current", rewards = daily_rewards_summary(server));
+                    return Ok(());
+                }
+            };
+            args.advance();
+
+            let reward = match args.current().map(|value| value.to_lowercase()).as_deref() {
+                Some("off" | "none" | "reset") => None,
+                _ => {
+                    let amount = args.single::<i32>().ok().filter(|amount| *amount > 0 && *amount <= MAX_DAILY_REWARD);
+                    let species = match args.current() {
+                        None => Some(None),
+                        Some(thing) if thing.eq_ignore_ascii_case("coins") || thing.eq_ignore_ascii_case("coin") => Some(None),
+                        Some(query) => server.find_species(query).map(|species| Some(species.id)),
+                    };
+                    match (amount, species) {
+                        (Some(amount), Some(species)) => Some(DailyReward { species, amount }),
+                        _ => {
+                            error!(ctx, message.channel_id, lang => "settings.daily.invalid", max = MAX_DAILY_REWARD);
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+            match reward {
+                Some(reward) => {
+                    server.daily_rewards.insert(streak, reward);
+                    success!(ctx, message.channel_id, lang => "settings.daily.changed", day = streak, reward = reward.describe(server));
+                }
+                None => {
+                    server.daily_rewards.remove(&streak);
+                    success!(ctx, message.channel_id, lang => "settings.daily.removed", day = streak);
+                }
+            }
+
+            quick_init!(ctx ~data~ => st:store);
+            store.save_daily_reward(message.guild_id.unwrap().0, streak, reward).await?;
         }
         Some("rules" | "rule") => {
             args.advance();
+            let lang = server.lang.clone();
             if let Some(kind) = match args.current() {
                 Some("forest" | "grow") => Some(1),
                 Some("commands" | "command") => Some(2),
@@ -106,8 +353,7 @@ async fn cmd_settings(ctx: &Context, message: &Message, mut args: Args) -> Comma
                         Some("server" | "guild" | "0") => Some(0 as u64),
                         Some(thing) => {
                             let mut result = None;
-                            let re = Regex::new("[<#>]").unwrap();
-                            if let Ok(channel_id) = re.replace_all(&thing, "").into_owned().parse::<u64>() {
+                            if let Some(channel_id) = parse_channel_mention(thing) {
                                 if let Ok(channels) = message.guild_id.unwrap().channels(&ctx.http).await {
                                     if channels.contains_key(&ChannelId::from(channel_id)) {
                                         result = Some(channel_id);
@@ -132,11 +378,8 @@ async fn cmd_settings(ctx: &Context, message: &Message, mut args: Args) -> Comma
                                 rules.channels.remove(&scope);
                             }
                             
-                            quick_init!(ctx ~rules~~data~ => p:pool; message => s:server);
-                            sqlx::query!(
-                                "DELETE FROM rules WHERE guild_id = $1 AND kind = $2 AND scope = $3",
-                                server.id, kind, scope as i64
-                            ).execute(pool).await?;
+                            quick_init!(ctx ~rules~~data~ => st:store);
+                            store.save_rule(message.guild_id.unwrap().0, kind, scope, None).await?;
                         }
                         else {
                             let allowed = allowance == 1;
@@ -147,69 +390,80 @@ async fn cmd_settings(ctx: &Context, message: &Message, mut args: Args) -> Comma
                                 rules.channels.insert(scope.clone(), allowed);
                             }
 
-                            quick_init!(ctx ~rules~~data~ => p:pool; message => s:server);
-                            sqlx::query!(
-                               "INSERT INTO rules (guild_id, kind, scope, allowance) VALUES ($1, $2, $3, $4)
-                                ON CONFLICT (guild_id, kind, scope) DO UPDATE SET allowance = $4",
-                                server.id, kind, scope as i64, allowed
-                            ).execute(pool).await?;
+                            quick_init!(ctx ~rules~~data~ => st:store);
+                            store.save_rule(message.guild_id.unwrap().0, kind, scope, Some(allowed)).await?;
                         }
 
                         success!(
-                            ctx, message.channel_id => "Rules update: __{}__ has been set to **{}** {}.", 
-                            match kind {
-                                1 => "forest growth",
-                                2 => "commands",
+                            ctx, message.channel_id, lang => "settings.rules.updated",
+                            kind = tr!(lang => match kind {
+                                1 => "settings.rules.kind.forest",
+                                2 => "settings.rules.kind.commands",
                                 _ => panic!("Unsupported kind")
-                            }, 
-                            match allowance {
-                                1 => "allowed",
-                                0 => "denied",
-                                2 => "inherited",
+                            }),
+                            allowance = tr!(lang => match allowance {
+                                1 => "settings.rules.allowance.allowed",
+                                0 => "settings.rules.allowance.denied",
+                                2 => "settings.rules.allowance.inherited",
                                 _ => panic!("Unsupported allowance")
+                            }),
+                            scope = match scope {
+                                0 => tr!(lang => "settings.rules.scope.global"),
+                                channel => tr!(lang => "settings.rules.scope.channel", channel = channel)
                             },
-                            match scope {
-                                0 => "globally".to_owned(),
-                                channel => format!("in channel <#{}>", channel)
-                            }
                         );
                     } else {
-                        error!(ctx, message.channel_id => "Missing or invalid scope! You can use either `server` or mention a channel.",);
+                        error!(ctx, message.channel_id, lang => "settings.rules.invalid_scope");
                     }
                 }
                 else {
-                    error!(ctx, message.channel_id => "Missing or invalid allowance! Valid options are: `allow`, `deny`, `inherit`",);
+                    error!(ctx, message.channel_id, lang => "settings.rules.invalid_allowance");
                 }
             } else {
-                error!(ctx, message.channel_id => "Missing or invalid rule kind! You can use either `forest` or `commands`.",);
+                error!(ctx, message.channel_id, lang => "settings.rules.invalid_kind");
             }
         }
+        Some("species" | "trees") => {
+            args.advance();
+            std::mem::drop(data);
+            return species_settings(ctx, message, args).await;
+        }
         Some(_) => {
-            error!(ctx, message.channel_id =>
-               "Invalid settings argument!
-                Arguments: `prefix`, `lang`, `cooldown`, `rules`
-                > Using this command without argument will give you an overview of the settings",
-            );
+            error!(ctx, message.channel_id, server.lang => "settings.invalid_argument");
         }
         None => {
-            let forest_rules_summary = rules_summary!(server.forest_rules, "The forest", "can grow", "is");
-            let commands_rules_summary = rules_summary!(server.commands_rules, "Commands", "are allowed", "are");
-            
+            let forest_rules_summary = rules_summary!(server.lang, server.forest_rules, "forest_rules");
+            let commands_rules_summary = rules_summary!(server.lang, server.commands_rules, "commands_rules");
+            let (lang_name, lang_flag) = crate::i18n::describe(&server.lang);
+
             let _ = message.channel_id.send_message(&ctx.http, |m| {
                 m.embed(|e: &mut CreateEmbed| {
-                    e.title("Server Configuration");
+                    e.title(tr!(server.lang => "settings.overview.title"));
                     e.color(DEFAULT_COLOR);
 
-                    e.field("General Settings", eformat!(
-                       "Prefix: `{}`
-                        Language: English :flag_gb:
-                        Cooldown: {} seconds (/:forest/)",
-                        server.prefix, server.plant_cooldown
+                    e.field(tr!(server.lang => "settings.overview.general"), tr!(
+                        server.lang => "settings.overview.general_value",
+                        prefix = server.prefix, lang_name = lang_name, lang_flag = lang_flag,
+                        cooldown = server.plant_cooldown, workers = server.max_workers,
+                        global = tr!(server.lang => if server.global_forest {"settings.overview.global_enabled"} else {"settings.overview.global_disabled"}),
+                        milestones = milestones_summary(server), milestones_channel = milestones_channel(server),
+                        coin_gifts = server.daily_coin_gifts, item_gifts = server.daily_item_gifts,
                     ), false);
-                    e.field("Access Rules", eformat!(
-                       "(/:forest/) {}
-                        (:space_invader:) {}",
-                        forest_rules_summary, commands_rules_summary
+                    if !server.cooldown_overrides.is_empty() {
+                        let mut overrides = server.cooldown_overrides.iter().collect::<Vec<(&(u64, i16), &i16)>>();
+                        overrides.sort();
+                        e.field(tr!(server.lang => "settings.overview.cooldown_overrides"), overrides.iter()
+                            .map(|((channel_id, species_id), cooldown)| tr!(
+                                server.lang => "settings.overview.cooldown_override",
+                                scope = cooldown_scope(server, *channel_id, *species_id), count = **cooldown,
+                            ))
+                            .collect::<Vec<String>>()
+                            .join("\n"), false);
+                    }
+                    e.field(tr!(server.lang => "settings.overview.daily_rewards"), daily_rewards_summary(server), false);
+                    e.field(tr!(server.lang => "settings.overview.access_rules"), tr!(
+                        server.lang => "settings.overview.access_rules_value",
+                        forest = forest_rules_summary, commands = commands_rules_summary,
                     ), false);
                     
                     e
@@ -221,3 +475,258 @@ async fn cmd_settings(ctx: &Context, message: &Message, mut args: Args) -> Comma
 
     Ok(())
 }
+
+/// Highest tree total a milestone can be set to
+const MAX_MILESTONE: i64 = 1_000_000_000;
+/// Milestones a server can set at once
+const MAX_MILESTONES: usize = 10;
+/// Highest daily cap of gifts, for coins and items alike
+const MAX_DAILY_GIFTS: i32 = 1_000_000;
+/// Highest streak day a daily reward can be set for
+const MAX_STREAK_DAY: i16 = 365;
+/// Highest amount of coins or seedlings of a daily reward
+const MAX_DAILY_REWARD: i32 = 100_000;
+
+fn milestones_summary(server: &Server) -> String {
+    if server.milestones.is_empty() {
+        return tr!(server.lang => "settings.milestones.none");
+    }
+    server.milestones.iter()
+        .map(|threshold| format!("**{}**", threshold))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// One line per daily reward, with the streak day it is given from
+fn daily_rewards_summary(server: &Server) -> String {
+    if server.daily_rewards.is_empty() {
+        return tr!(server.lang => "settings.daily.none");
+    }
+    server.daily_rewards.iter()
+        .map(|(streak, reward)| tr!(server.lang => "settings.daily.reward", day = streak, reward = reward.describe(server)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Where milestones are announced
+fn milestones_channel(server: &Server) -> String {
+    match server.milestones_channel {
+        Some(channel_id) => format!("<#{}>", channel_id),
+        None => tr!(server.lang => "settings.milestones.where_reached"),
+    }
+}
+
+/// Describe the scope of a cooldown override, empty for the server-wide cooldown
+fn cooldown_scope(server: &Server, channel_id: u64, species_id: i16) -> String {
+    let mut scope = String::new();
+    if let Some(species) = server.species.get(&species_id) {
+        scope.push_str(&tr!(server.lang => "settings.cooldown.scope.species", emoji = species.emoji, name = species.name));
+    } else if species_id != 0 {
+        scope.push_str(&tr!(server.lang => "settings.cooldown.scope.unknown_species"));
+    }
+    if channel_id != 0 {
+        scope.push_str(&tr!(server.lang => "settings.cooldown.scope.channel", channel = channel_id));
+    }
+    scope
+}
+
+/// Parse an emoji to be used by a species
+/// Custom emojis have to belong to the guild, their name is refreshed from it
+async fn parse_species_emoji(ctx: &Context, guild_id: GuildId, input: &str) -> Option<EmojiKey> {
+    match EmojiKey::parse(input)? {
+        EmojiKey::Custom { id, .. } => {
+            let emoji = guild_id.emoji(&ctx.http, EmojiId(id)).await.ok()?;
+            Some(EmojiKey::Custom {
+                id,
+                name: emoji.name,
+                animated: emoji.animated,
+            })
+        }
+        emoji => Some(emoji)
+    }
+}
+
+async fn species_settings(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    quick_init!(ctx -> data => st:store; message => s:server);
+    let lang = server.lang.clone();
+    match args.current() {
+        Some("add" | "create") => {
+            args.advance();
+            let emoji = args.single::<String>().ok();
+            let values = (args.single::<i32>(), args.single::<i32>(), args.single::<i32>());
+            let name = args.rest().trim().to_owned();
+            if let (Some(input), (Ok(pallet_cost), Ok(default_qty), Ok(coins))) = (emoji, values) {
+                let emoji = match parse_species_emoji(ctx, message.guild_id.unwrap(), &input).await {
+                    Some(emoji) => emoji,
+                    None => {
+                        error!(ctx, message.channel_id, lang => "settings.species.invalid_emoji", emoji = input);
+                        return Ok(());
+                    }
+                };
+                if server.species.values().any(|species| species.emoji == emoji) {
+                    error!(ctx, message.channel_id, lang => "settings.species.emoji_taken", emoji = emoji);
+                    return Ok(());
+                }
+                if name.is_empty() || pallet_cost < 0 || default_qty < -1 || coins < 0 {
+                    error!(ctx, message.channel_id, lang => "settings.species.invalid_values");
+                    return Ok(());
+                }
+
+                let species = Species {
+                    id: store.next_species_id(message.guild_id.unwrap().0).await?,
+                    emoji,
+                    name,
+                    pallet_cost,
+                    pallet_qty: DEFAULT_PALLET_QTY,
+                    default_qty,
+                    coins,
+                    growth_rate: DEFAULT_GROWTH_RATE,
+                    enabled: true,
+                };
+                store.save_species(message.guild_id.unwrap().0, &species).await?;
+
+                quick_init!(ctx -> mut data ~data~; message => s:server);
+                let species_id = species.id;
+                server.species.insert(species_id, species);
+                server.index_species();
+
+                let species = server.species.get(&species_id).unwrap();
+                success!(ctx, message.channel_id, lang => "settings.species.added", emoji = species.emoji, name = species.name);
+            } else {
+                error!(ctx, message.channel_id, lang => "settings.species.add_usage");
+            }
+        }
+        Some("edit" | "set") => {
+            args.advance();
+            let mut species = match args.current().and_then(|query| server.find_species(query)) {
+                Some(species) => species.clone(),
+                None => {
+                    error!(ctx, message.channel_id, lang => "settings.species.unknown");
+                    return Ok(());
+                }
+            };
+            args.advance();
+            let field = args.current().map(|field| field.to_lowercase());
+            args.advance();
+            let value = args.rest().trim().to_owned();
+
+            let new_emoji = if field.as_deref() == Some("emoji") {
+                parse_species_emoji(ctx, message.guild_id.unwrap(), &value).await
+            } else {
+                None
+            };
+            let valid = match (field.as_deref(), value.parse::<i32>()) {
+                (Some("emoji"), _) => match new_emoji {
+                    Some(emoji) if !server.species.values().any(|other| other.id != species.id && other.emoji == emoji) => {
+                        species.emoji = emoji;
+                        true
+                    }
+                    _ => false
+                },
+                (Some("name"), _) => !value.is_empty() && {
+                    species.name = value.clone();
+                    true
+                },
+                (Some("cost" | "pallet_cost"), Ok(cost)) if cost >= 0 => {
+                    species.pallet_cost = cost;
+                    true
+                }
+                (Some("pallet_qty" | "unpack"), Ok(qty)) if qty > 0 => {
+                    species.pallet_qty = qty;
+                    true
+                }
+                (Some("qty" | "default_qty"), Ok(qty)) if qty >= -1 => {
+                    species.default_qty = qty;
+                    true
+                }
+                (Some("coins"), Ok(coins)) if coins >= 0 => {
+                    species.coins = coins;
+                    true
+                }
+                (Some("growth_rate" | "growth"), Ok(growth_rate)) if growth_rate >= 0 => {
+                    species.growth_rate = growth_rate;
+                    true
+                }
+                _ => false
+            };
+            if !valid {
+                error!(ctx, message.channel_id, lang => "settings.species.invalid_field");
+                return Ok(());
+            }
+
+            store.save_species(message.guild_id.unwrap().0, &species).await?;
+
+            success!(ctx, message.channel_id, lang => "settings.species.updated", emoji = species.emoji, name = species.name);
+            quick_init!(ctx -> mut data ~data~; message => s:server);
+            server.species.insert(species.id, species);
+            server.index_species();
+        }
+        Some(action @ ("enable" | "disable")) => {
+            let enabled = action == "enable";
+            args.advance();
+            if let Some(species) = args.current().and_then(|query| server.find_species(query)) {
+                let species_id = species.id;
+                store.save_species(message.guild_id.unwrap().0, &Species { enabled, ..species.clone() }).await?;
+
+                success!(ctx, message.channel_id, lang => if enabled { "settings.species.enabled" } else { "settings.species.disabled" },
+                    emoji = species.emoji, name = species.name);
+                quick_init!(ctx -> mut data ~data~; message => s:server);
+                server.species.get_mut(&species_id).unwrap().enabled = enabled;
+                server.index_species();
+            } else {
+                error!(ctx, message.channel_id, lang => "settings.species.unknown");
+            }
+        }
+        Some("remove" | "delete") => {
+            args.advance();
+            if let Some(species) = args.current().and_then(|query| server.find_species(query)) {
+                let species_id = species.id;
+                store.remove_species(message.guild_id.unwrap().0, species_id).await?;
+
+                success!(ctx, message.channel_id, lang => "settings.species.removed", emoji = species.emoji, name = species.name);
+                quick_init!(ctx -> mut data ~data~; message => s:server);
+                server.species.remove(&species_id);
+                server.index_species();
+                server.cooldown_overrides.retain(|(_, species), _| *species != species_id);
+                server.daily_rewards.retain(|_, reward| reward.species != Some(species_id));
+                for player in server.player_cache.1.values_mut() {
+                    player.storage.remove(&(ItemType::Pallet, species_id));
+                    player.storage.remove(&(ItemType::Seedling, species_id));
+                    player.greenhouse.retain(|_, slot| slot.species != species_id);
+                }
+            } else {
+                error!(ctx, message.channel_id, lang => "settings.species.unknown");
+            }
+        }
+        Some(_) => {
+            error!(ctx, message.channel_id, lang => "settings.species.invalid_argument");
+        }
+        None => {
+            let mut species = server.species.values().collect::<Vec<&Species>>();
+            species.sort_by_key(|species| species.id);
+
+            let _ = message.channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e: &mut CreateEmbed| {
+                    e.title(tr!(lang => "settings.species.list.title"));
+                    e.color(DEFAULT_COLOR);
+
+                    e.description(species.iter()
+                        .map(|species| tr!(
+                            lang => "settings.species.list.entry",
+                            id = species.id, emoji = species.emoji, name = species.name,
+                            disabled = if species.enabled { "".to_owned() } else { tr!(lang => "settings.species.list.disabled") },
+                            cost = species.pallet_cost, pallet_qty = species.pallet_qty, coins = species.coins,
+                            growth_rate = species.growth_rate,
+                            qty = if species.default_qty == -1 { "∞".to_owned() } else { species.default_qty.to_string() },
+                        ))
+                        .collect::<Vec<String>>()
+                        .join("\n"));
+                    e
+                });
+                m
+            }).await?;
+        }
+    };
+
+    Ok(())
+}
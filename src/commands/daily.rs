@@ -0,0 +1,71 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        macros::command,
+        CommandResult,
+    },
+};
+
+use crate::prelude::*;
+use crate::error::Error;
+use crate::game::{self, Action, Refusal};
+use super::trade::refusal_reason;
+
+#[command("daily")]
+#[aliases("streak")]
+async fn cmd_daily(ctx: &Context, message: &Message) -> CommandResult {
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let lang = server.lang.clone();
+    let now = SystemTime::now();
+    let effects = match game::play(server, player, &Action::ClaimDaily { today: day(now) }) {
+        Ok(effects) => effects,
+        Err(Refusal::AlreadyClaimed) => {
+            let streak = player.daily_streak;
+            std::mem::drop(data);
+            info!(ctx, message.channel_id, lang => (":calendar:") "daily.already_claimed",
+                streak = streak, count = hours_until_tomorrow(now));
+            return Ok(());
+        }
+        Err(Refusal::NoDailyReward) => {
+            std::mem::drop(data);
+            error!(ctx, message.channel_id, lang => "daily.disabled");
+            return Ok(());
+        }
+        Err(refusal) => {
+            let reason = refusal_reason(server, &refusal);
+            std::mem::drop(data);
+            error!(ctx, message.channel_id, lang => "daily.refused", reason = reason);
+            return Ok(());
+        }
+    };
+    let streak = effects.daily.map(|(_, streak)| streak).unwrap_or(1);
+    let reward = server.daily_reward(streak).map(|reward| reward.describe(server)).unwrap_or_default();
+    let next = server.daily_reward(streak + 1).map(|reward| reward.describe(server)).unwrap_or_default();
+    let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+    std::mem::drop(data);
+
+    match store.save_effects(guild_id, user_id, player_id, &effects).await {
+        Ok(()) => {}
+        // another message of this player claimed it in the meantime
+        Err(Error::OutOfDate) => {
+            error!(ctx, message.channel_id, lang => "daily.out_of_date");
+            return Ok(());
+        }
+        Err(why) => return Err(why.into()),
+    }
+    quick_init!(ctx -> mut data; message => s:server [player]);
+    game::apply(player, &effects);
+    std::mem::drop(data);
+
+    success!(ctx, message.channel_id, lang => "daily.claimed", reward = reward, streak = streak, next = next);
+    Ok(())
+}
+
+/// Hours left before the next daily claim, which resets at midnight (UTC)
+fn hours_until_tomorrow(now: SystemTime) -> u64 {
+    let elapsed = now.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs() % 86400).unwrap_or(0);
+    ((86400 - elapsed) + 3599) / 3600
+}
@@ -12,6 +12,9 @@ use serenity::{
 };
 
 use crate::prelude::*;
+use crate::error::Error;
+use crate::game::{self, Action, Refusal, ShopItem};
+use super::trade::refusal_reason;
 
 macro_rules! storage_field {
     (($e:ident) $title:expr, $storage:expr, $item_type:expr, $map:tt) => {
@@ -38,27 +41,35 @@ macro_rules! storage_field {
 #[command("storage")]
 #[aliases("shed", "seedlings")]
 async fn cmd_storage(ctx: &Context, message: &Message) -> CommandResult {
+    crate::time_check::run(ctx, message).await?;
+
     quick_init!(ctx; message => s:server [player]);
     let _ = message.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e: &mut CreateEmbed| {
-            e.title("Your storage");
+            e.title(tr!(server.lang => "storage.title"));
             e.color(DEFAULT_COLOR);
 
-            e.description(eformat!(
-               "/:shed/ You own a **{storage_size}** shed.
-                Storage capacity: **/:pallet/ {max_pallets} pallets** and **:seedling: {max_seedlings} seedlings**",
-                storage_size = "small", max_pallets = 50, max_seedlings = 250,
+            let shed = player.shed();
+            e.description(tr!(
+                server.lang => "storage.description",
+                storage_size = shed.display_name(&server.lang),
+                pallets = player.stored(ItemType::Pallet), max_pallets = shed.max_pallets,
+                seedlings = player.stored(ItemType::Seedling), max_seedlings = shed.max_seedlings,
+                upgrade = match player.next_shed() {
+                    Some(next) => tr!(server.lang => "storage.upgrade", name = next.display_name(&server.lang), cost = next.cost),
+                    None => "".to_owned(),
+                },
             ));
 
-            storage_field!((e) "Pallets", player.storage, ItemType::Pallet, (|k, amount| {
+            storage_field!((e) tr!(server.lang => "storage.pallets"), player.storage, ItemType::Pallet, (|k, amount| {
                 if k == &0 {
-                    eformat!("/:pallet/ Empty pallets: **{}**", amount)
+                    tr!(server.lang => "storage.empty_pallets", amount = amount)
                 } else {
                     let species = server.species.get(k).unwrap();
                     eformat!("/:pallet/{}: **{}**", species.emoji, amount)
                 }
             }));
-            storage_field!((e) "Seedlings", player.storage, ItemType::Seedling, (|k, amount| {
+            storage_field!((e) tr!(server.lang => "storage.seedlings"), player.storage, ItemType::Seedling, (|k, amount| {
                 let species = server.species.get(k).unwrap();
                 format!(":seedling:{}: **{}**", species.emoji, amount)
             }));
@@ -71,37 +82,142 @@ async fn cmd_storage(ctx: &Context, message: &Message) -> CommandResult {
     Ok(())
 }
 
+#[command("unpack")]
+async fn cmd_unpack(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let lang = server.lang.clone();
+    let species = match args.current().and_then(|query| server.find_species(query)) {
+        Some(species) => species.clone(),
+        None => {
+            error!(ctx, message.channel_id, lang => "unpack.usage");
+            return Ok(());
+        }
+    };
+
+    let owned = *player.storage.get(&(ItemType::Pallet, species.id)).unwrap_or(&0);
+    args.advance();
+    let amount = match args.current() {
+        None => 1,
+        Some("all") => owned,
+        Some(amount) => amount.parse::<i32>().unwrap_or(0),
+    };
+    let effects = match game::play(server, player, &Action::Unpack { species_id: species.id, amount }) {
+        Ok(effects) => effects,
+        Err(Refusal::StorageFull(full)) => {
+            error!(ctx, message.channel_id, lang => "unpack.storage_full",
+                count = amount.saturating_mul(species.pallet_qty), reason = full.describe(&lang));
+            return Ok(());
+        }
+        Err(_) => {
+            error!(ctx, message.channel_id, lang => "unpack.not_enough_owned", emoji = species.emoji, count = owned);
+            return Ok(());
+        }
+    };
+    // the game rules made sure it doesn't overflow
+    let seedlings = amount * species.pallet_qty;
+
+    let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+    match store.save_effects(guild_id, user_id, player_id, &effects).await {
+        Ok(()) => {}
+        // the pallets have been used in the meantime
+        Err(Error::OutOfDate) => {
+            error!(ctx, message.channel_id, lang => "unpack.not_enough", emoji = species.emoji);
+            return Ok(());
+        }
+        Err(why) => return Err(why.into()),
+    }
+
+    quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+    game::apply(player, &effects);
+
+    success!(ctx, message.channel_id, lang => "unpack.done", count = amount, emoji = species.emoji, seedlings = seedlings);
+    Ok(())
+}
+
+impl ShopItem {
+    fn display(&self, lang: &str) -> String {
+        match self {
+            ShopItem::Pallet(species, 1) => tr!(lang => "shop.item.pallet", emoji = species.emoji, name = species.name),
+            ShopItem::Pallet(species, qty) => tr!(lang => "shop.item.pallets", emoji = species.emoji, name = species.name, count = *qty),
+            ShopItem::ShedUpgrade(tier) => tr!(lang => "shop.item.shed_upgrade", name = SHED_TIERS[*tier].display_name(lang)),
+        }
+    }
+}
+
 #[command("shop")]
 #[aliases("store")]
-async fn cmd_shop(ctx: &Context, message: &Message, _args: Args) -> CommandResult {
+async fn cmd_shop(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
     quick_init!(ctx -> data =>; message => s:server [player]);
-    let mut msg = message.channel_id.say(&ctx.http, "Loading the shop...").await?;
+    let lang = server.lang.clone();
+    let shed_upgrade = player.next_shed().map(|_| ShopItem::ShedUpgrade(player.storage_upgrade.max(1) as usize));
+    if args.current() == Some("buy") {
+        args.advance();
+        let species = args.current().and_then(|query| server.find_species(query)).cloned();
+        args.advance();
+        let qty = match args.current() {
+            None => Some(1),
+            Some(qty) => qty.parse::<i32>().ok().filter(|qty| *qty > 0),
+        };
+        let item = match (species, qty) {
+            (Some(species), Some(qty)) => ShopItem::Pallet(species, qty),
+            _ => {
+                std::mem::drop(data);
+                error!(ctx, message.channel_id, lang => "shop.buy_usage");
+                return Ok(());
+            }
+        };
+        // balance and capacity are checked right away, and again on confirmation
+        if let Err(refusal) = game::play(server, player, &Action::Buy(item.clone())) {
+            let reason = refusal_reason(server, &refusal);
+            std::mem::drop(data);
+            error!(ctx, message.channel_id, lang => "shop.buy_refused", item = item.display(&lang), reason = reason);
+            return Ok(());
+        }
+        std::mem::drop(data);
+        return create_shop_transaction(ctx, &message, &item).await;
+    }
+    if args.current() == Some("upgrade") {
+        std::mem::drop(data);
+        if let Some(item) = shed_upgrade {
+            let _ = create_shop_transaction(ctx, &message, &item).await?;
+        } else {
+            info!(ctx, message.channel_id, lang => (":tada:") "shop.fully_upgraded");
+        }
+        return Ok(());
+    }
+
+    let mut msg = message.channel_id.say(&ctx.http, tr!(lang => "shop.loading")).await?;
 
-    let buyable_species: Vec<Species> = server.species.values()
-        .filter(|s| s.pallet_cost > 0)
+    let mut buyable_species: Vec<Species> = server.species.values()
+        .filter(|s| s.enabled && s.pallet_cost > 0)
         .map(|s| s.clone())
         .collect();
+    buyable_species.sort_by_key(|s| s.id);
+    let mut items: Vec<ShopItem> = buyable_species.into_iter().map(|species| ShopItem::Pallet(species, 1)).collect();
+    // one reaction per item, 0 is not used
+    items.truncate(EMOJI_NUMBERS.len() - 1 - shed_upgrade.is_some() as usize);
+    items.extend(shed_upgrade);
     let player_coins = player.coins;
     std::mem::drop(data);
 
-    for i in 1..=buyable_species.len() {
+    for i in 1..=items.len() {
         let _ = msg.react(&ctx.http, EMOJI_NUMBERS[i].clone()).await?;
     }
     msg.edit(&ctx.http, |m| {
         m.content("");
         m.embed(|e: &mut CreateEmbed| {
-            e.title("Shop");
+            e.title(tr!(lang => "shop.title"));
             e.color(DEFAULT_COLOR);
             
             let mut lines = Vec::new();
-            for (i, species) in buyable_species.iter().enumerate() {
+            for (i, item) in items.iter().enumerate() {
                 lines.push(eformat!(
-                    "/:pallet/{emoji} `{identifier}. {name} Pallet` [{cost} /:coin/]",
-                    emoji = species.emoji, name = species.name, cost = species.pallet_cost,
+                    "`{identifier}.` {item} [{cost} /:coin/]",
+                    item = item.display(&lang), cost = item.cost(),
                     identifier = i + 1
                 ));
             }
-            e.description(eformat!("{}\n\nYour balance: **{}** /:coin/", lines.join("\n"), player_coins));
+            e.description(format!("{}\n\n{}", lines.join("\n"), tr!(lang => "shop.balance", coins = player_coins)));
             e
         });
         m
@@ -114,8 +230,8 @@ async fn cmd_shop(ctx: &Context, message: &Message, _args: Args) -> CommandResul
         .await {
         if let ReactionType::Unicode(emoji) = &reaction_action.as_inner_ref().emoji {
             let identifier = (emoji.chars().next().unwrap() as u32) - 48 - 1;
-            if let Some(species) = buyable_species.get(identifier as usize) {
-                let _ = create_shop_transaction(ctx, &message, species).await?;
+            if let Some(item) = items.get(identifier as usize) {
+                let _ = create_shop_transaction(ctx, &message, item).await?;
             }
         }
     }
@@ -123,10 +239,14 @@ async fn cmd_shop(ctx: &Context, message: &Message, _args: Args) -> CommandResul
     Ok(())
 }
 
-async fn create_shop_transaction(ctx: &Context, origin: &Message, species: &Species) -> CommandResult {
+async fn create_shop_transaction(ctx: &Context, origin: &Message, item: &ShopItem) -> CommandResult {
     let user = &origin.author;
+    quick_init!(ctx -> data; origin => s:server);
+    let lang = server.lang.clone();
+    std::mem::drop(data);
+
     let mut msg = origin.channel_id.send_message(&ctx.http, |m|
-        m.embed(|e| shop_transaction_create_embed(e, "PENDING", 0x303F9F, user, &species))
+        m.embed(|e| shop_transaction_create_embed(e, &lang, TransactionStatus::Pending, 0x303F9F, user, item))
     ).await?;
 
     let _ = msg.react(&ctx.http, ReactionType::Unicode("✅".to_string())).await?;
@@ -142,70 +262,94 @@ async fn create_shop_transaction(ctx: &Context, origin: &Message, species: &Spec
         .await {
         if let ReactionType::Unicode(emoji) = &reaction_action.as_inner_ref().emoji {
             if emoji.as_str() == "✅" {
-                quick_init!(ctx -> mut data =>; origin => s:server [player]);
-                if player.coins >= species.pallet_cost {
-                    player.coins -= species.pallet_cost;
-
-                    let (player_id, species_id) = (player.id, species.id);
-                    let (cost, qty) = (species.pallet_cost, 1);
-                    let _ = player.give_item(ItemType::Pallet, species_id, qty);
-
-                    quick_init!(ctx ~data~ => p:pool);
-                    sqlx::query!(
-                       "UPDATE players SET coins = coins - $2 WHERE id = $1",
-                       player_id, cost 
-                    ).execute(pool).await?;
-                    sqlx::query!(
-                       "INSERT INTO storage (player_id, item_type, item_id, amount) VALUES ($1, $2, $3, $4)
-                        ON CONFLICT(player_id, item_type, item_id) DO UPDATE SET amount = storage.amount + $4",
-                        player_id, ItemType::Pallet as i16, species_id, qty
-                    ).execute(pool).await?;
-
-                    msg.edit(&ctx.http, |m|
-                        m.embed(|e| shop_transaction_create_embed(e, "CONFIRMED", 0x03A9F4, user, &species))
-                    ).await?;
-                } else {
-                    msg.edit(&ctx.http, |m|
-                        m.embed(|e| shop_transaction_create_embed(e, "CACELLED; NOT ENOUGH COINS", 0xFFA000, user, &species))
-                    ).await?;
+                quick_init!(ctx -> data => st:store; origin => s:server [player]);
+                let effects = match game::play(server, player, &Action::Buy(item.clone())) {
+                    Ok(effects) => effects,
+                    Err(refusal) => {
+                        let reason = refusal_reason(server, &refusal);
+                        std::mem::drop(data);
+                        msg.edit(&ctx.http, |m|
+                            m.embed(|e| shop_transaction_create_embed(e, &lang, TransactionStatus::Refused(reason), 0xFFA000, user, item))
+                        ).await?;
+                        return Ok(());
+                    }
+                };
+                let (guild_id, player_id) = (player.guild_id, player.id);
+                std::mem::drop(data);
+
+                match store.save_effects(guild_id, user.id.0, player_id, &effects).await {
+                    Ok(()) => {}
+                    // the balance or the shed changed since the cache was loaded
+                    Err(Error::OutOfDate) => {
+                        let reason = tr!(lang => "shop.transaction.out_of_date");
+                        msg.edit(&ctx.http, |m|
+                            m.embed(|e| shop_transaction_create_embed(e, &lang, TransactionStatus::Refused(reason), 0xFFA000, user, item))
+                        ).await?;
+                        return Ok(());
+                    }
+                    Err(why) => return Err(why.into()),
                 }
+                quick_init!(ctx -> mut data; origin => s:server [player]);
+                game::apply(player, &effects);
+                std::mem::drop(data);
+
+                msg.edit(&ctx.http, |m|
+                    m.embed(|e| shop_transaction_create_embed(e, &lang, TransactionStatus::Confirmed, 0x03A9F4, user, item))
+                ).await?;
+                crate::achievements::run(ctx, origin).await?;
                 return Ok(());
             }
         }
         msg.edit(&ctx.http, |m|
-            m.embed(|e| shop_transaction_create_embed(e, "CANCELLED", 0xFFA000, user, &species))
+            m.embed(|e| shop_transaction_create_embed(e, &lang, TransactionStatus::Cancelled, 0xFFA000, user, item))
         ).await?;
     } else {
         msg.edit(&ctx.http, |m|
-            m.embed(|e| shop_transaction_create_embed(e, "TIMED OUT", 0xFFA000, user, &species))
+            m.embed(|e| shop_transaction_create_embed(e, &lang, TransactionStatus::TimedOut, 0xFFA000, user, item))
         ).await?;
     }
 
     Ok(())
 }
 
-fn shop_transaction_create_embed<'a, C>(e: &'a mut CreateEmbed, status: &'a str, color: C, user: &User, species: &Species) 
-    -> &'a mut CreateEmbed
+/// State of a shop transaction, shown in its embed title
+enum TransactionStatus {
+    Pending,
+    Confirmed,
+    Cancelled,
+    /// Cancelled on confirmation, with the reason
+    Refused(String),
+    TimedOut,
+}
+
+impl TransactionStatus {
+    fn display(&self, lang: &str) -> String {
+        match self {
+            TransactionStatus::Pending => tr!(lang => "shop.transaction.status.pending"),
+            TransactionStatus::Confirmed => tr!(lang => "shop.transaction.status.confirmed"),
+            TransactionStatus::Cancelled => tr!(lang => "shop.transaction.status.cancelled"),
+            TransactionStatus::Refused(reason) => tr!(lang => "shop.transaction.status.refused", reason = reason.to_uppercase()),
+            TransactionStatus::TimedOut => tr!(lang => "shop.transaction.status.timed_out"),
+        }
+    }
+}
+
+fn shop_transaction_create_embed<'a, C>(
+    e: &'a mut CreateEmbed, lang: &str, status: TransactionStatus, color: C, user: &User, item: &ShopItem
+) -> &'a mut CreateEmbed
 where C: Into<Colour> {
-    e.title(format!("Shop Transaction ({})", status));
+    e.title(tr!(lang => "shop.transaction.title", status = status.display(lang)));
     e.color(color);
 
-    if status == "PENDING" {
-        e.description(eformat!("
-            **Cost:** {cost} /:coin/
-            **Item:** /:pallet/{emoji} `{name} Pallet`
-
-            **React with :white_check_mark: to confirm the transaction.**
-        ", cost = species.pallet_cost, emoji = species.emoji, name = species.name));
+    let details = tr!(lang => "shop.transaction.details", cost = item.cost(), item = item.display(lang));
+    if let TransactionStatus::Pending = status {
+        e.description(format!("{}\n\n{}", details, tr!(lang => "shop.transaction.confirm")));
     } else {
-        e.description(eformat!("
-            **Cost:** {cost}/:coin/
-            **Item:** /:pallet/{emoji} `{name} Pallet`
-        ", cost = species.pallet_cost, emoji = species.emoji, name = species.name));
+        e.description(details);
     }
     
     e.footer(|f| {
-        f.text(format!("Transaction holder: {}", user.tag()));
+        f.text(tr!(lang => "shop.transaction.holder", user = user.tag()));
         if let Some(icon_url) = user.avatar_url() {
             f.icon_url(icon_url);
         } else {
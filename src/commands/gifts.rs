@@ -0,0 +1,187 @@
+use std::time::SystemTime;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        macros::command,
+        CommandResult, Args
+    },
+    builder::CreateEmbed,
+};
+
+use crate::prelude::*;
+use crate::error::Error;
+use crate::game::{self, Offer};
+use super::trade::{item_label, parse_item, refusal_reason};
+
+/// Transfers listed by the `transfers` command
+const TRANSFERS_SHOWN: usize = 15;
+
+lazy_static! {
+    static ref USER_MENTION_REGEX: Regex = Regex::new(r"^<@!?\d+>$").unwrap();
+}
+
+#[command("give")]
+#[aliases("gift", "donate")]
+async fn cmd_give(ctx: &Context, message: &Message, args: Args) -> CommandResult {
+    quick_init!(ctx -> data; message => s:server);
+    let lang = server.lang.clone();
+    // the mention can be anywhere in the arguments
+    let words = args.raw().filter(|word| !USER_MENTION_REGEX.is_match(word)).collect::<Vec<&str>>();
+    let gift = match words.as_slice() {
+        [thing, amount] => parse_item(server, thing, None).zip(amount.parse::<i32>().ok()),
+        [thing, kind, amount] => parse_item(server, thing, Some(*kind)).zip(amount.parse::<i32>().ok()),
+        _ => None,
+    };
+    std::mem::drop(data);
+    let (receiver, item, amount) = match (message.mentions.first(), gift) {
+        (Some(user), Some((item, amount))) if !user.bot && user.id != message.author.id && amount > 0 => (user.clone(), item, amount),
+        _ => {
+            error!(ctx, message.channel_id, lang => "give.usage");
+            return Ok(());
+        }
+    };
+    crate::cache_user(ctx, message, receiver.id.0).await?;
+
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let daily_cap = if item.is_some() { server.daily_item_gifts } else { server.daily_coin_gifts };
+    if daily_cap == 0 {
+        error!(ctx, message.channel_id, lang => "give.disabled");
+        return Ok(());
+    }
+    let (guild_id, user_id) = (player.guild_id, player.user_id);
+    std::mem::drop(data);
+
+    let (coins, items) = store.gifts_today(guild_id, user_id).await?;
+    let given = if item.is_some() { items } else { coins };
+    if given + amount as i64 > daily_cap as i64 {
+        error!(ctx, message.channel_id, lang => "give.limit_reached", count = (daily_cap as i64 - given).max(0));
+        return Ok(());
+    }
+
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let receiver_player = server.player_cache.get(&receiver.id.0).ok_or(Error::Missing("player"))?;
+    let mut offer = Offer::default();
+    match item {
+        Some(item) => offer.add_items(item, amount),
+        None => offer.add_coins(amount),
+    }
+    let (mut giver_effects, mut receiver_effects) = match game::trade(server, (player, &offer), (receiver_player, &Offer::default())) {
+        Ok(effects) => effects,
+        Err((refused_user_id, refusal)) => {
+            let reason = refusal_reason(server, &refusal);
+            let key = if refused_user_id == user_id {"give.refused"} else {"give.receiver_refused"};
+            std::mem::drop(data);
+            error!(ctx, message.channel_id, lang => key, reason = reason);
+            return Ok(());
+        }
+    };
+    giver_effects.reason = Reason::Gift;
+    receiver_effects.reason = Reason::Gift;
+    let description = match item {
+        Some((item_type, item_id)) => format!("**{}** {}", amount, item_label(server, item_type, item_id)),
+        None => eformat!("**{}** /:coin/", amount),
+    };
+    let transfer = Transfer {
+        giver_id: user_id,
+        receiver_id: receiver.id.0,
+        item,
+        amount,
+        created_at: SystemTime::now(),
+    };
+
+    let result = store.save_gift(
+        guild_id,
+        (user_id, player.id, &giver_effects),
+        (receiver_player.user_id, receiver_player.id, &receiver_effects),
+        &transfer, daily_cap,
+    ).await;
+    std::mem::drop(data);
+    match result {
+        Ok(true) => {}
+        Ok(false) => {
+            error!(ctx, message.channel_id, lang => "give.limit_reached", count = 0);
+            return Ok(());
+        }
+        // the giver spent their coins or items in the meantime
+        Err(Error::OutOfDate) => {
+            error!(ctx, message.channel_id, lang => "give.out_of_date");
+            return Ok(());
+        }
+        Err(why) => return Err(why.into()),
+    }
+
+    quick_init!(ctx -> mut data; message => s:server);
+    for (user_id, effects) in [(user_id, &giver_effects), (receiver.id.0, &receiver_effects)].iter() {
+        if let Some(player) = server.player_cache.1.get_mut(user_id) {
+            game::apply(player, effects);
+        }
+    }
+    std::mem::drop(data);
+
+    success!(ctx, message.channel_id, lang => "give.done", gift = description, user_id = receiver.id.0);
+    Ok(())
+}
+
+#[command("transfers")]
+#[aliases("ledger")]
+async fn cmd_transfers(ctx: &Context, message: &Message) -> CommandResult {
+    let permissions = message.member(&ctx.cache).await?.permissions(&ctx.cache).await?;
+    quick_init!(ctx -> data => st:store; message => s:server);
+    let lang = server.lang.clone();
+    if !permissions.manage_guild() && message.author.id.0 != 345259637513256960 {
+        error!(ctx, message.channel_id, lang => "settings.insufficient_permissions");
+        return Ok(());
+    }
+    let guild_id = server.id as u64;
+    std::mem::drop(data);
+
+    let user_id = message.mentions.first().map(|user| user.id.0);
+    let transfers = store.transfers(guild_id, user_id, TRANSFERS_SHOWN).await?;
+
+    quick_init!(ctx -> data; message => s:server);
+    let lines = transfers.iter()
+        .map(|transfer| tr!(
+            lang => "transfers.entry",
+            giver_id = transfer.giver_id, receiver_id = transfer.receiver_id,
+            gift = match transfer.item {
+                Some((item_type, item_id)) => format!("**{}** {}", transfer.amount, item_label(server, item_type, item_id)),
+                None => eformat!("**{}** /:coin/", transfer.amount),
+            },
+            age = describe_age(&lang, transfer.created_at),
+        ))
+        .collect::<Vec<String>>();
+    std::mem::drop(data);
+
+    message.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e: &mut CreateEmbed| {
+            e.title(tr!(lang => "transfers.title"));
+            e.color(DEFAULT_COLOR);
+            let mut description = match user_id {
+                Some(user_id) => tr!(lang => "transfers.of_user", user_id = user_id),
+                None => tr!(lang => "transfers.latest"),
+            };
+            description.push_str("\n\n");
+            if lines.is_empty() {
+                description.push_str(&tr!(lang => "transfers.empty"));
+            } else {
+                description.push_str(&lines.join("\n"));
+            }
+            e.description(description)
+        })
+    }).await?;
+    Ok(())
+}
+
+/// Time elapsed since a transfer or a ledger entry, in minutes, hours or days
+pub(super) fn describe_age(lang: &str, created_at: SystemTime) -> String {
+    let minutes = created_at.elapsed().map(|elapsed| elapsed.as_secs() / 60).unwrap_or(0);
+    match minutes {
+        0..=59 => tr!(lang => "transfers.age.minutes", count = minutes),
+        60..=1439 => tr!(lang => "transfers.age.hours", count = minutes / 60),
+        _ => tr!(lang => "transfers.age.days", count = minutes / 1440),
+    }
+}
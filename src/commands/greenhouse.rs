@@ -0,0 +1,214 @@
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        macros::command,
+        CommandResult, Args
+    },
+    builder::CreateEmbed,
+};
+use crate::prelude::*;
+use crate::error::Error;
+use crate::game::{self, Action, GreenhouseDelta, Refusal, StorageDelta};
+
+#[command("greenhouse")]
+#[aliases("gh")]
+async fn cmd_greenhouse(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    crate::time_check::run(ctx, message).await?;
+
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let lang = server.lang.clone();
+    match args.current() {
+        Some(subcommand @ ("fill" | "plant" | "sow" | "seed")) => {
+            // slots are filled either with a pallet, or with seedlings when sowing
+            let sowing = subcommand == "sow" || subcommand == "seed";
+            let usage = if sowing { "greenhouse.sow.usage" } else { "greenhouse.fill.usage" };
+            args.advance();
+            let species = match args.current().and_then(|query| server.find_species(query)) {
+                Some(species) => species.clone(),
+                None => {
+                    error!(ctx, message.channel_id, lang => usage);
+                    return Ok(());
+                }
+            };
+            let (item_type, qty) = if sowing {
+                args.advance();
+                match args.current().and_then(|amount| amount.parse::<i32>().ok()).filter(|amount| *amount > 0) {
+                    Some(amount) => (ItemType::Seedling, amount),
+                    None => {
+                        error!(ctx, message.channel_id, lang => usage);
+                        return Ok(());
+                    }
+                }
+            } else {
+                (ItemType::Pallet, 1)
+            };
+            args.advance();
+            // an invalid slot number is refused by the game rules
+            let slot_id = args.current().map(|slot| slot.parse::<i16>().unwrap_or(0));
+
+            let action = Action::FillGreenhouse { slot_id, species_id: species.id, item_type, qty };
+            let effects = match game::play(server, player, &action) {
+                Ok(effects) => effects,
+                Err(Refusal::NotGrowable) => {
+                    error!(ctx, message.channel_id, lang => "greenhouse.fill.not_growable", emoji = species.emoji, name = species.name);
+                    return Ok(());
+                }
+                Err(Refusal::NotEnoughItems { .. }) => {
+                    match item_type {
+                        ItemType::Pallet => error!(ctx, message.channel_id, lang => "greenhouse.fill.missing_pallet", emoji = species.emoji),
+                        ItemType::Seedling => error!(ctx, message.channel_id, lang => "greenhouse.sow.missing_seedlings",
+                            emoji = species.emoji, count = qty),
+                    }
+                    return Ok(());
+                }
+                Err(Refusal::NoFreeSlot) => {
+                    error!(ctx, message.channel_id, lang => "greenhouse.fill.no_slot");
+                    return Ok(());
+                }
+                Err(_) => {
+                    error!(ctx, message.channel_id, lang => usage);
+                    return Ok(());
+                }
+            };
+            let (slot_id, remaining) = effects.greenhouse.iter()
+                .find_map(|delta| match delta {
+                    GreenhouseDelta::Fill { slot_id, remaining, .. } => Some((*slot_id, *remaining)),
+                    _ => None,
+                })
+                .expect("filling a greenhouse slot gives a fill delta");
+
+            let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+            match store.save_effects(guild_id, user_id, player_id, &effects).await {
+                Ok(()) => {}
+                Err(Error::OutOfDate) => {
+                    error!(ctx, message.channel_id, lang => "greenhouse.out_of_date");
+                    return Ok(());
+                }
+                Err(why) => return Err(why.into()),
+            }
+
+            quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+            game::apply(player, &effects);
+
+            success!(ctx, message.channel_id, lang => "greenhouse.fill.done",
+                slot = slot_id, count = remaining, emoji = species.emoji, growth_rate = species.growth_rate);
+        }
+        Some("collect" | "harvest") => {
+            let effects = match game::play(server, player, &Action::CollectGreenhouse) {
+                Ok(effects) => effects,
+                Err(_) => {
+                    if player.greenhouse.values().any(|slot| slot.grown > 0) {
+                        error!(ctx, message.channel_id, lang => "greenhouse.collect.storage_full");
+                    } else {
+                        info!(ctx, message.channel_id, lang => (":potted_plant:") "greenhouse.collect.nothing");
+                    }
+                    return Ok(());
+                }
+            };
+            let mut collected: Vec<(i16, i32)> = Vec::new();
+            for delta in effects.storage.iter() {
+                if let StorageDelta::Add { item_id, qty, .. } = delta {
+                    match collected.iter_mut().find(|(species_id, _)| *species_id == *item_id) {
+                        Some((_, amount)) => *amount += qty,
+                        None => collected.push((*item_id, *qty)),
+                    }
+                }
+            }
+            let lines = collected.iter()
+                .map(|(species_id, qty)| match server.species.get(species_id) {
+                    Some(species) => format!(":seedling:{} **{}**", species.emoji, qty),
+                    None => format!(":seedling: **{}**", qty),
+                })
+                .collect::<Vec<String>>();
+
+            let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+            match store.save_effects(guild_id, user_id, player_id, &effects).await {
+                Ok(()) => {}
+                Err(Error::OutOfDate) => {
+                    error!(ctx, message.channel_id, lang => "greenhouse.out_of_date");
+                    return Ok(());
+                }
+                Err(why) => return Err(why.into()),
+            }
+
+            quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+            game::apply(player, &effects);
+
+            success!(ctx, message.channel_id, lang => "greenhouse.collect.done", seedlings = lines.join(", "));
+        }
+        Some("upgrade") => {
+            let next = match player.next_greenhouse() {
+                Some(next) => next,
+                None => {
+                    info!(ctx, message.channel_id, lang => (":tada:") "greenhouse.upgrade.fully_upgraded");
+                    return Ok(());
+                }
+            };
+            let effects = match game::play(server, player, &Action::UpgradeGreenhouse) {
+                Ok(effects) => effects,
+                Err(_) => {
+                    error!(ctx, message.channel_id, lang => "greenhouse.upgrade.too_expensive",
+                        name = next.display_name(&lang), cost = next.cost, coins = player.coins);
+                    return Ok(());
+                }
+            };
+
+            let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+            match store.save_effects(guild_id, user_id, player_id, &effects).await {
+                Ok(()) => {}
+                // coins have been spent in the meantime
+                Err(Error::OutOfDate) => {
+                    error!(ctx, message.channel_id, lang => "greenhouse.upgrade.not_enough_coins");
+                    return Ok(());
+                }
+                Err(why) => return Err(why.into()),
+            }
+
+            quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+            game::apply(player, &effects);
+
+            success!(ctx, message.channel_id, lang => "greenhouse.upgrade.done", name = next.display_name(&lang), count = next.slots);
+        }
+        Some(_) => {
+            error!(ctx, message.channel_id, lang => "greenhouse.invalid_argument");
+        }
+        None => {
+            let tier = player.greenhouse_tier();
+            let _ = message.channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e: &mut CreateEmbed| {
+                    e.title(tr!(lang => "greenhouse.view.title"));
+                    e.color(DEFAULT_COLOR);
+
+                    e.description(tr!(
+                        lang => "greenhouse.view.description",
+                        name = tier.display_name(&lang), count = tier.slots, multiplier = GREENHOUSE_YIELD,
+                        upgrade = match player.next_greenhouse() {
+                            Some(next) => tr!(lang => "greenhouse.view.upgrade", name = next.display_name(&lang), cost = next.cost),
+                            None => "".to_owned(),
+                        },
+                    ));
+
+                    let lines = (1..=tier.slots)
+                        .map(|slot_id| match player.greenhouse.get(&slot_id) {
+                            Some(slot) => {
+                                let (emoji, growth_rate) = match server.species.get(&slot.species) {
+                                    Some(species) => (species.emoji.to_string(), species.growth_rate),
+                                    None => (":heavy_multiplication_x:".to_owned(), 0),
+                                };
+                                tr!(lang => "greenhouse.view.slot", slot = slot_id, emoji = emoji,
+                                    grown = slot.grown, remaining = slot.remaining, growth_rate = growth_rate)
+                            }
+                            None => tr!(lang => "greenhouse.view.empty_slot", slot = slot_id),
+                        })
+                        .collect::<Vec<String>>();
+                    e.field(tr!(lang => "greenhouse.view.slots"), lines.join("\n"), false);
+                    e
+                });
+                m
+            }).await?;
+        }
+    };
+
+    Ok(())
+}
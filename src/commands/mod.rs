@@ -2,10 +2,38 @@ mod meta;
 mod stats;
 mod forest;
 mod config;
+mod greenhouse;
+mod workers;
+mod achievements;
+mod trade;
+mod gifts;
+mod wallet;
+mod daily;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    /// A channel mention, e.g. `<#123>`, or a bare channel id
+    static ref CHANNEL_MENTION_REGEX: Regex = Regex::new(r"^(?:<#(\d+)>|(\d+))$").unwrap();
+}
+
+/// Id of the channel mentioned in a command argument
+fn parse_channel_mention(input: &str) -> Option<u64> {
+    let captures = CHANNEL_MENTION_REGEX.captures(input)?;
+    captures.get(1).or_else(|| captures.get(2))?.as_str().parse::<u64>().ok()
+}
 
 pub mod prelude {
     pub use super::meta::*;
     pub use super::stats::*;
     pub use super::forest::*;
     pub use super::config::*;
+    pub use super::greenhouse::*;
+    pub use super::workers::*;
+    pub use super::achievements::*;
+    pub use super::trade::*;
+    pub use super::gifts::*;
+    pub use super::wallet::*;
+    pub use super::daily::*;
 }
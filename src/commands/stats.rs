@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
 use serenity::{
     prelude::*,
     model::prelude::*,
@@ -5,95 +9,168 @@ use serenity::{
         macros::command,
         CommandResult, Args
     },
-    builder::CreateEmbed
+    builder::CreateEmbed,
+    http::AttachmentType,
 };
-use sqlx::Row;
-
-use crate::prelude::DEFAULT_COLOR;
-
-macro_rules! bake_stats_query {
-    ($table:expr, $where:expr, $limit:expr) => {
-        sqlx::query(format!("
-            WITH total AS (SELECT SUM(count) AS total from {table} WHERE {where})
-            SELECT species, SUM(count) as total, 
-                ROUND(SUM(count)::float/(SELECT total from total) * 10000) / 100 AS percent
-            FROM {table} WHERE {where} GROUP BY species
-            ORDER BY total DESC LIMIT {limit}
-        ", table = $table, where = $where, limit = $limit).as_str())
-    };
-    (LEADERBOARDS $table:expr, $where:expr, $limit:expr) => {
-        sqlx::query(format!("
-            WITH total AS (SELECT SUM(count) AS total from {table} WHERE {where})
-            SELECT a.user_id, b.total, 
-                ROUND(b.total::float/(SELECT total from total) * 10000) / 100 AS percent,
-                a.species AS fav_species, a.channel_id AS fav_channel
-            FROM {table} a 
-            INNER JOIN (
-                SELECT user_id, MAX(count) count, SUM(count) total
-                FROM {table} WHERE {where} GROUP BY user_id
-            ) b
-            ON a.user_id = b.user_id AND a.count = b.count AND {where}
-            ORDER BY total DESC LIMIT {limit}
-        ", table = $table, where = $where, limit = $limit).as_str())
-    };
+use crate::error::Result;
+use crate::models::{EmojiKey, Ranked, Server, Species, TreeFilter, TreeScope, Window};
+use crate::prelude::{OfficialForest, Store, DEFAULT_COLOR};
+use crate::render::{self, Sprite};
+
+/// Time window of stats given as argument
+fn parse_window(input: &str) -> Option<Window> {
+    match input.to_lowercase().as_str() {
+        "today" | "day" => Some(Window::Today),
+        "week" => Some(Window::Week),
+        "month" => Some(Window::Month),
+        "all" => Some(Window::All),
+        _ => None,
+    }
+}
+
+/// Title suffix, empty for all-time stats
+fn window_label(window: Window, lang: &str) -> String {
+    match window {
+        Window::Today => format!(" ({})", tr!(lang => "stats.window.today")),
+        Window::Week => format!(" ({})", tr!(lang => "stats.window.week")),
+        Window::Month => format!(" ({})", tr!(lang => "stats.window.month")),
+        Window::All => String::new(),
+    }
+}
+
+/// Forests stats are computed on
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    Channel,
+    Server,
+    /// Every server which didn't opt out, and the Official Forest
+    Global,
+}
+
+impl Scope {
+    fn parse(input: &str) -> Option<Scope> {
+        match input.to_lowercase().as_str() {
+            "channel" => Some(Scope::Channel),
+            "server" | "guild" => Some(Scope::Server),
+            "global" | "world" => Some(Scope::Global),
+            _ => None,
+        }
+    }
+
+    /// Forests of the scope, as seen from a message
+    fn trees(self, message: &Message, official_forest: Option<u64>) -> TreeScope {
+        match self {
+            Scope::Channel => TreeScope::Channel(message.channel_id.0),
+            Scope::Server => TreeScope::Server(message.guild_id.map_or(0, |guild_id| guild_id.0)),
+            Scope::Global => TreeScope::Global { official_forest },
+        }
+    }
+
+    /// Title of a stats embed, `command` being the prefix of the translation key
+    fn title(self, command: &str, lang: &str) -> String {
+        tr!(lang => &format!("{}.title.{}", command, match self {
+            Scope::Channel => "channel",
+            Scope::Server => "server",
+            Scope::Global => "global",
+        }))
+    }
+}
+
+/// Species used to display stats
+/// Global stats only know default species, and species 0 standing for every custom species
+fn displayed_species(scope: Scope, server: &Server) -> HashMap<i16, Species> {
+    match scope {
+        Scope::Global => {
+            let mut species: HashMap<i16, Species> = Species::defaults().into_iter().map(|species| (species.id, species)).collect();
+            // a seedling, also drawn as `Sprite::Sapling` in forest views
+            species.insert(0, Species {
+                id: 0,
+                emoji: EmojiKey::from("🌱"),
+                name: tr!(server.lang => "stats.custom_species"),
+                pallet_cost: 0,
+                pallet_qty: 0,
+                default_qty: 0,
+                coins: 0,
+                growth_rate: 0,
+                enabled: true,
+            });
+            species
+        }
+        _ => server.species.clone(),
+    }
+}
+
+/// Window and scope arguments, given in any order
+fn stats_args(args: &Args) -> (Window, Option<Scope>) {
+    let mut window = Window::All;
+    let mut scope = None;
+    for arg in args.raw() {
+        if let Some(parsed) = parse_window(arg) {
+            window = parsed;
+        } else if let Some(parsed) = Scope::parse(arg) {
+            scope = Some(parsed);
+        }
+    }
+    (window, scope)
 }
 
 macro_rules! trees_stats {
-    (($e:ident) $species_hashmap:expr, $trees_stats:ident) => ({
+    (($e:ident) $lang:expr, $species_hashmap:expr, $trees_stats:ident) => ({
             let mut trees = Vec::new();
             let mut total_trees = 0;
 
             for tree in $trees_stats.iter() {
-                let total = tree.get::<i64, _>("total");
-                total_trees += total;
+                total_trees += tree.count;
 
-                let (emoji, name) = if let Some(species) = $species_hashmap.get(&tree.get::<i16, _>("species")) {
-                    (species.emoji.clone(), species.name.clone())
+                let (emoji, name) = if let Some(species) = $species_hashmap.get(&tree.species) {
+                    (species.emoji.to_string(), species.name.clone())
                 } else {
-                    (":heavy_multiplication_x:".to_owned(), "Unknown".to_owned())
+                    (":heavy_multiplication_x:".to_owned(), tr!($lang => "stats.unknown_species"))
                 };
                 trees.push(format!(
                     "**{}%** ({}) - {} `{}`",
-                    tree.get::<f64, _>("percent"), 
-                    total, emoji, name
+                    tree.percent, tree.count, emoji, name
                 ));
             }
-            $e.field(format!("Trees ({})", total_trees), trees.join("\n"), true);
+            $e.field(tr!($lang => "stats.trees", count = total_trees), trees.join("\n"), true);
     });
 }
 
 #[command("stats")]
 async fn cmd_stats(ctx: &Context, message: &Message, args: Args) -> CommandResult {
-    quick_init!(ctx => p:pool; message => s:server);
+    quick_init!(ctx -> data => st:store; message => s:server);
+    let official_forest = data.get::<OfficialForest>().copied();
+    let (window, scope) = stats_args(&args);
+    let scope = scope.unwrap_or(Scope::Channel);
+    let filter = TreeFilter::new(scope.trees(message, official_forest), window);
+    let trees_stats = store.species_counts(&filter, Some(5)).await?;
 
-    let guild_wide = args.current() == Some("server");
-    let trees_stats = bake_stats_query!("trees", if guild_wide {"guild_id = $1"} else {"channel_id = $1"}, 5)
-        .bind(if guild_wide {server.id} else {message.channel_id.0 as i64})
-        .fetch_all(pool).await?;
-
-    let description = if guild_wide {
-        let biggest_channel = sqlx::query!(
-            "SELECT channel_id, SUM(count) as total FROM trees WHERE guild_id = $1 GROUP BY channel_id ORDER BY SUM(count) DESC LIMIT 1",
-            server.id
-        ).fetch_one(pool).await?;
-        Some(eformat!("/:forest/ Biggest forest: <#{}> ({})", biggest_channel.channel_id, biggest_channel.total.unwrap()))
-    } else {
-        None
+    let description = match (scope, official_forest) {
+        (Scope::Server, _) => store.channel_counts(&filter, 1).await?
+            .first()
+            .map(|(channel_id, count)| tr!(
+                server.lang => "stats.biggest_forest",
+                channel = channel_id,
+                count = count
+            )),
+        (Scope::Global, Some(official_forest)) => {
+            let official = TreeFilter::new(TreeScope::Channel(official_forest), window);
+            let count = store.species_counts(&official, None).await?.iter().map(|tree| tree.count).sum::<i64>();
+            Some(tr!(server.lang => "stats.official_forest", count = count))
+        }
+        _ => None,
     };
+    let species = displayed_species(scope, server);
 
     let _ = message.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e: &mut CreateEmbed| {
-            if guild_wide {
-                e.title("Server Forest");
-            } else {
-                e.title("Channel Forest");
-            }
+            e.title(scope.title("stats", &server.lang) + &window_label(window, &server.lang));
             if let Some(desc) = description {
                 e.description(desc);
             }
             e.color(DEFAULT_COLOR);
 
-            trees_stats!((e) server.species, trees_stats);
+            trees_stats!((e) server.lang, species, trees_stats);
             e
         })
     }).await;
@@ -103,82 +180,292 @@ async fn cmd_stats(ctx: &Context, message: &Message, args: Args) -> CommandResul
 #[command("mystats")]
 #[aliases("my-stats")]
 async fn cmd_mystats(ctx: &Context, message: &Message, args: Args) -> CommandResult {
-    quick_init!(ctx => p:pool; message => s:server);
-
-    let guild_wide = args.current() == Some("server");
-    let trees_stats = bake_stats_query!("trees", if guild_wide {
-        "guild_id = $1 AND user_id = $2"
-    } else {
-        "channel_id = $1 AND user_id = $2"
-    }, 5)
-        .bind(if guild_wide {server.id} else {message.channel_id.0 as i64})
-        .bind(message.author.id.0 as i64)
-        .fetch_all(pool).await?;
+    quick_init!(ctx -> data => st:store; message => s:server);
+    let official_forest = data.get::<OfficialForest>().copied();
+    let (window, scope) = stats_args(&args);
+    let scope = scope.unwrap_or(Scope::Channel);
+    let filter = TreeFilter {
+        user_id: Some(message.author.id.0),
+        ..TreeFilter::new(scope.trees(message, official_forest), window)
+    };
+    let trees_stats = store.species_counts(&filter, Some(5)).await?;
+    let species = displayed_species(scope, server);
 
     let _ = message.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e: &mut CreateEmbed| {
-            if guild_wide {
-                e.title("Personal Forest (Server)");
-            } else {
-                e.title("Personal Forest (Channel)");
-            }
+            e.title(scope.title("mystats", &server.lang) + &window_label(window, &server.lang));
             e.color(DEFAULT_COLOR);
 
-            trees_stats!((e) server.species, trees_stats);
+            trees_stats!((e) server.lang, species, trees_stats);
             e
         })
     }).await;
     Ok(())
 }
 
+/// Leaderboard entries per page
+const LEADERBOARD_PAGE_SIZE: usize = 10;
+
+/// What players are ranked on
+#[derive(Clone, Copy)]
+enum Ranking {
+    Trees,
+    /// Trees of a single species
+    Species(i16),
+    /// Coins in the wallet, always server-wide and all-time
+    Coins,
+}
+
+impl Ranking {
+    fn species_id(self) -> Option<i16> {
+        match self {
+            Ranking::Species(species_id) => Some(species_id),
+            _ => None,
+        }
+    }
+}
+
+/// A leaderboard, as requested by a user whose rank is always shown
+struct Leaderboard {
+    ranking: Ranking,
+    window: Window,
+    scope: Scope,
+    /// Trees ranked, unused when ranking coins
+    filter: TreeFilter,
+    guild_id: u64,
+    user_id: u64,
+}
+
 #[command("leaderboard")]
+#[aliases("lb")]
 async fn cmd_leaderboard(ctx: &Context, message: &Message, args: Args) -> CommandResult {
-    quick_init!(ctx => p:pool; message => s:server);
+    quick_init!(ctx -> data => st:store; message => s:server);
+    let (mut window, mut scope, mut ranking) = (Window::All, Scope::Server, Ranking::Trees);
+    for arg in args.raw() {
+        if let Some(parsed) = parse_window(arg) {
+            window = parsed;
+            continue;
+        }
+        if let Some(parsed) = Scope::parse(arg) {
+            scope = parsed;
+            continue;
+        }
+        match arg.to_lowercase().as_str() {
+            "trees" => ranking = Ranking::Trees,
+            "coins" => ranking = Ranking::Coins,
+            _ => match server.find_species(arg) {
+                Some(species) => ranking = Ranking::Species(species.id),
+                None => {
+                    error!(ctx, message.channel_id, server.lang => "leaderboard.usage");
+                    return Ok(());
+                }
+            }
+        }
+    }
+    // coins are only meaningful in their server
+    if let Ranking::Coins = ranking {
+        scope = Scope::Server;
+        window = Window::All;
+    }
+    // and so are ids of custom species
+    if let (Ranking::Species(species_id), Scope::Global) = (ranking, scope) {
+        if !Species::is_default_id(species_id) {
+            error!(ctx, message.channel_id, server.lang => "leaderboard.custom_species_global");
+            return Ok(());
+        }
+    }
+
+    let leaderboard = Leaderboard {
+        ranking, window, scope,
+        filter: TreeFilter {
+            species: ranking.species_id(),
+            ..TreeFilter::new(scope.trees(message, data.get::<OfficialForest>().copied()), window)
+        },
+        guild_id: server.id as u64,
+        user_id: message.author.id.0,
+    };
+    let lang = server.lang.clone();
+    let species = displayed_species(scope, server);
+    std::mem::drop(data);
 
-    let guild_wide = args.current() != Some("channel");
-    let leaderboard = bake_stats_query!(LEADERBOARDS "trees", if guild_wide {"guild_id = $1"} else {"channel_id = $1"}, 5)
-        .bind(if guild_wide {server.id} else {message.channel_id.0 as i64})
-        .fetch_all(pool).await?;
+    let mut page = 0;
+    let (rows, ranked) = leaderboard.page(&store, page).await?;
+    let mut msg = message.channel_id.send_message(&ctx.http, |m| m.embed(|e| {
+        leaderboard.embed(e, &lang, &species, page, &rows, ranked)
+    })).await?;
 
-    let _ = message.channel_id.send_message(&ctx.http, |m| {
+    let pages = Leaderboard::pages(ranked);
+    if pages <= 1 {
+        return Ok(());
+    }
+    let _ = msg.react(&ctx.http, ReactionType::Unicode("◀️".to_string())).await?;
+    let _ = msg.react(&ctx.http, ReactionType::Unicode("▶️".to_string())).await?;
+
+    // reactions can't be removed without the manage messages permission,
+    // so both adding and removing one turns the page
+    while let Some(reaction_action) = msg.await_reaction(&ctx)
+        .author_id(message.author.id)
+        .added(true)
+        .removed(true)
+        .filter(|r| match &r.emoji {
+            ReactionType::Unicode(emoji) => emoji == "◀️" || emoji == "▶️",
+            _ => false
+        })
+        .timeout(Duration::from_secs(60))
+        .await {
+        page = match &reaction_action.as_inner_ref().emoji {
+            ReactionType::Unicode(emoji) if emoji == "◀️" => (page + pages - 1) % pages,
+            _ => (page + 1) % pages,
+        };
+        let (rows, ranked) = leaderboard.page(&store, page).await?;
+        msg.edit(&ctx.http, |m| m.embed(|e| {
+            leaderboard.embed(e, &lang, &species, page, &rows, ranked)
+        })).await?;
+    }
+    Ok(())
+}
+
+impl Leaderboard {
+    /// Rows of a leaderboard page, along with the row of the message author wherever they are ranked
+    /// Also gives the number of ranked players
+    async fn page(&self, store: &Arc<dyn Store>, page: usize) -> Result<(Vec<Ranked>, usize)> {
+        let offset = page * LEADERBOARD_PAGE_SIZE;
+        match self.ranking {
+            Ranking::Coins => store.coin_leaderboard(self.guild_id, self.user_id, offset, LEADERBOARD_PAGE_SIZE).await,
+            _ => store.tree_leaderboard(&self.filter, self.user_id, offset, LEADERBOARD_PAGE_SIZE).await,
+        }
+    }
+
+    fn pages(ranked: usize) -> usize {
+        (ranked + LEADERBOARD_PAGE_SIZE - 1) / LEADERBOARD_PAGE_SIZE
+    }
+
+    fn embed<'a>(
+        &self, e: &'a mut CreateEmbed, lang: &str, species: &HashMap<i16, Species>, page: usize, rows: &[Ranked], ranked: usize
+    ) -> &'a mut CreateEmbed {
+        let Leaderboard { ranking, window, scope, user_id, .. } = *self;
+        let title = match ranking {
+            Ranking::Coins => tr!(lang => "leaderboard.title.coins"),
+            _ => scope.title("leaderboard", lang),
+        };
+        match ranking.species_id().and_then(|id| species.get(&id)) {
+            Some(species) => e.title(format!("{} - {} {}{}", title, species.emoji, species.name, window_label(window, lang))),
+            None => e.title(title + &window_label(window, lang)),
+        };
+        e.color(DEFAULT_COLOR);
+
+        let entry = |row: &Ranked| {
+            let rank = match row.rank {
+                1 => ":first_place:".to_owned(),
+                2 => ":second_place:".to_owned(),
+                3 => ":third_place:".to_owned(),
+                _ => format!("#{}", rank)
+            };
+            if let Ranking::Coins = ranking {
+                return tr!(
+                    lang => "leaderboard.coins_entry",
+                    rank = rank,
+                    total = row.total,
+                    user_id = row.user_id,
+                );
+            }
+            tr!(
+                lang => "leaderboard.entry",
+                rank = rank,
+                percent = row.percent,
+                total = row.total,
+                user_id = row.user_id,
+                fav_species = match row.favorite.and_then(|(species_id, _)| species.get(&species_id)) {
+                    Some(fav_species) => fav_species.emoji.to_string(),
+                    None => tr!(lang => "leaderboard.unknown_species"),
+                },
+                // channels of other servers can't be mentioned
+                fav_extra = match row.favorite {
+                    Some((_, channel_id)) if scope == Scope::Server => tr!(lang => "leaderboard.favorite_forest", channel = channel_id),
+                    _ => String::new(),
+                },
+            )
+        };
+
+        let ranks = page * LEADERBOARD_PAGE_SIZE + 1..=(page + 1) * LEADERBOARD_PAGE_SIZE;
+        let on_page = |row: &Ranked| ranks.contains(&row.rank);
+        let lines: Vec<String> = rows.iter().filter(|row| on_page(row)).map(|row| entry(row)).collect();
+        let name = match ranking {
+            Ranking::Coins => tr!(lang => "leaderboard.richest_players"),
+            _ => tr!(lang => "leaderboard.best_planters"),
+        };
+        if lines.is_empty() {
+            e.field(name, tr!(lang => "leaderboard.empty"), false);
+        } else {
+            e.field(name, lines.join("\n"), false);
+        }
+
+        // the author's rank is always shown, even when it isn't on this page
+        match rows.iter().find(|row| row.user_id == user_id) {
+            Some(row) if !on_page(row) => {
+                e.field(tr!(lang => "leaderboard.your_rank"), entry(row), false);
+            }
+            None => {
+                e.field(tr!(lang => "leaderboard.your_rank"), tr!(lang => "leaderboard.unranked"), false);
+            }
+            _ => {}
+        }
+
+        let pages = Leaderboard::pages(ranked);
+        if pages > 1 {
+            e.footer(|f| f.text(tr!(lang => "leaderboard.page", page = page + 1, pages = pages)));
+        }
+        e
+    }
+}
+
+#[command("forest")]
+async fn cmd_forest(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    quick_init!(ctx -> data => st:store; message => s:server);
+    if args.current() != Some("view") {
+        error!(ctx, message.channel_id, server.lang => "forest.usage");
+        return Ok(());
+    }
+    args.advance();
+
+    let guild_wide = args.current() == Some("server");
+    let scope = if guild_wide {TreeScope::Server(server.id as u64)} else {TreeScope::Channel(message.channel_id.0)};
+    let mut trees = store.species_counts(&TreeFilter::new(scope, Window::All), None).await?;
+    trees.sort_by_key(|tree| tree.species);
+
+    let counts: Vec<(Sprite, u64)> = trees.iter()
+        .map(|tree| (Sprite::for_species(server.species.get(&tree.species)), tree.count.max(0) as u64))
+        .collect();
+    let total_trees: u64 = counts.iter().map(|(_, count)| count).sum();
+    let lang = server.lang.clone();
+    std::mem::drop(data);
+
+    if total_trees == 0 {
+        error!(ctx, message.channel_id, lang => "forest.view.empty");
+        return Ok(());
+    }
+
+    // the same forest always looks the same
+    let seed = if guild_wide {message.guild_id.unwrap().0} else {message.channel_id.0};
+    let image = render::render_forest(seed, &counts)?;
+
+    message.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e: &mut CreateEmbed| {
             if guild_wide {
-                e.title("Server Leaderboard");
+                e.title(tr!(lang => "stats.title.server"));
             } else {
-                e.title("Channel Leaderboard");
+                e.title(tr!(lang => "stats.title.channel"));
             }
             e.color(DEFAULT_COLOR);
-
-            let mut lines = Vec::new();
-            for (i, planter) in leaderboard.iter().enumerate() {
-                lines.push(eformat!(
-                   "{rank} **{percent}%** ({total}) - <@!{user_id}>
-                    > Favorite tree: {fav_species}{fav_extra}",
-                    rank = match i {
-                        0 => ":first_place:".to_owned(),
-                        1 => ":second_place:".to_owned(),
-                        2 => ":third_place:".to_owned(),
-                        _ => format!("#{}", i + 1)
-                    },
-                    percent = planter.get::<f64, _>("percent"), 
-                    total = planter.get::<i64, _>("total"),
-                    user_id = planter.get::<i64, _>("user_id"),
-                    fav_species = if let Some(fav_species) = server.species.get(&planter.get::<i16, _>("fav_species")) {
-                        fav_species.emoji.clone()
-                    } else {
-                        "*unknown*".to_owned()
-                    },
-                    fav_extra = if guild_wide {
-                        format!(" | Favorite forest: <#{}>", planter.get::<i64, _>("fav_channel"))
-                    } else {
-                        format!("")
-                    }
-                ));
+            e.image("attachment://forest.png");
+            if total_trees > render::MAX_TREES {
+                e.footer(|f| f.text(tr!(lang => "forest.view.scaled", count = total_trees, drawn = render::MAX_TREES)));
+            } else {
+                e.footer(|f| f.text(tr!(lang => "stats.trees", count = total_trees)));
             }
-            e.field(eformat!("/:ranger/ Best tree planters",), lines.join("\n"), true);
             e
-        })
-    }).await;
-
+        });
+        m.add_file(AttachmentType::Bytes { data: image.into(), filename: "forest.png".to_owned() })
+    }).await?;
     Ok(())
 }
@@ -0,0 +1,282 @@
+use std::time::Duration;
+
+use futures::stream::StreamExt;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        macros::command,
+        CommandResult,
+    },
+    builder::CreateEmbed,
+    utils::Colour
+};
+
+use crate::prelude::*;
+use crate::error::Error;
+use crate::game::{self, Offer, Refusal};
+
+/// Seconds a trade stays open
+const TRADE_TIMEOUT: u64 = 300;
+
+lazy_static! {
+    /// Change of an offer, e.g. `+3 🌲 pallets`, `-10 🌴` or `+50 coins`
+    static ref OFFER_CHANGE_REGEX: Regex = Regex::new(r"^([+-]) *(\d{1,6}) +(\S+)(?: +(\w+))?$").unwrap();
+}
+
+#[command("trade")]
+#[aliases("exchange")]
+async fn cmd_trade(ctx: &Context, message: &Message) -> CommandResult {
+    quick_init!(ctx -> data; message => s:server);
+    let lang = server.lang.clone();
+    std::mem::drop(data);
+    let partner = match message.mentions.first() {
+        Some(user) if !user.bot && user.id != message.author.id => user.clone(),
+        _ => {
+            error!(ctx, message.channel_id, lang => "trade.usage");
+            return Ok(());
+        }
+    };
+    crate::cache_user(ctx, message, partner.id.0).await?;
+
+    let users = [message.author.clone(), partner];
+    let participants = [users[0].id, users[1].id];
+    let mut offers = [Offer::default(), Offer::default()];
+    let mut confirmed = [false, false];
+
+    let empty = tr!(lang => "trade.empty_offer");
+    let mut msg = message.channel_id.send_message(&ctx.http, |m|
+        m.embed(|e| trade_embed(e, &lang, &TradeStatus::Pending, &users, &[empty.clone(), empty.clone()], &confirmed))
+    ).await?;
+    msg.react(&ctx.http, ReactionType::Unicode("✅".to_string())).await?;
+    msg.react(&ctx.http, ReactionType::Unicode("❌".to_string())).await?;
+
+    let mut replies = message.channel_id.await_replies(&ctx)
+        .filter(move |reply| participants.contains(&reply.author.id) && OFFER_CHANGE_REGEX.is_match(reply.content.trim()))
+        .await;
+    let mut reactions = msg.await_reactions(&ctx)
+        .added(true)
+        .removed(true)
+        .filter(move |reaction| participants.contains(&reaction.user_id) && match &reaction.emoji {
+            ReactionType::Unicode(emoji) => emoji == "✅" || emoji == "❌",
+            _ => false
+        })
+        .await;
+    let deadline = tokio::time::delay_for(Duration::from_secs(TRADE_TIMEOUT));
+    tokio::pin!(deadline);
+
+    let status = loop {
+        tokio::select! {
+            _ = &mut deadline => break TradeStatus::TimedOut,
+            Some(reply) = replies.next() => {
+                let side = (reply.author.id != participants[0]) as usize;
+                quick_init!(ctx -> data; message => s:server);
+                let mut offer = offers[side].clone();
+                match parse_change(server, reply.content.trim()) {
+                    Some((Some(item), qty)) => offer.add_items(item, qty),
+                    Some((None, coins)) => offer.add_coins(coins),
+                    None => {
+                        error!(ctx, message.channel_id, lang => "trade.invalid_change");
+                        continue;
+                    }
+                }
+                let player = server.player_cache.get(&participants[side].0).ok_or(Error::Missing("player"))?;
+                if let Err(refusal) = game::check_offer(server, player, &offer) {
+                    let reason = refusal_reason(server, &refusal);
+                    std::mem::drop(data);
+                    error!(ctx, message.channel_id, lang => "trade.change_refused", reason = reason);
+                    continue;
+                }
+                offers[side] = offer;
+                // a confirmation only holds for the offers it has been given to
+                confirmed = [false, false];
+                let descriptions = [describe_offer(server, &offers[0]), describe_offer(server, &offers[1])];
+                std::mem::drop(data);
+                msg.edit(&ctx.http, |m|
+                    m.embed(|e| trade_embed(e, &lang, &TradeStatus::Pending, &users, &descriptions, &confirmed))
+                ).await?;
+            }
+            Some(reaction_action) = reactions.next() => {
+                let reaction = reaction_action.as_inner_ref();
+                let side = (reaction.user_id != participants[0]) as usize;
+                if let ReactionType::Unicode(emoji) = &reaction.emoji {
+                    if emoji == "❌" {
+                        if reaction_action.is_added() {
+                            break TradeStatus::Cancelled;
+                        }
+                        continue;
+                    }
+                }
+                confirmed[side] = reaction_action.is_added();
+                if confirmed == [true, true] && !(offers[0].is_empty() && offers[1].is_empty()) {
+                    break TradeStatus::Confirmed;
+                }
+                quick_init!(ctx -> data; message => s:server);
+                let descriptions = [describe_offer(server, &offers[0]), describe_offer(server, &offers[1])];
+                std::mem::drop(data);
+                msg.edit(&ctx.http, |m|
+                    m.embed(|e| trade_embed(e, &lang, &TradeStatus::Pending, &users, &descriptions, &confirmed))
+                ).await?;
+            }
+        }
+    };
+    let status = match status {
+        TradeStatus::Confirmed => execute_trade(ctx, message, participants, &offers).await?,
+        status => status,
+    };
+
+    quick_init!(ctx -> data; message => s:server);
+    let descriptions = [describe_offer(server, &offers[0]), describe_offer(server, &offers[1])];
+    std::mem::drop(data);
+    msg.edit(&ctx.http, |m|
+        m.embed(|e| trade_embed(e, &lang, &status, &users, &descriptions, &confirmed))
+    ).await?;
+    Ok(())
+}
+
+/// Swap the offers of a confirmed trade, checked again against the cached players
+async fn execute_trade(ctx: &Context, message: &Message, participants: [UserId; 2], offers: &[Offer; 2]) -> crate::error::Result<TradeStatus> {
+    quick_init!(ctx -> data => st:store; message => s:server);
+    let first = server.player_cache.get(&participants[0].0).ok_or(Error::Missing("player"))?;
+    let second = server.player_cache.get(&participants[1].0).ok_or(Error::Missing("player"))?;
+    let (first_effects, second_effects) = match game::trade(server, (first, &offers[0]), (second, &offers[1])) {
+        Ok(effects) => effects,
+        Err((user_id, refusal)) => return Ok(TradeStatus::Refused(tr!(
+            server.lang => "trade.refused", user_id = user_id, reason = refusal_reason(server, &refusal)
+        ))),
+    };
+
+    let result = store.save_trade(
+        server.id as u64,
+        (first.user_id, first.id, &first_effects),
+        (second.user_id, second.id, &second_effects),
+    ).await;
+    match result {
+        Ok(()) => {}
+        // one of the players spent their coins or items in the meantime
+        Err(Error::OutOfDate) => return Ok(TradeStatus::Refused(tr!(server.lang => "trade.refusal.out_of_date"))),
+        Err(why) => return Err(why),
+    }
+
+    quick_init!(ctx -> mut data ~data~; message => s:server);
+    for (user_id, effects) in [(participants[0], &first_effects), (participants[1], &second_effects)].iter() {
+        if let Some(player) = server.player_cache.1.get_mut(&user_id.0) {
+            game::apply(player, effects);
+        }
+    }
+    Ok(TradeStatus::Confirmed)
+}
+
+/// Parse a change of offer into the item (None for coins) and the quantity to add, negative to remove
+fn parse_change(server: &Server, content: &str) -> Option<(Option<(ItemType, i16)>, i32)> {
+    let captures = OFFER_CHANGE_REGEX.captures(content)?;
+    let qty = captures[2].parse::<i32>().ok()?;
+    let qty = if &captures[1] == "-" { -qty } else { qty };
+    let item = parse_item(server, &captures[3], captures.get(4).map(|kind| kind.as_str()))?;
+    Some((item, qty))
+}
+
+/// Parse coins (Some(None)) or an item from a species and an optional kind, seedlings by default
+pub(super) fn parse_item(server: &Server, thing: &str, kind: Option<&str>) -> Option<Option<(ItemType, i16)>> {
+    if let "coin" | "coins" = thing.to_lowercase().as_str() {
+        return Some(None);
+    }
+
+    let species = server.find_species(thing)?;
+    let item_type = match kind.map(|kind| kind.to_lowercase()).as_deref() {
+        None | Some("seedling" | "seedlings") => ItemType::Seedling,
+        Some("pallet" | "pallets") => ItemType::Pallet,
+        Some(_) => return None,
+    };
+    Some(Some((item_type, species.id)))
+}
+
+pub(super) fn item_label(server: &Server, item_type: ItemType, item_id: i16) -> String {
+    let emoji = server.species.get(&item_id)
+        .map(|species| species.emoji.to_string())
+        .unwrap_or_else(|| "?".to_owned());
+    match item_type {
+        ItemType::Pallet => eformat!("/:pallet/{}", emoji),
+        ItemType::Seedling => format!(":seedling:{}", emoji),
+    }
+}
+
+/// One line per offered item or coins
+fn describe_offer(server: &Server, offer: &Offer) -> String {
+    let mut lines = offer.items.iter()
+        .map(|((item_type, item_id), qty)| format!("**{}** {}", qty, item_label(server, *item_type, *item_id)))
+        .collect::<Vec<String>>();
+    if offer.coins > 0 {
+        lines.push(eformat!("**{}** /:coin/", offer.coins));
+    }
+    if lines.is_empty() {
+        return tr!(server.lang => "trade.empty_offer");
+    }
+    lines.join("\n")
+}
+
+/// Explain why an action has been refused, be it an offer, a trade, a gift or a purchase
+pub(super) fn refusal_reason(server: &Server, refusal: &Refusal) -> String {
+    match refusal {
+        Refusal::NotEnoughCoins { coins, .. } => tr!(server.lang => "trade.refusal.not_enough_coins", count = *coins),
+        Refusal::NotEnoughItems { item_type, item_id } => tr!(
+            server.lang => "trade.refusal.not_enough_items", item = item_label(server, *item_type, *item_id)
+        ),
+        Refusal::StorageFull(full) => full.describe(&server.lang),
+        Refusal::InfiniteItems => tr!(server.lang => "trade.refusal.infinite"),
+        Refusal::AlreadyUpgraded => tr!(server.lang => "trade.refusal.already_upgraded"),
+        Refusal::ForestForbidden | Refusal::UnknownSpecies | Refusal::AlreadyClaimed | Refusal::NoDailyReward
+        | Refusal::InvalidQuantity | Refusal::NotGrowable | Refusal::NoFreeSlot | Refusal::NothingToCollect
+        | Refusal::TooManyWorkers | Refusal::UnknownWorker => {
+            tr!(server.lang => "trade.refusal.unavailable")
+        }
+    }
+}
+
+/// State of a trade, shown in its embed title
+enum TradeStatus {
+    Pending,
+    Confirmed,
+    Cancelled,
+    /// Cancelled on confirmation, with the reason
+    Refused(String),
+    TimedOut,
+}
+
+impl TradeStatus {
+    fn display(&self, lang: &str) -> String {
+        match self {
+            TradeStatus::Pending => tr!(lang => "trade.status.pending"),
+            TradeStatus::Confirmed => tr!(lang => "trade.status.confirmed"),
+            TradeStatus::Cancelled => tr!(lang => "trade.status.cancelled"),
+            TradeStatus::Refused(_) => tr!(lang => "trade.status.refused"),
+            TradeStatus::TimedOut => tr!(lang => "trade.status.timed_out"),
+        }
+    }
+
+    fn color(&self) -> Colour {
+        match self {
+            TradeStatus::Pending => Colour::new(0x303F9F),
+            TradeStatus::Confirmed => Colour::new(0x03A9F4),
+            _ => Colour::new(0xFFA000),
+        }
+    }
+}
+
+fn trade_embed<'a>(
+    e: &'a mut CreateEmbed, lang: &str, status: &TradeStatus, users: &[User; 2], offers: &[String; 2], confirmed: &[bool; 2]
+) -> &'a mut CreateEmbed {
+    e.title(tr!(lang => "trade.title", status = status.display(lang)));
+    e.color(status.color());
+    if let TradeStatus::Pending = status {
+        e.description(tr!(lang => "trade.instructions", count = TRADE_TIMEOUT / 60));
+    } else if let TradeStatus::Refused(reason) = status {
+        e.description(reason);
+    }
+    for ((user, offer), confirmed) in users.iter().zip(offers.iter()).zip(confirmed.iter()) {
+        e.field(format!("{} {}", if *confirmed { "✅" } else { "⌛" }, user.tag()), offer, true);
+    }
+    e
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        macros::command,
+        CommandResult,
+    },
+    builder::CreateEmbed,
+};
+
+use crate::prelude::*;
+use super::gifts::describe_age;
+use super::trade::item_label;
+
+/// Ledger entries per page of the wallet history
+const WALLET_PAGE_SIZE: usize = 10;
+
+/// Mismatching players listed by the `reconcile` command
+const RECONCILE_SHOWN: usize = 20;
+
+#[command("wallet")]
+#[aliases("history")]
+async fn cmd_wallet(ctx: &Context, message: &Message) -> CommandResult {
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let lang = server.lang.clone();
+    let (player_id, coins) = (player.id, player.coins);
+    std::mem::drop(data);
+
+    let mut page = 0;
+    let (entries, total) = store.ledger(player_id, 0, WALLET_PAGE_SIZE).await?;
+    let pages = (total + WALLET_PAGE_SIZE - 1) / WALLET_PAGE_SIZE;
+    let lines = describe_entries(ctx, message, &entries).await?;
+    let mut msg = message.channel_id.send_message(&ctx.http, |m| m.embed(|e| {
+        wallet_embed(e, &lang, &message.author, coins, &lines, page, pages)
+    })).await?;
+
+    if pages <= 1 {
+        return Ok(());
+    }
+    let _ = msg.react(&ctx.http, ReactionType::Unicode("◀️".to_string())).await?;
+    let _ = msg.react(&ctx.http, ReactionType::Unicode("▶️".to_string())).await?;
+
+    // same as the leaderboard, both adding and removing a reaction turns the page
+    while let Some(reaction_action) = msg.await_reaction(&ctx)
+        .author_id(message.author.id)
+        .added(true)
+        .removed(true)
+        .filter(|r| match &r.emoji {
+            ReactionType::Unicode(emoji) => emoji == "◀️" || emoji == "▶️",
+            _ => false
+        })
+        .timeout(Duration::from_secs(60))
+        .await {
+        page = match &reaction_action.as_inner_ref().emoji {
+            ReactionType::Unicode(emoji) if emoji == "◀️" => (page + pages - 1) % pages,
+            _ => (page + 1) % pages,
+        };
+        let (entries, _) = store.ledger(player_id, page * WALLET_PAGE_SIZE, WALLET_PAGE_SIZE).await?;
+        let lines = describe_entries(ctx, message, &entries).await?;
+        msg.edit(&ctx.http, |m| m.embed(|e| {
+            wallet_embed(e, &lang, &message.author, coins, &lines, page, pages)
+        })).await?;
+    }
+    Ok(())
+}
+
+#[command("reconcile")]
+async fn cmd_reconcile(ctx: &Context, message: &Message) -> CommandResult {
+    let permissions = message.member(&ctx.cache).await?.permissions(&ctx.cache).await?;
+    quick_init!(ctx -> data => st:store; message => s:server);
+    let lang = server.lang.clone();
+    if !permissions.manage_guild() && message.author.id.0 != 345259637513256960 {
+        error!(ctx, message.channel_id, lang => "settings.insufficient_permissions");
+        return Ok(());
+    }
+    let guild_id = server.id as u64;
+    std::mem::drop(data);
+
+    let mismatches = store.reconcile(guild_id).await?;
+    if mismatches.is_empty() {
+        success!(ctx, message.channel_id, lang => "reconcile.balanced");
+        return Ok(());
+    }
+
+    let mut lines = mismatches.iter()
+        .take(RECONCILE_SHOWN)
+        .map(|(user_id, coins, sum)| eformat!("<@!{}>: **{}** /:coin/ ≠ **{}** /:coin/", user_id, coins, sum))
+        .collect::<Vec<String>>();
+    if mismatches.len() > RECONCILE_SHOWN {
+        lines.push(tr!(lang => "reconcile.more", count = mismatches.len() - RECONCILE_SHOWN));
+    }
+    message.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e: &mut CreateEmbed| e
+            .title(tr!(lang => "reconcile.title"))
+            .color(DEFAULT_COLOR)
+            .description(format!(
+                "{}\n\n{}",
+                tr!(lang => "reconcile.mismatches", count = mismatches.len()),
+                lines.join("\n")
+            ))
+        )
+    }).await?;
+    Ok(())
+}
+
+/// One line per ledger entry, with the item, the delta, the reason and the age
+async fn describe_entries(ctx: &Context, message: &Message, entries: &[LedgerEntry]) -> crate::error::Result<Vec<String>> {
+    quick_init!(ctx -> data; message => s:server);
+    Ok(entries.iter()
+        .map(|entry| {
+            let thing = match entry.item {
+                Some((item_type, item_id)) => item_label(server, item_type, item_id),
+                None => eformat!("/:coin/",),
+            };
+            tr!(
+                server.lang => "wallet.entry",
+                delta = format!("{:+}", entry.delta), thing = thing,
+                reason = tr!(server.lang => &format!("wallet.reason.{}", entry.reason.as_str())),
+                age = describe_age(&server.lang, entry.created_at),
+            )
+        })
+        .collect())
+}
+
+fn wallet_embed<'a>(
+    e: &'a mut CreateEmbed, lang: &str, user: &User, coins: i32, lines: &[String], page: usize, pages: usize
+) -> &'a mut CreateEmbed {
+    e.title(tr!(lang => "wallet.title", user = user.name));
+    e.color(DEFAULT_COLOR);
+    let mut description = tr!(lang => "wallet.balance", count = coins);
+    description.push_str("\n\n");
+    if lines.is_empty() {
+        description.push_str(&tr!(lang => "wallet.empty"));
+    } else {
+        description.push_str(&lines.join("\n"));
+    }
+    e.description(description);
+    if pages > 1 {
+        e.footer(|f| f.text(tr!(lang => "wallet.page", page = page + 1, pages = pages)));
+    }
+    e
+}
@@ -0,0 +1,181 @@
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        macros::command,
+        CommandResult, Args
+    },
+    builder::CreateEmbed,
+};
+
+use crate::prelude::*;
+use crate::error::Error;
+use crate::game::{self, Action, Refusal};
+use super::parse_channel_mention;
+
+#[command("workers")]
+#[aliases("worker")]
+async fn cmd_workers(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    crate::time_check::run(ctx, message).await?;
+
+    quick_init!(ctx -> data => st:store; message => s:server [player]);
+    let lang = server.lang.clone();
+    match args.current() {
+        Some("hire") => {
+            let cost = player.worker_cost();
+            let worker_id = store.next_worker_id().await?;
+            let effects = match game::play(server, player, &Action::HireWorker { worker_id }) {
+                Ok(effects) => effects,
+                Err(Refusal::TooManyWorkers) => {
+                    error!(ctx, message.channel_id, lang => "workers.hire.limit", count = server.max_workers);
+                    return Ok(());
+                }
+                Err(_) => {
+                    error!(ctx, message.channel_id, lang => "workers.hire.too_expensive", cost = cost, coins = player.coins);
+                    return Ok(());
+                }
+            };
+
+            let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+            match store.save_effects(guild_id, user_id, player_id, &effects).await {
+                Ok(()) => {}
+                // coins have been spent in the meantime
+                Err(Error::OutOfDate) => {
+                    error!(ctx, message.channel_id, lang => "workers.hire.not_enough_coins");
+                    return Ok(());
+                }
+                Err(why) => return Err(why.into()),
+            }
+
+            quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+            game::apply(player, &effects);
+
+            success!(ctx, message.channel_id, lang => "workers.hire.done", worker = player.workers.len(), cost = cost);
+        }
+        Some("assign") => {
+            args.advance();
+            let index = args.current().and_then(|n| n.trim_start_matches('#').parse::<usize>().ok());
+            let worker_id = match index.and_then(|n| player.workers.get(n.wrapping_sub(1))) {
+                Some(worker) => worker.id,
+                None => {
+                    error!(ctx, message.channel_id, lang => "workers.assign.usage");
+                    return Ok(());
+                }
+            };
+
+            args.advance();
+            let mut channel_id = None;
+            if let Some(thing) = args.current() {
+                if let Some(id) = parse_channel_mention(thing) {
+                    if let Ok(channels) = message.guild_id.unwrap().channels(&ctx.http).await {
+                        if channels.contains_key(&ChannelId::from(id)) {
+                            channel_id = Some(id);
+                        }
+                    }
+                }
+            }
+            let channel_id = match channel_id {
+                Some(channel_id) => channel_id,
+                None => {
+                    error!(ctx, message.channel_id, lang => "workers.assign.invalid_channel");
+                    return Ok(());
+                }
+            };
+            let effects = match game::play(server, player, &Action::AssignWorker { worker_id, channel_id }) {
+                Ok(effects) => effects,
+                Err(Refusal::ForestForbidden) => {
+                    error!(ctx, message.channel_id, lang => "workers.assign.forbidden_channel");
+                    return Ok(());
+                }
+                Err(_) => {
+                    error!(ctx, message.channel_id, lang => "workers.assign.usage");
+                    return Ok(());
+                }
+            };
+
+            let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+            match store.save_effects(guild_id, user_id, player_id, &effects).await {
+                Ok(()) => {}
+                Err(Error::OutOfDate) => {
+                    error!(ctx, message.channel_id, lang => "workers.out_of_date");
+                    return Ok(());
+                }
+                Err(why) => return Err(why.into()),
+            }
+
+            quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+            game::apply(player, &effects);
+
+            success!(ctx, message.channel_id, lang => "workers.assign.done", worker = index.unwrap(), channel = channel_id);
+        }
+        Some("fire") => {
+            args.advance();
+            let index = args.current().and_then(|n| n.trim_start_matches('#').parse::<usize>().ok());
+            let worker_id = match index.and_then(|n| player.workers.get(n.wrapping_sub(1))) {
+                Some(worker) => worker.id,
+                None => {
+                    error!(ctx, message.channel_id, lang => "workers.fire.usage");
+                    return Ok(());
+                }
+            };
+            let effects = match game::play(server, player, &Action::FireWorker { worker_id }) {
+                Ok(effects) => effects,
+                Err(_) => {
+                    error!(ctx, message.channel_id, lang => "workers.fire.usage");
+                    return Ok(());
+                }
+            };
+
+            let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
+            match store.save_effects(guild_id, user_id, player_id, &effects).await {
+                Ok(()) => {}
+                Err(Error::OutOfDate) => {
+                    error!(ctx, message.channel_id, lang => "workers.out_of_date");
+                    return Ok(());
+                }
+                Err(why) => return Err(why.into()),
+            }
+
+            quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+            game::apply(player, &effects);
+
+            success!(ctx, message.channel_id, lang => "workers.fire.done", worker = index.unwrap());
+        }
+        Some(_) => {
+            error!(ctx, message.channel_id, lang => "workers.invalid_argument");
+        }
+        None => {
+            let _ = message.channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e: &mut CreateEmbed| {
+                    e.title(tr!(lang => "workers.view.title"));
+                    e.color(DEFAULT_COLOR);
+
+                    e.description(tr!(
+                        lang => "workers.view.description",
+                        count = player.workers.len(), max = server.max_workers,
+                        interval = WORKER_PLANT_INTERVAL / 60, hours = WORKER_MAX_CATCH_UP / 3600,
+                        hire = if player.workers.len() < server.max_workers.max(0) as usize {
+                            tr!(lang => "workers.view.hire", cost = player.worker_cost())
+                        } else {
+                            "".to_owned()
+                        },
+                    ));
+
+                    if !player.workers.is_empty() {
+                        e.field(tr!(lang => "workers.view.workers"), player.workers.iter().enumerate()
+                            .map(|(i, worker)| match worker.channel_id {
+                                Some(channel_id) => tr!(lang => "workers.view.assigned", worker = i + 1, channel = channel_id),
+                                None => tr!(lang => "workers.view.unassigned", worker = i + 1),
+                            })
+                            .collect::<Vec<String>>()
+                            .join("\n"), false);
+                    }
+                    e
+                });
+                m
+            }).await?;
+        }
+    };
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serenity::async_trait;
+use sqlx::postgres::PgPool;
+
+/// Identifies a running cooldown: (guild_id, user_id, channel_id, species)
+/// where the channel and species are the scope of the applied cooldown rule (0 for any)
+pub type CooldownKey = (u64, u64, u64, i16);
+
+/// Storage of planting cooldowns
+///
+/// Bot processes sharing the same store also share cooldowns,
+/// implementations must make `try_start` atomic for that matter
+#[async_trait]
+pub trait CooldownStore: Send + Sync {
+    /// Start a cooldown unless one is already running for the same key
+    /// Returns false if the key is still on cooldown
+    async fn try_start(&self, key: CooldownKey, cooldown: Duration) -> Result<bool, sqlx::Error>;
+
+    /// Evict expired cooldowns, returns how many were evicted
+    async fn sweep(&self) -> Result<u64, sqlx::Error>;
+}
+
+/// In-process cooldowns, lost on restart and not shared with other processes
+#[derive(Default)]
+pub struct LocalCooldowns(Mutex<HashMap<CooldownKey, Instant>>);
+
+#[async_trait]
+impl CooldownStore for LocalCooldowns {
+    async fn try_start(&self, key: CooldownKey, cooldown: Duration) -> Result<bool, sqlx::Error> {
+        let mut cooldowns = self.0.lock().unwrap();
+        let now = Instant::now();
+        if cooldowns.get(&key).map_or(false, |expires_at| *expires_at > now) {
+            return Ok(false);
+        }
+        cooldowns.insert(key, now + cooldown);
+        Ok(true)
+    }
+
+    async fn sweep(&self) -> Result<u64, sqlx::Error> {
+        let mut cooldowns = self.0.lock().unwrap();
+        let (now, before) = (Instant::now(), cooldowns.len());
+        cooldowns.retain(|_, expires_at| *expires_at > now);
+        Ok((before - cooldowns.len()) as u64)
+    }
+}
+
+/// Cooldowns persisted in the database, surviving restarts and shared across processes
+pub struct DatabaseCooldowns(pub PgPool);
+
+#[async_trait]
+impl CooldownStore for DatabaseCooldowns {
+    async fn try_start(&self, key: CooldownKey, cooldown: Duration) -> Result<bool, sqlx::Error> {
+        let (guild_id, user_id, channel_id, species) = key;
+        // the upsert only goes through if there is no running cooldown, which makes it atomic
+        let started = sqlx::query!("
+            INSERT INTO plant_cooldowns (guild_id, user_id, channel_id, species, expires_at)
+            VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP + make_interval(secs => $5))
+            ON CONFLICT (guild_id, user_id, channel_id, species) DO UPDATE SET expires_at = EXCLUDED.expires_at
+            WHERE plant_cooldowns.expires_at <= CURRENT_TIMESTAMP
+            RETURNING 1 AS started",
+            guild_id as i64, user_id as i64, channel_id as i64, species, cooldown.as_secs_f64()
+        ).fetch_optional(&self.0).await?;
+        Ok(started.is_some())
+    }
+
+    async fn sweep(&self) -> Result<u64, sqlx::Error> {
+        let done = sqlx::query!("DELETE FROM plant_cooldowns WHERE expires_at <= CURRENT_TIMESTAMP")
+            .execute(&self.0).await?;
+        Ok(done.rows_affected())
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use serenity::Error as SerenityError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Anything that can go wrong while handling a message
+#[derive(Debug)]
+pub enum Error {
+    /// Query or connection failure
+    Database(sqlx::Error),
+    /// Discord API, gateway or cache failure
+    Discord(SerenityError),
+    /// Image decoding or encoding failure
+    Image(image::ImageError),
+    /// A server or player is neither cached nor stored
+    Missing(&'static str),
+    /// Stored data doesn't match the models, e.g. an unknown item type
+    Corrupted(String),
+    /// Effects computed from the cache can't be applied to the stored data, the cache is out of date
+    OutOfDate,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database(why) => write!(f, "database error: {}", why),
+            Error::Discord(why) => write!(f, "discord error: {}", why),
+            Error::Image(why) => write!(f, "image error: {}", why),
+            Error::Missing(what) => write!(f, "missing {}", what),
+            Error::Corrupted(what) => write!(f, "corrupted data: {}", what),
+            Error::OutOfDate => write!(f, "cache out of date"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(why) => Some(why),
+            Error::Discord(why) => Some(why),
+            Error::Image(why) => Some(why),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(why: sqlx::Error) -> Error {
+        Error::Database(why)
+    }
+}
+
+impl From<SerenityError> for Error {
+    fn from(why: SerenityError) -> Error {
+        Error::Discord(why)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(why: image::ImageError) -> Error {
+        Error::Image(why)
+    }
+}
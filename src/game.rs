@@ -0,0 +1,986 @@
+//! Game rules, independent from Discord and the database.
+//! Actions are evaluated against the cached server and player and give effects,
+//! handlers persist these effects and then apply them to the cache with `apply`.
+
+use std::time::Duration;
+
+use crate::cooldown::CooldownKey;
+use crate::models::*;
+
+/// Something a player does
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Plant a tree of a species in a channel
+    Plant { channel_id: u64, species_id: i16 },
+    /// Receive the default seedlings of every enabled species, given to new players
+    GrantDefaultSeedlings,
+    /// Buy an item from the shop
+    Buy(ShopItem),
+    /// Claim the daily reward, on a day counted since the epoch (see `day`)
+    ClaimDaily { today: u64 },
+    /// Unpack pallets of a species into seedlings, keeping the empty pallets
+    Unpack { species_id: i16, amount: i32 },
+    /// Fill a greenhouse slot (the first empty one if none is given) with pallets, or with seedlings when sowing
+    FillGreenhouse { slot_id: Option<i16>, species_id: i16, item_type: ItemType, qty: i32 },
+    /// Collect the seedlings grown in the greenhouse, as many as the shed can hold
+    CollectGreenhouse,
+    /// Upgrade the greenhouse to its next tier
+    UpgradeGreenhouse,
+    /// Hire a worker, with an id allocated beforehand (see `Store::next_worker_id`)
+    HireWorker { worker_id: i32 },
+    /// Assign a worker to plant trees in a channel
+    AssignWorker { worker_id: i32, channel_id: u64 },
+    FireWorker { worker_id: i32 },
+}
+
+/// Anything that can be bought in the shop
+#[derive(Debug, Clone)]
+pub enum ShopItem {
+    /// Pallets of a species, with the quantity bought at once
+    Pallet(Species, i32),
+    /// Upgrade to the given shed tier (index in `SHED_TIERS`)
+    ShedUpgrade(usize),
+}
+
+impl ShopItem {
+    /// Total cost, saturating so that huge quantities are simply unaffordable
+    pub fn cost(&self) -> i32 {
+        match self {
+            ShopItem::Pallet(species, qty) => species.pallet_cost.saturating_mul(*qty),
+            ShopItem::ShedUpgrade(tier) => SHED_TIERS[*tier].cost,
+        }
+    }
+}
+
+/// Change of a storage entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageDelta {
+    /// Add (or remove with a negative quantity) items, infinite amounts stay infinite
+    Add { item_type: ItemType, item_id: i16, qty: i32 },
+    /// Make the amount infinite
+    Infinite { item_type: ItemType, item_id: i16 },
+    /// Remove finite items, infinite amounts can't be taken from
+    Take { item_type: ItemType, item_id: i16, qty: i32 },
+}
+
+/// Change of a greenhouse slot
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenhouseDelta {
+    /// Start growing seedlings in an empty slot
+    Fill { slot_id: i16, species_id: i16, remaining: i32 },
+    /// Take grown seedlings from a slot, which is emptied once it is done (see `GreenhouseSlot::is_done`)
+    Collect { slot_id: i16, qty: i32 },
+}
+
+/// Change of the workers of a player
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkerDelta {
+    Hire { worker_id: i32 },
+    Assign { worker_id: i32, channel_id: u64 },
+    Fire { worker_id: i32 },
+}
+
+/// Reaction to add to the message which triggered the action
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reaction {
+    Planted,
+    MissingSeedling,
+}
+
+/// Outcome of an accepted action
+#[derive(Debug, PartialEq)]
+pub struct Effects {
+    pub storage: Vec<StorageDelta>,
+    /// Coins to add to the wallet, negative when spent
+    pub coins: i32,
+    /// New shed tier
+    pub storage_upgrade: Option<i16>,
+    pub greenhouse: Vec<GreenhouseDelta>,
+    /// New greenhouse tier
+    pub greenhouse_upgrade: Option<i16>,
+    pub workers: Vec<WorkerDelta>,
+    /// Planted trees, as (channel_id, species, count)
+    pub trees: Vec<(u64, i16, i32)>,
+    /// Cooldown to start first, other effects must be dropped if it is already running
+    pub cooldown: Option<(CooldownKey, Duration)>,
+    pub reaction: Option<Reaction>,
+    /// Daily claim, as (day, new streak)
+    pub daily: Option<(u64, i32)>,
+    /// Recorded in the ledger along with every change of coins and items
+    pub reason: Reason,
+}
+
+impl Effects {
+    /// No change yet, every change being recorded in the ledger with the given reason
+    pub fn new(reason: Reason) -> Effects {
+        Effects {
+            storage: Vec::new(),
+            coins: 0,
+            storage_upgrade: None,
+            greenhouse: Vec::new(),
+            greenhouse_upgrade: None,
+            workers: Vec::new(),
+            trees: Vec::new(),
+            cooldown: None,
+            reaction: None,
+            daily: None,
+            reason,
+        }
+    }
+
+    /// Whether there is nothing to persist
+    pub fn is_noop(&self) -> bool {
+        self.storage.is_empty() && self.coins == 0 && self.storage_upgrade.is_none() && self.greenhouse.is_empty()
+            && self.greenhouse_upgrade.is_none() && self.workers.is_empty() && self.trees.is_empty() && self.daily.is_none()
+    }
+}
+
+/// Reason why an action is refused
+#[derive(Debug)]
+pub enum Refusal {
+    /// The forest can't grow in the channel
+    ForestForbidden,
+    /// The species doesn't exist or is disabled
+    UnknownSpecies,
+    NotEnoughCoins { cost: i32, coins: i32 },
+    StorageFull(StorageFull),
+    AlreadyUpgraded,
+    NotEnoughItems { item_type: ItemType, item_id: i16 },
+    /// Infinite items can't be given away, they would be duplicated
+    InfiniteItems,
+    /// The daily reward has already been claimed today
+    AlreadyClaimed,
+    /// The server has no daily reward
+    NoDailyReward,
+    /// The quantity is not positive, or too large to be handled
+    InvalidQuantity,
+    /// The species can't be grown in a greenhouse
+    NotGrowable,
+    /// The greenhouse slot doesn't exist or is already filled, or no slot is empty
+    NoFreeSlot,
+    /// No grown seedling to collect, or no room for them in the shed
+    NothingToCollect,
+    /// The player already has as many workers as the server allows
+    TooManyWorkers,
+    UnknownWorker,
+}
+
+/// Evaluate an action of a player, nothing is changed until the effects are applied
+pub fn play(server: &Server, player: &Player, action: &Action) -> Result<Effects, Refusal> {
+    match action {
+        Action::Plant { channel_id, species_id } => plant(server, player, *channel_id, *species_id),
+        Action::GrantDefaultSeedlings => Ok(grant_default_seedlings(server, player)),
+        Action::Buy(item) => buy(player, item),
+        Action::ClaimDaily { today } => claim_daily(server, player, *today),
+        Action::Unpack { species_id, amount } => unpack(server, player, *species_id, *amount),
+        Action::FillGreenhouse { slot_id, species_id, item_type, qty } => {
+            fill_greenhouse(server, player, *slot_id, *species_id, *item_type, *qty)
+        }
+        Action::CollectGreenhouse => collect_greenhouse(player),
+        Action::UpgradeGreenhouse => upgrade_greenhouse(player),
+        Action::HireWorker { worker_id } => hire_worker(server, player, *worker_id),
+        Action::AssignWorker { worker_id, channel_id } => assign_worker(server, player, *worker_id, *channel_id),
+        Action::FireWorker { worker_id } => fire_worker(player, *worker_id),
+    }
+}
+
+fn plant(server: &Server, player: &Player, channel_id: u64, species_id: i16) -> Result<Effects, Refusal> {
+    if !server.forest_rules.check(&channel_id) {
+        return Err(Refusal::ForestForbidden);
+    }
+    let species = match server.species.get(&species_id) {
+        Some(species) if species.enabled => species,
+        _ => return Err(Refusal::UnknownSpecies),
+    };
+
+    let mut effects = Effects::new(Reason::Plant);
+    let cooldown = server.cooldown_for(channel_id, species_id);
+    if cooldown.seconds > 0 {
+        let key = (player.guild_id, player.user_id, cooldown.channel_scope, cooldown.species_scope);
+        effects.cooldown = Some((key, Duration::from_secs(cooldown.seconds as u64)));
+    }
+
+    match player.storage.get(&(ItemType::Seedling, species_id)) {
+        Some(amount) if *amount == -1 || *amount > 0 => {
+            effects.storage.push(StorageDelta::Add { item_type: ItemType::Seedling, item_id: species_id, qty: -1 });
+            effects.coins = species.coins;
+            effects.trees.push((channel_id, species_id, 1));
+            effects.reaction = Some(Reaction::Planted);
+        }
+        _ => effects.reaction = Some(Reaction::MissingSeedling),
+    }
+    Ok(effects)
+}
+
+fn grant_default_seedlings(server: &Server, player: &Player) -> Effects {
+    let mut species = server.species.values()
+        .filter(|species| species.enabled)
+        .collect::<Vec<&Species>>();
+    species.sort_by_key(|species| species.id);
+
+    let mut effects = Effects::new(Reason::Starter);
+    // default seedlings are capped to what the shed can hold
+    let mut free_space = player.free_space(ItemType::Seedling);
+    for species in species.into_iter() {
+        if species.default_qty == -1 {
+            effects.storage.push(StorageDelta::Infinite { item_type: ItemType::Seedling, item_id: species.id });
+        } else {
+            let qty = species.default_qty.min(free_space);
+            if qty > 0 {
+                free_space -= qty;
+                effects.storage.push(StorageDelta::Add { item_type: ItemType::Seedling, item_id: species.id, qty });
+            }
+        }
+    }
+    effects
+}
+
+/// Add items to the storage of a player, refused if the shed can't hold them (see `Player::can_store`)
+fn add_items(player: &Player, effects: &mut Effects, item_type: ItemType, item_id: i16, qty: i32) -> Result<(), Refusal> {
+    player.can_store(item_type, item_id, qty).map_err(Refusal::StorageFull)?;
+    effects.storage.push(StorageDelta::Add { item_type, item_id, qty });
+    Ok(())
+}
+
+fn buy(player: &Player, item: &ShopItem) -> Result<Effects, Refusal> {
+    if let ShopItem::Pallet(species, qty) = item {
+        if !species.enabled || species.pallet_cost <= 0 || *qty <= 0 {
+            return Err(Refusal::UnknownSpecies);
+        }
+    }
+    let cost = item.cost();
+    if player.coins < cost {
+        return Err(Refusal::NotEnoughCoins { cost, coins: player.coins });
+    }
+
+    let mut effects = Effects {
+        coins: -cost,
+        ..Effects::new(Reason::Purchase)
+    };
+    match item {
+        ShopItem::Pallet(species, qty) => {
+            add_items(player, &mut effects, ItemType::Pallet, species.id, *qty)?;
+        }
+        ShopItem::ShedUpgrade(tier) => {
+            if player.storage_upgrade.max(1) as usize != *tier {
+                return Err(Refusal::AlreadyUpgraded);
+            }
+            effects.storage_upgrade = Some(*tier as i16 + 1);
+        }
+    }
+    Ok(effects)
+}
+
+fn claim_daily(server: &Server, player: &Player, today: u64) -> Result<Effects, Refusal> {
+    if player.last_daily == Some(today) {
+        return Err(Refusal::AlreadyClaimed);
+    }
+    // the streak only goes on if the last claim was yesterday
+    let streak = match player.last_daily {
+        Some(day) if day + 1 == today => player.daily_streak + 1,
+        _ => 1,
+    };
+    let reward = server.daily_reward(streak).ok_or(Refusal::NoDailyReward)?;
+
+    let mut effects = Effects {
+        daily: Some((today, streak)),
+        ..Effects::new(Reason::Daily)
+    };
+    match reward.species {
+        None => effects.coins = reward.amount,
+        Some(species_id) => {
+            match server.species.get(&species_id) {
+                Some(species) if species.enabled => {}
+                _ => return Err(Refusal::UnknownSpecies),
+            }
+            // the reward waits for some room in the shed
+            add_items(player, &mut effects, ItemType::Seedling, species_id, reward.amount)?;
+        }
+    }
+    Ok(effects)
+}
+
+fn unpack(server: &Server, player: &Player, species_id: i16, amount: i32) -> Result<Effects, Refusal> {
+    let species = server.species.get(&species_id).ok_or(Refusal::UnknownSpecies)?;
+    if amount <= 0 {
+        return Err(Refusal::InvalidQuantity);
+    }
+    match player.storage.get(&(ItemType::Pallet, species_id)) {
+        Some(-1) => return Err(Refusal::InfiniteItems),
+        Some(owned) if *owned >= amount => {}
+        _ => return Err(Refusal::NotEnoughItems { item_type: ItemType::Pallet, item_id: species_id }),
+    }
+    // both the amount and the pallet quantity are set by users, the product may not fit
+    let seedlings = amount.checked_mul(species.pallet_qty).ok_or(Refusal::StorageFull(StorageFull {
+        item_type: ItemType::Seedling,
+        free: player.free_space(ItemType::Seedling),
+    }))?;
+
+    let mut effects = Effects::new(Reason::Unpack);
+    effects.storage.push(StorageDelta::Take { item_type: ItemType::Pallet, item_id: species_id, qty: amount });
+    add_items(player, &mut effects, ItemType::Pallet, 0, amount)?;
+    add_items(player, &mut effects, ItemType::Seedling, species_id, seedlings)?;
+    Ok(effects)
+}
+
+fn fill_greenhouse(
+    server: &Server, player: &Player, slot_id: Option<i16>, species_id: i16, item_type: ItemType, qty: i32
+) -> Result<Effects, Refusal> {
+    let species = server.species.get(&species_id).ok_or(Refusal::UnknownSpecies)?;
+    if species.growth_rate <= 0 {
+        return Err(Refusal::NotGrowable);
+    }
+    let seedlings = match item_type {
+        ItemType::Pallet => qty.checked_mul(species.pallet_qty),
+        ItemType::Seedling => Some(qty),
+    };
+    let remaining = match seedlings.and_then(|seedlings| seedlings.checked_mul(GREENHOUSE_YIELD)) {
+        Some(remaining) if qty > 0 && remaining > 0 => remaining,
+        _ => return Err(Refusal::InvalidQuantity),
+    };
+    // infinite seedlings can't be sown, they would be turned into finite ones
+    match player.storage.get(&(item_type, species_id)) {
+        Some(owned) if *owned >= qty => {}
+        _ => return Err(Refusal::NotEnoughItems { item_type, item_id: species_id }),
+    }
+    let slot_id = match slot_id {
+        Some(slot_id) if slot_id >= 1 && slot_id <= player.greenhouse_tier().slots
+            && !player.greenhouse.contains_key(&slot_id) => slot_id,
+        Some(_) => return Err(Refusal::NoFreeSlot),
+        None => player.free_greenhouse_slot().ok_or(Refusal::NoFreeSlot)?,
+    };
+
+    let mut effects = Effects::new(Reason::Greenhouse);
+    effects.storage.push(StorageDelta::Take { item_type, item_id: species_id, qty });
+    if item_type == ItemType::Pallet {
+        add_items(player, &mut effects, ItemType::Pallet, 0, qty)?;
+    }
+    effects.greenhouse.push(GreenhouseDelta::Fill { slot_id, species_id, remaining });
+    Ok(effects)
+}
+
+fn collect_greenhouse(player: &Player) -> Result<Effects, Refusal> {
+    let mut slot_ids = player.greenhouse.keys().copied().collect::<Vec<i16>>();
+    slot_ids.sort();
+
+    let mut effects = Effects::new(Reason::Greenhouse);
+    // collected seedlings are capped to what the shed can hold
+    let mut free_space = player.free_space(ItemType::Seedling);
+    for slot_id in slot_ids.into_iter() {
+        let slot = &player.greenhouse[&slot_id];
+        // seedlings of a species with infinite seedlings can always be collected, they are lost anyway
+        let infinite = player.storage.get(&(ItemType::Seedling, slot.species)) == Some(&-1);
+        let qty = if infinite { slot.grown } else { slot.grown.min(free_space) };
+        if qty <= 0 {
+            continue;
+        }
+        if !infinite {
+            free_space -= qty;
+        }
+        effects.greenhouse.push(GreenhouseDelta::Collect { slot_id, qty });
+        effects.storage.push(StorageDelta::Add { item_type: ItemType::Seedling, item_id: slot.species, qty });
+    }
+    if effects.greenhouse.is_empty() {
+        return Err(Refusal::NothingToCollect);
+    }
+    Ok(effects)
+}
+
+fn upgrade_greenhouse(player: &Player) -> Result<Effects, Refusal> {
+    let next = player.next_greenhouse().ok_or(Refusal::AlreadyUpgraded)?;
+    if player.coins < next.cost {
+        return Err(Refusal::NotEnoughCoins { cost: next.cost, coins: player.coins });
+    }
+    Ok(Effects {
+        coins: -next.cost,
+        greenhouse_upgrade: Some(player.greenhouse_upgrade.max(1) + 1),
+        ..Effects::new(Reason::Purchase)
+    })
+}
+
+fn hire_worker(server: &Server, player: &Player, worker_id: i32) -> Result<Effects, Refusal> {
+    if player.workers.len() >= server.max_workers.max(0) as usize {
+        return Err(Refusal::TooManyWorkers);
+    }
+    let cost = player.worker_cost();
+    if player.coins < cost {
+        return Err(Refusal::NotEnoughCoins { cost, coins: player.coins });
+    }
+    let mut effects = Effects {
+        coins: -cost,
+        ..Effects::new(Reason::Purchase)
+    };
+    effects.workers.push(WorkerDelta::Hire { worker_id });
+    Ok(effects)
+}
+
+fn assign_worker(server: &Server, player: &Player, worker_id: i32, channel_id: u64) -> Result<Effects, Refusal> {
+    if !player.workers.iter().any(|worker| worker.id == worker_id) {
+        return Err(Refusal::UnknownWorker);
+    }
+    if !server.forest_rules.check(&channel_id) {
+        return Err(Refusal::ForestForbidden);
+    }
+    let mut effects = Effects::new(Reason::Work);
+    effects.workers.push(WorkerDelta::Assign { worker_id, channel_id });
+    Ok(effects)
+}
+
+fn fire_worker(player: &Player, worker_id: i32) -> Result<Effects, Refusal> {
+    if !player.workers.iter().any(|worker| worker.id == worker_id) {
+        return Err(Refusal::UnknownWorker);
+    }
+    let mut effects = Effects::new(Reason::Work);
+    effects.workers.push(WorkerDelta::Fire { worker_id });
+    Ok(effects)
+}
+
+/// Items and coins given by one side of a trade
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Offer {
+    /// Items as ((item type, species), qty)
+    pub items: Vec<((ItemType, i16), i32)>,
+    pub coins: i32,
+}
+
+impl Offer {
+    /// Add (or remove with a negative quantity) items, an item is dropped from the offer once its quantity reaches 0
+    pub fn add_items(&mut self, item: (ItemType, i16), qty: i32) {
+        match self.items.iter().position(|(offered, _)| *offered == item) {
+            Some(index) => {
+                self.items[index].1 += qty;
+                if self.items[index].1 <= 0 {
+                    self.items.remove(index);
+                }
+            }
+            None if qty > 0 => self.items.push((item, qty)),
+            None => {}
+        }
+    }
+
+    /// Add (or remove with a negative amount) coins, the offer never goes below 0
+    pub fn add_coins(&mut self, coins: i32) {
+        self.coins = (self.coins + coins).max(0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.coins == 0
+    }
+}
+
+/// Check that a player owns what they offer in a trade
+pub fn check_offer(server: &Server, player: &Player, offer: &Offer) -> Result<(), Refusal> {
+    if player.coins < offer.coins {
+        return Err(Refusal::NotEnoughCoins { cost: offer.coins, coins: player.coins });
+    }
+    for ((item_type, item_id), qty) in offer.items.iter() {
+        if !server.species.contains_key(item_id) {
+            return Err(Refusal::UnknownSpecies);
+        }
+        match player.storage.get(&(*item_type, *item_id)) {
+            Some(-1) => return Err(Refusal::InfiniteItems),
+            Some(amount) if amount >= qty => {}
+            _ => return Err(Refusal::NotEnoughItems { item_type: *item_type, item_id: *item_id }),
+        }
+    }
+    Ok(())
+}
+
+/// Evaluate a trade where each player gives their offer to the other one
+/// Returns the effects for both players, or the user id of the player the trade is refused for along with the reason
+pub fn trade(server: &Server, first: (&Player, &Offer), second: (&Player, &Offer)) -> Result<(Effects, Effects), (u64, Refusal)> {
+    let (first_player, first_offer) = first;
+    let (second_player, second_offer) = second;
+    check_offer(server, first_player, first_offer).map_err(|refusal| (first_player.user_id, refusal))?;
+    check_offer(server, second_player, second_offer).map_err(|refusal| (second_player.user_id, refusal))?;
+
+    let first_effects = exchange(first_player, first_offer, second_offer)
+        .map_err(|refusal| (first_player.user_id, refusal))?;
+    let second_effects = exchange(second_player, second_offer, first_offer)
+        .map_err(|refusal| (second_player.user_id, refusal))?;
+    Ok((first_effects, second_effects))
+}
+
+/// Effects of a trade for one of its players, received items must fit in the shed once given ones are gone
+fn exchange(player: &Player, given: &Offer, received: &Offer) -> Result<Effects, Refusal> {
+    for item_type in [ItemType::Pallet, ItemType::Seedling].iter() {
+        let freed: i32 = given.items.iter()
+            .filter(|((given_type, _), _)| given_type == item_type)
+            .map(|(_, qty)| qty)
+            .sum();
+        let needed: i32 = received.items.iter()
+            .filter(|((received_type, item_id), _)| {
+                received_type == item_type && player.storage.get(&(*item_type, *item_id)) != Some(&-1)
+            })
+            .map(|(_, qty)| qty)
+            .sum();
+        let free = player.free_space(*item_type) + freed;
+        if needed > free {
+            return Err(Refusal::StorageFull(StorageFull { item_type: *item_type, free }));
+        }
+    }
+
+    let mut effects = Effects {
+        coins: received.coins - given.coins,
+        ..Effects::new(Reason::Trade)
+    };
+    for ((item_type, item_id), qty) in given.items.iter() {
+        effects.storage.push(StorageDelta::Take { item_type: *item_type, item_id: *item_id, qty: *qty });
+    }
+    for ((item_type, item_id), qty) in received.items.iter() {
+        effects.storage.push(StorageDelta::Add { item_type: *item_type, item_id: *item_id, qty: *qty });
+    }
+    Ok(effects)
+}
+
+/// Apply effects to a cached player, once they have been persisted
+pub fn apply(player: &mut Player, effects: &Effects) {
+    for delta in effects.storage.iter() {
+        match delta {
+            StorageDelta::Add { item_type, item_id, qty } => {
+                let amount = match *player.storage.get(&(*item_type, *item_id)).unwrap_or(&0) {
+                    -1 => -1,
+                    amount => amount + qty,
+                };
+                player.storage.insert((*item_type, *item_id), amount);
+            }
+            StorageDelta::Infinite { item_type, item_id } => {
+                player.storage.insert((*item_type, *item_id), -1);
+            }
+            StorageDelta::Take { item_type, item_id, qty } => {
+                if let Some(amount) = player.storage.get_mut(&(*item_type, *item_id)) {
+                    *amount -= qty;
+                }
+            }
+        }
+    }
+    player.coins += effects.coins;
+    if let Some(storage_upgrade) = effects.storage_upgrade {
+        player.storage_upgrade = storage_upgrade;
+    }
+    for delta in effects.greenhouse.iter() {
+        match delta {
+            GreenhouseDelta::Fill { slot_id, species_id, remaining } => {
+                player.greenhouse.insert(*slot_id, GreenhouseSlot {
+                    species: *species_id,
+                    remaining: *remaining,
+                    grown: 0,
+                    progress: 0.0,
+                });
+            }
+            GreenhouseDelta::Collect { slot_id, qty } => {
+                if let Some(slot) = player.greenhouse.get_mut(slot_id) {
+                    slot.grown -= qty;
+                    if slot.is_done() {
+                        player.greenhouse.remove(slot_id);
+                    }
+                }
+            }
+        }
+    }
+    if let Some(greenhouse_upgrade) = effects.greenhouse_upgrade {
+        player.greenhouse_upgrade = greenhouse_upgrade;
+    }
+    for delta in effects.workers.iter() {
+        match delta {
+            WorkerDelta::Hire { worker_id } => player.workers.push(Worker {
+                id: *worker_id,
+                channel_id: None,
+                progress: 0.0,
+            }),
+            WorkerDelta::Assign { worker_id, channel_id } => {
+                if let Some(worker) = player.workers.iter_mut().find(|worker| worker.id == *worker_id) {
+                    worker.channel_id = Some(*channel_id);
+                }
+            }
+            WorkerDelta::Fire { worker_id } => player.workers.retain(|worker| worker.id != *worker_id),
+        }
+    }
+    if let Some((day, streak)) = effects.daily {
+        player.last_daily = Some(day);
+        player.daily_streak = streak;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::cooldown::{CooldownStore, LocalCooldowns};
+    use crate::prelude::ParentedCache;
+
+    pub(crate) const GUILD_ID: u64 = 1;
+    pub(crate) const USER_ID: u64 = 2;
+    pub(crate) const CHANNEL_ID: u64 = 3;
+
+    pub(crate) fn server() -> Server {
+        let mut server = Server {
+            id: GUILD_ID as i64,
+            prefix: "f-".to_owned(),
+            lang: "en".to_owned(),
+            forest_rules: Rules { global: true, channels: HashMap::new() },
+            plant_cooldown: 60,
+            cooldown_overrides: HashMap::new(),
+            commands_rules: Rules { global: true, channels: HashMap::new() },
+            max_workers: 3,
+            global_forest: true,
+            milestones_channel: None,
+            milestones: vec![100, 1000, 10000],
+            reached_milestones: HashSet::new(),
+            daily_coin_gifts: 500,
+            daily_item_gifts: 100,
+            daily_rewards: DailyReward::defaults(),
+            species: Species::defaults().into_iter().map(|species| (species.id, species)).collect(),
+            species_from_emojis: HashMap::new(),
+            player_cache: ParentedCache::new(GUILD_ID),
+        };
+        server.index_species();
+        server
+    }
+
+    pub(crate) fn player(coins: i32, storage: &[((ItemType, i16), i32)]) -> Player {
+        Player::new(1, USER_ID, GUILD_ID, coins, 1, storage.iter().cloned().collect(), false)
+    }
+
+    fn plant_action(species_id: i16) -> Action {
+        Action::Plant { channel_id: CHANNEL_ID, species_id }
+    }
+
+    #[test]
+    fn plant_uses_a_seedling_and_earns_coins() {
+        let server = server();
+        let mut player = player(0, &[((ItemType::Seedling, 1), 3)]);
+        let coins = server.species[&1].coins;
+
+        let effects = play(&server, &player, &plant_action(1)).unwrap();
+        assert_eq!(effects.reaction, Some(Reaction::Planted));
+        assert_eq!(effects.trees, vec![(CHANNEL_ID, 1, 1)]);
+        assert_eq!(effects.coins, coins);
+
+        apply(&mut player, &effects);
+        assert_eq!(player.storage[&(ItemType::Seedling, 1)], 2);
+        assert_eq!(player.coins, coins);
+    }
+
+    #[test]
+    fn plant_without_seedling_only_reacts() {
+        let server = server();
+        let player = player(0, &[((ItemType::Seedling, 1), 0)]);
+
+        let effects = play(&server, &player, &plant_action(1)).unwrap();
+        assert_eq!(effects.reaction, Some(Reaction::MissingSeedling));
+        assert!(effects.storage.is_empty() && effects.trees.is_empty());
+        assert_eq!(effects.coins, 0);
+    }
+
+    #[test]
+    fn plant_with_infinite_seedlings_keeps_them_infinite() {
+        let server = server();
+        let mut player = player(0, &[((ItemType::Seedling, 1), -1)]);
+
+        for _ in 0..3 {
+            let effects = play(&server, &player, &plant_action(1)).unwrap();
+            assert_eq!(effects.reaction, Some(Reaction::Planted));
+            apply(&mut player, &effects);
+        }
+        assert_eq!(player.storage[&(ItemType::Seedling, 1)], -1);
+    }
+
+    #[test]
+    fn plant_is_refused_outside_of_the_forest() {
+        let mut server = server();
+        server.forest_rules.channels.insert(CHANNEL_ID, false);
+        let player = player(0, &[((ItemType::Seedling, 1), 3)]);
+
+        assert!(matches!(play(&server, &player, &plant_action(1)), Err(Refusal::ForestForbidden)));
+    }
+
+    #[test]
+    fn plant_of_a_disabled_species_is_refused() {
+        let mut server = server();
+        server.species.get_mut(&1).unwrap().enabled = false;
+        let player = player(0, &[((ItemType::Seedling, 1), 3)]);
+
+        assert!(matches!(play(&server, &player, &plant_action(1)), Err(Refusal::UnknownSpecies)));
+    }
+
+    #[test]
+    fn plant_cooldown_uses_the_most_specific_override() {
+        let mut server = server();
+        let player = player(0, &[((ItemType::Seedling, 1), 3)]);
+
+        let effects = play(&server, &player, &plant_action(1)).unwrap();
+        assert_eq!(effects.cooldown, Some(((GUILD_ID, USER_ID, 0, 0), Duration::from_secs(60))));
+
+        server.cooldown_overrides.insert((0, 1), 30);
+        server.cooldown_overrides.insert((CHANNEL_ID, 0), 20);
+        let effects = play(&server, &player, &plant_action(1)).unwrap();
+        assert_eq!(effects.cooldown, Some(((GUILD_ID, USER_ID, CHANNEL_ID, 0), Duration::from_secs(20))));
+
+        server.cooldown_overrides.insert((CHANNEL_ID, 1), 0);
+        let effects = play(&server, &player, &plant_action(1)).unwrap();
+        assert_eq!(effects.cooldown, None);
+    }
+
+    #[tokio::test]
+    async fn plant_cooldown_blocks_the_same_scope() {
+        let server = server();
+        let player = player(0, &[((ItemType::Seedling, 1), 3)]);
+        let cooldowns = LocalCooldowns::default();
+
+        let (key, duration) = play(&server, &player, &plant_action(1)).unwrap().cooldown.unwrap();
+        assert!(cooldowns.try_start(key, duration).await.unwrap());
+        assert!(!cooldowns.try_start(key, duration).await.unwrap());
+
+        let other_player = (key.0, key.1 + 1, key.2, key.3);
+        assert!(cooldowns.try_start(other_player, duration).await.unwrap());
+    }
+
+    #[test]
+    fn default_seedlings_are_capped_by_the_shed() {
+        let mut server = server();
+        for species in server.species.values_mut() {
+            species.default_qty = 100;
+        }
+        server.species.get_mut(&1).unwrap().default_qty = -1;
+        let mut player = player(0, &[]);
+
+        let effects = play(&server, &player, &Action::GrantDefaultSeedlings).unwrap();
+        apply(&mut player, &effects);
+        assert_eq!(player.storage[&(ItemType::Seedling, 1)], -1);
+        assert_eq!(player.stored(ItemType::Seedling), player.shed().max_seedlings);
+    }
+
+    #[test]
+    fn buying_a_pallet_costs_coins() {
+        let server = server();
+        let species = server.species[&2].clone();
+        let mut player = player(species.pallet_cost, &[]);
+
+        let effects = play(&server, &player, &Action::Buy(ShopItem::Pallet(species.clone(), 1))).unwrap();
+        apply(&mut player, &effects);
+        assert_eq!(player.coins, 0);
+        assert_eq!(player.storage[&(ItemType::Pallet, species.id)], 1);
+
+        let refusal = play(&server, &player, &Action::Buy(ShopItem::Pallet(species, 1)));
+        assert!(matches!(refusal, Err(Refusal::NotEnoughCoins { coins: 0, .. })));
+    }
+
+    #[test]
+    fn buying_a_pallet_is_refused_when_the_shed_is_full() {
+        let server = server();
+        let species = server.species[&2].clone();
+        let max_pallets = SHED_TIERS[0].max_pallets;
+        let player = player(species.pallet_cost, &[((ItemType::Pallet, 3), max_pallets)]);
+
+        let refusal = play(&server, &player, &Action::Buy(ShopItem::Pallet(species, 1)));
+        assert!(matches!(refusal, Err(Refusal::StorageFull(StorageFull { free: 0, .. }))));
+    }
+
+    #[test]
+    fn bulk_purchases_are_checked_as_a_whole() {
+        let server = server();
+        let species = server.species[&2].clone();
+        let max_pallets = SHED_TIERS[0].max_pallets;
+        let mut player = player(species.pallet_cost * 3, &[((ItemType::Pallet, 3), max_pallets - 3)]);
+
+        let refusal = play(&server, &player, &Action::Buy(ShopItem::Pallet(species.clone(), 4)));
+        assert!(matches!(refusal, Err(Refusal::NotEnoughCoins { .. })));
+        player.coins = species.pallet_cost * 4;
+        let refusal = play(&server, &player, &Action::Buy(ShopItem::Pallet(species.clone(), 4)));
+        assert!(matches!(refusal, Err(Refusal::StorageFull(StorageFull { free: 3, .. }))));
+
+        let effects = play(&server, &player, &Action::Buy(ShopItem::Pallet(species.clone(), 3))).unwrap();
+        apply(&mut player, &effects);
+        assert_eq!(player.coins, species.pallet_cost);
+        assert_eq!(player.storage[&(ItemType::Pallet, species.id)], 3);
+
+        let refusal = play(&server, &player, &Action::Buy(ShopItem::Pallet(species, i32::MAX)));
+        assert!(matches!(refusal, Err(Refusal::NotEnoughCoins { cost: i32::MAX, .. })));
+    }
+
+    #[test]
+    fn shed_upgrades_are_bought_once() {
+        let server = server();
+        let mut player = player(SHED_TIERS[1].cost, &[]);
+        let upgrade = Action::Buy(ShopItem::ShedUpgrade(1));
+
+        let effects = play(&server, &player, &upgrade).unwrap();
+        apply(&mut player, &effects);
+        assert_eq!(player.storage_upgrade, 2);
+        assert_eq!(player.shed().name, SHED_TIERS[1].name);
+
+        player.coins = SHED_TIERS[1].cost;
+        assert!(matches!(play(&server, &player, &upgrade), Err(Refusal::AlreadyUpgraded)));
+    }
+
+    fn partner(coins: i32, storage: &[((ItemType, i16), i32)]) -> Player {
+        Player::new(2, USER_ID + 1, GUILD_ID, coins, 1, storage.iter().cloned().collect(), false)
+    }
+
+    #[test]
+    fn trades_swap_offers() {
+        let server = server();
+        let mut first = player(10, &[((ItemType::Pallet, 2), 2)]);
+        let mut second = partner(0, &[((ItemType::Seedling, 3), 20)]);
+        let mut first_offer = Offer::default();
+        first_offer.add_items((ItemType::Pallet, 2), 1);
+        first_offer.add_coins(5);
+        let mut second_offer = Offer::default();
+        second_offer.add_items((ItemType::Seedling, 3), 20);
+
+        let (first_effects, second_effects) = trade(&server, (&first, &first_offer), (&second, &second_offer)).unwrap();
+        apply(&mut first, &first_effects);
+        apply(&mut second, &second_effects);
+        assert_eq!((first.coins, second.coins), (5, 5));
+        assert_eq!(first.storage[&(ItemType::Pallet, 2)], 1);
+        assert_eq!(first.storage[&(ItemType::Seedling, 3)], 20);
+        assert_eq!(second.storage[&(ItemType::Pallet, 2)], 1);
+        assert_eq!(second.storage[&(ItemType::Seedling, 3)], 0);
+    }
+
+    #[test]
+    fn infinite_items_cant_be_traded() {
+        let server = server();
+        let first = player(0, &[((ItemType::Seedling, 1), -1)]);
+        let mut offer = Offer::default();
+        offer.add_items((ItemType::Seedling, 1), 1);
+
+        let refusal = trade(&server, (&first, &offer), (&partner(0, &[]), &Offer::default()));
+        assert!(matches!(refusal, Err((USER_ID, Refusal::InfiniteItems))));
+    }
+
+    #[test]
+    fn traded_items_must_fit_in_the_shed() {
+        let server = server();
+        let max_seedlings = SHED_TIERS[0].max_seedlings;
+        let first = player(0, &[((ItemType::Seedling, 2), max_seedlings)]);
+        let second = partner(0, &[((ItemType::Seedling, 3), 5)]);
+        let mut first_offer = Offer::default();
+        let mut second_offer = Offer::default();
+        second_offer.add_items((ItemType::Seedling, 3), 5);
+
+        let refusal = trade(&server, (&first, &first_offer), (&second, &second_offer));
+        assert!(matches!(refusal, Err((USER_ID, Refusal::StorageFull(StorageFull { free: 0, .. })))));
+
+        // giving seedlings away makes room for the received ones
+        first_offer.add_items((ItemType::Seedling, 2), 5);
+        assert!(trade(&server, (&first, &first_offer), (&second, &second_offer)).is_ok());
+    }
+
+    #[test]
+    fn offers_never_go_below_zero() {
+        let mut offer = Offer::default();
+        offer.add_items((ItemType::Seedling, 1), 3);
+        offer.add_items((ItemType::Seedling, 1), -5);
+        offer.add_coins(-5);
+        assert!(offer.is_empty());
+    }
+
+    #[test]
+    fn daily_streak_goes_on_until_a_day_is_missed() {
+        let server = server();
+        let mut player = player(0, &[]);
+
+        for today in 10..13 {
+            let effects = play(&server, &player, &Action::ClaimDaily { today }).unwrap();
+            apply(&mut player, &effects);
+        }
+        assert_eq!(player.daily_streak, 3);
+        assert_eq!(player.coins, 10 + 15 + 20);
+        assert!(matches!(play(&server, &player, &Action::ClaimDaily { today: 12 }), Err(Refusal::AlreadyClaimed)));
+
+        let effects = play(&server, &player, &Action::ClaimDaily { today: 14 }).unwrap();
+        assert_eq!(effects.daily, Some((14, 1)));
+        assert_eq!(effects.coins, 10);
+    }
+
+    #[test]
+    fn daily_seedlings_must_fit_in_the_shed() {
+        let mut server = server();
+        server.daily_rewards = [(1, DailyReward { species: Some(2), amount: 5 })].iter().cloned().collect();
+        let player = player(0, &[((ItemType::Seedling, 3), SHED_TIERS[0].max_seedlings)]);
+
+        let refusal = play(&server, &player, &Action::ClaimDaily { today: 10 });
+        assert!(matches!(refusal, Err(Refusal::StorageFull(StorageFull { free: 0, .. }))));
+
+        server.daily_rewards.clear();
+        assert!(matches!(play(&server, &player, &Action::ClaimDaily { today: 10 }), Err(Refusal::NoDailyReward)));
+    }
+
+    #[test]
+    fn long_streaks_keep_the_last_reward() {
+        let server = server();
+        assert_eq!(server.daily_reward(100), server.daily_rewards.values().last());
+        assert_eq!(server.daily_reward(0), None);
+    }
+
+    #[test]
+    fn unpacked_pallets_are_kept_empty() {
+        let server = server();
+        let mut player = player(0, &[((ItemType::Pallet, 2), 3)]);
+
+        let effects = play(&server, &player, &Action::Unpack { species_id: 2, amount: 2 }).unwrap();
+        apply(&mut player, &effects);
+        assert_eq!(player.storage[&(ItemType::Pallet, 2)], 1);
+        assert_eq!(player.storage[&(ItemType::Pallet, 0)], 2);
+        assert_eq!(player.storage[&(ItemType::Seedling, 2)], 20);
+
+        let refusal = play(&server, &player, &Action::Unpack { species_id: 2, amount: 2 });
+        assert!(matches!(refusal, Err(Refusal::NotEnoughItems { .. })));
+    }
+
+    #[test]
+    fn unpacked_seedlings_must_fit_in_the_shed() {
+        let server = server();
+        let player = player(0, &[((ItemType::Pallet, 2), 1), ((ItemType::Seedling, 3), SHED_TIERS[0].max_seedlings - 5)]);
+
+        let refusal = play(&server, &player, &Action::Unpack { species_id: 2, amount: 1 });
+        assert!(matches!(refusal, Err(Refusal::StorageFull(StorageFull { free: 5, .. }))));
+    }
+
+    #[test]
+    fn greenhouse_seedlings_are_collected_as_the_shed_allows() {
+        let server = server();
+        let mut player = player(0, &[((ItemType::Pallet, 2), 1)]);
+
+        let fill = Action::FillGreenhouse { slot_id: None, species_id: 2, item_type: ItemType::Pallet, qty: 1 };
+        let effects = play(&server, &player, &fill).unwrap();
+        assert_eq!(effects.greenhouse, vec![GreenhouseDelta::Fill { slot_id: 1, species_id: 2, remaining: 10 * GREENHOUSE_YIELD }]);
+        apply(&mut player, &effects);
+        assert!(matches!(play(&server, &player, &fill), Err(Refusal::NotEnoughItems { .. })));
+        assert!(matches!(play(&server, &player, &Action::CollectGreenhouse), Err(Refusal::NothingToCollect)));
+
+        let slot = player.greenhouse.get_mut(&1).unwrap();
+        slot.remaining = 0;
+        slot.grown = 20;
+        player.storage.insert((ItemType::Seedling, 3), SHED_TIERS[0].max_seedlings - 5);
+        let effects = play(&server, &player, &Action::CollectGreenhouse).unwrap();
+        assert_eq!(effects.greenhouse, vec![GreenhouseDelta::Collect { slot_id: 1, qty: 5 }]);
+        apply(&mut player, &effects);
+        assert_eq!(player.greenhouse[&1].grown, 15);
+        assert_eq!(player.storage[&(ItemType::Seedling, 2)], 5);
+    }
+
+    #[test]
+    fn each_worker_costs_more_than_the_previous() {
+        let server = server();
+        let mut player = player(WORKER_COST * 3, &[]);
+
+        for worker_id in 1..=2 {
+            let effects = play(&server, &player, &Action::HireWorker { worker_id }).unwrap();
+            apply(&mut player, &effects);
+        }
+        assert_eq!(player.coins, 0);
+        assert!(matches!(play(&server, &player, &Action::HireWorker { worker_id: 3 }), Err(Refusal::NotEnoughCoins { .. })));
+
+        let effects = play(&server, &player, &Action::AssignWorker { worker_id: 2, channel_id: CHANNEL_ID }).unwrap();
+        apply(&mut player, &effects);
+        assert_eq!(player.workers[1].channel_id, Some(CHANNEL_ID));
+        let effects = play(&server, &player, &Action::FireWorker { worker_id: 1 }).unwrap();
+        apply(&mut player, &effects);
+        assert_eq!(player.workers.len(), 1);
+        assert!(matches!(play(&server, &player, &Action::FireWorker { worker_id: 1 }), Err(Refusal::UnknownWorker)));
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+/// Available locales, as (code, displayable name, flag emoji)
+pub const LOCALES: [(&str, &str, &str); 2] = [
+    ("en", "English", ":flag_gb:"),
+    ("fr", "Français", ":flag_fr:"),
+];
+
+/// Locale used when a message is missing from another locale
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// A catalog entry, either a simple text or plural forms selected by the `count` argument
+enum Text {
+    Simple(String),
+    Plural(HashMap<String, String>),
+}
+
+type Catalog = HashMap<String, Text>;
+
+lazy_static! {
+    static ref CATALOGS: HashMap<&'static str, Catalog> = {
+        let mut catalogs = HashMap::new();
+        catalogs.insert("en", parse_catalog(include_str!("../locales/en.toml")));
+        catalogs.insert("fr", parse_catalog(include_str!("../locales/fr.toml")));
+        catalogs
+    };
+
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{(\w+)\}").unwrap();
+    static ref ICON_REGEX: Regex = Regex::new(r"/:(\w+)/").unwrap();
+
+    /// Custom emojis, messages use the same `/:name/` syntax as `eformat!`
+    static ref ICONS: HashMap<&'static str, String> = {
+        let mut icons = HashMap::new();
+        icons.insert("forest", eformat!("/:forest/",));
+        icons.insert("pallet", eformat!("/:pallet/",));
+        icons.insert("coin", eformat!("/:coin/",));
+        icons.insert("shed", eformat!("/:shed/",));
+        icons.insert("ranger", eformat!("/:ranger/",));
+        icons
+    };
+}
+
+const PLURAL_CATEGORIES: [&str; 6] = ["zero", "one", "two", "few", "many", "other"];
+
+/// Flatten a TOML bundle into dotted keys
+fn parse_catalog(source: &str) -> Catalog {
+    fn flatten(catalog: &mut Catalog, prefix: &str, table: &toml::value::Table) {
+        for (key, value) in table.iter() {
+            let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            match value {
+                toml::Value::String(text) => {
+                    catalog.insert(key, Text::Simple(text.clone()));
+                }
+                toml::Value::Table(table) if table.contains_key("other")
+                    && table.keys().all(|form| PLURAL_CATEGORIES.contains(&form.as_str())) =>
+                {
+                    let forms = table.iter()
+                        .filter_map(|(form, text)| text.as_str().map(|text| (form.clone(), text.to_owned())))
+                        .collect();
+                    catalog.insert(key, Text::Plural(forms));
+                }
+                toml::Value::Table(table) => flatten(catalog, &key, table),
+                _ => {}
+            }
+        }
+    }
+
+    let mut catalog = HashMap::new();
+    match source.parse::<toml::Value>() {
+        Ok(toml::Value::Table(table)) => flatten(&mut catalog, "", &table),
+        _ => panic!("Invalid locale bundle"),
+    }
+    catalog
+}
+
+/// Plural category of a number, following CLDR rules for integers
+fn plural_category(lang: &str, count: f64) -> &'static str {
+    match lang {
+        "fr" if count == 0.0 || count == 1.0 => "one",
+        "fr" => "other",
+        _ if count == 1.0 => "one",
+        _ => "other",
+    }
+}
+
+pub fn is_supported(lang: &str) -> bool {
+    LOCALES.iter().any(|(code, _, _)| *code == lang)
+}
+
+/// Displayable name and flag of a locale
+pub fn describe(lang: &str) -> (&'static str, &'static str) {
+    LOCALES.iter()
+        .find(|(code, _, _)| *code == lang)
+        .map(|(_, name, flag)| (*name, *flag))
+        .unwrap_or(("Unknown", ":grey_question:"))
+}
+
+/// Get a message from the catalog of a locale and fill its `{placeholders}`
+/// Falls back to the default locale, then to the key itself
+pub fn translate(lang: &str, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let text = CATALOGS.get(lang)
+        .and_then(|catalog| catalog.get(key))
+        .or_else(|| CATALOGS[DEFAULT_LOCALE].get(key));
+    let text = match text {
+        Some(Text::Simple(text)) => text.as_str(),
+        Some(Text::Plural(forms)) => {
+            let count = args.iter()
+                .find(|(name, _)| *name == "count")
+                .and_then(|(_, value)| value.to_string().parse::<f64>().ok())
+                .unwrap_or(0.0);
+            match forms.get(plural_category(lang, count)).or_else(|| forms.get("other")) {
+                Some(text) => text.as_str(),
+                None => key,
+            }
+        }
+        None => key,
+    };
+
+    let text = PLACEHOLDER_REGEX.replace_all(text, |captures: &Captures| {
+        match args.iter().find(|(name, _)| *name == &captures[1]) {
+            Some((_, value)) => value.to_string(),
+            None => captures[0].to_owned(),
+        }
+    });
+    ICON_REGEX.replace_all(&text, |captures: &Captures| {
+        match ICONS.get(&captures[1]) {
+            Some(icon) => icon.clone(),
+            None => captures[0].to_owned(),
+        }
+    }).into_owned()
+}
@@ -1,8 +1,8 @@
 #![feature(or_patterns)]
 
 use std::env;
-use std::collections::HashMap;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::Duration;
 
 use lazy_static::*;
 use regex::Regex;
@@ -16,22 +16,40 @@ use serenity::{
     },
     framework::standard::{
         macros::{group, hook},
-        StandardFramework,
+        CommandResult, StandardFramework,
     },
+    builder::CreateEmbed,
     http::Http,
     async_trait,
 };
-use sqlx::Row;
-use sqlx::postgres::{PgPoolOptions, PgPool};
+use sqlx::postgres::PgPoolOptions;
 
+use crate::error::{Error, Result};
 use crate::prelude::*;
 use crate::models::*;
 use crate::commands::prelude::*;
+use crate::cooldown::{LocalCooldowns, DatabaseCooldowns};
+use crate::game::{Action, Reaction};
+use crate::store::{MemoryStore, PgStore};
 
 #[macro_use]
 pub mod prelude;
+pub mod i18n;
 pub mod models;
+pub mod error;
 mod commands;
+mod cooldown;
+mod time_check;
+mod game;
+mod store;
+mod render;
+mod achievements;
+mod milestones;
+
+/// Seconds between each eviction of expired cooldowns
+const COOLDOWN_SWEEP_INTERVAL: u64 = 300;
+/// Seconds between each time check of cached players, workers keep planting for players who don't use commands
+const TIME_CHECK_INTERVAL: u64 = WORKER_PLANT_INTERVAL;
 
 struct Handler;
 
@@ -46,21 +64,32 @@ impl EventHandler for Handler {
 #[group]
 #[commands(
     cmd_help, cmd_ping, cmd_prefix, cmd_invite, cmd_support,
-    cmd_stats, cmd_mystats, cmd_leaderboard,
+    cmd_stats, cmd_mystats, cmd_leaderboard, cmd_forest,
     cmd_settings,
-    cmd_storage, cmd_shop,
+    cmd_storage, cmd_shop, cmd_unpack,
+    cmd_greenhouse, cmd_workers,
+    cmd_achievements, cmd_trade, cmd_give, cmd_transfers,
+    cmd_wallet, cmd_reconcile, cmd_daily,
 )]
 struct General;
 
 #[tokio::main]
-async fn main() -> Result<(), sqlx::Error> {
+async fn main() -> Result<()> {
     dotenv::dotenv().ok();
     let token = env::var(format!("DISCORD_{}", env::var("TK").unwrap_or("TOKEN".to_owned())))
         .expect("discord token");
 
-    let db_pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&env::var("DATABASE_URL").expect("database connection url")).await?;
+    // the memory store doesn't need a database, for tests and local demos
+    let db_pool = match env::var("STORE").as_deref() {
+        Ok("memory") => None,
+        _ => Some(PgPoolOptions::new()
+            .max_connections(5)
+            .connect(&env::var("DATABASE_URL").expect("database connection url")).await?),
+    };
+    let store: Arc<dyn Store> = match &db_pool {
+        Some(db_pool) => Arc::new(PgStore(db_pool.clone())),
+        None => Arc::new(MemoryStore::default()),
+    };
 
     let http = Http::new_with_token(&token);
     let bot_id = match http.get_current_application_info().await {
@@ -77,6 +106,7 @@ async fn main() -> Result<(), sqlx::Error> {
             .dynamic_prefix(dynamic_prefix)
         )
         .before(before_hook)
+        .after(after_hook)
         .normal_message(normal_message)
         .group(&GENERAL_GROUP);
 
@@ -85,145 +115,56 @@ async fn main() -> Result<(), sqlx::Error> {
         .event_handler(Handler)
         .await.expect("Unable to create the client!");
 
+    // cooldowns are kept in the database by default so that they survive restarts and can be shared by several processes
+    let cooldowns: Arc<dyn CooldownStore> = match (env::var("COOLDOWN_STORE").as_deref(), &db_pool) {
+        (Ok("local"), _) | (_, None) => Arc::new(LocalCooldowns::default()),
+        (_, Some(db_pool)) => Arc::new(DatabaseCooldowns(db_pool.clone())),
+    };
+    let cooldowns_to_sweep = cooldowns.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(COOLDOWN_SWEEP_INTERVAL));
+        loop {
+            interval.tick().await;
+            if let Err(why) = cooldowns_to_sweep.sweep().await {
+                println!("Could not sweep expired cooldowns: {:?}", why);
+            }
+        }
+    });
+
     {
         let mut data = client.data.write().await;
-        data.insert::<DatabaseConnection>(db_pool);
-        data.insert::<PlantCooldown>(HashMap::new());
-        data.insert::<ServerCache>(OneDatabaseCache::new(fetch_server));
-    }
-
-    if let Err(why) = client.start().await {
-        println!("An error occurred while running the client: {:?}", why);
-    }
-
-    Ok(())
-}
-
-async fn get_rules(pool: &PgPool, kind: u8, guild_id: &u64) -> Rules {
-    let rows = sqlx::query!(
-        "SELECT scope, allowance FROM rules WHERE guild_id = $1 AND kind = $2 ORDER BY scope ASC",
-        *guild_id as i64, kind as i16 
-    ).fetch_all(pool).await.unwrap();
-
-    let mut channels = HashMap::new();
-    for channel_rule in rows.iter() {
-        if channel_rule.scope == 0 {
-            continue;
+        data.insert::<DataStore>(store);
+        data.insert::<PlantCooldown>(cooldowns);
+        data.insert::<ServerCache>(Cache::new());
+        if let Some(channel_id) = env::var("OFFICIAL_FOREST").ok().and_then(|id| id.parse::<u64>().ok()) {
+            data.insert::<OfficialForest>(channel_id);
         }
-        channels.insert(channel_rule.scope as u64, channel_rule.allowance);
     }
 
-    Rules {
-        global: if !rows.is_empty() && rows[0].scope == 0 {
-            rows[0].allowance
-        } else {
-            true
-        },
-        channels,
-    }
-}
-
-macro_rules! temp_species {
-    ($species:ident, $id:expr, $emoji:expr, $name:expr, $cost:expr, $qty:expr, $coins:expr) => {
-        $species.insert($id, Species {
-            id: $id,
-            emoji: $emoji.to_owned(),
-            name: $name.to_owned(),
-            pallet_cost: $cost,
-            default_qty: $qty,
-            coins: $coins,
-        });
-    };
-}
-
-/// Fetch and parse a player from the database to cache it.
-/// If the player is new (not present in the db), it gets inserted with default values.
-/// 
-/// Note: Should be called only once per player (unique per user and per server) during runtime.
-#[hook]
-async fn fetch_player(pool: &PgPool, user_id: &u64, guild_id: &u64) -> Option<Player> {
-    let mut result = sqlx::query("SELECT * FROM players WHERE user_id = $1 AND guild_id = $2")
-        .bind(*user_id as i64)
-        .bind(*guild_id as i64)
-        .fetch_optional(pool).await.unwrap();
-    let new = result.is_none();
-    if result.is_none() {
-        result = sqlx::query("INSERT INTO players (user_id, guild_id) VALUES ($1, $2) ON CONFLICT DO NOTHING RETURNING *")
-            .bind(*user_id as i64)
-            .bind(*guild_id as i64)
-            .fetch_optional(pool).await.unwrap();
-    }
-
-    if let Some(result) = result {
-        let player_id: i32 = result.get("id");
-
-        let mut storage = HashMap::new();
-        let storage_rows = sqlx::query!(
-            "SELECT item_type, item_id, amount FROM storage WHERE player_id = $1",
-           player_id 
-        ).fetch_all(pool).await.unwrap();
-        for storage_item in storage_rows.iter() {
-            storage.insert((ItemType::from_i16(storage_item.item_type), storage_item.item_id), storage_item.amount);
+    let data = client.data.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(TIME_CHECK_INTERVAL));
+        loop {
+            interval.tick().await;
+            for (guild_id, user_id) in time_check::busy_players(&data).await {
+                if let Err(why) = time_check::run_for(&data, guild_id, user_id).await {
+                    println!("Could not run the time check of player {}: {}", user_id, why);
+                }
+            }
         }
+    });
 
-        Some(Player::new(player_id, *user_id, *guild_id, result.get("coins"), storage, new))
-    } else {
-        None
-    }
-}
-
-/// Fetch and parse a server from the database to cache it.
-/// If the server is new (not present in the db), it gets inserted with default values.
-/// 
-/// Note: Should be called only once per server during runtime.
-#[hook]
-async fn fetch_server(pool: &PgPool, guild_id: &u64) -> Option<Server> {
-    let mut result = sqlx::query("SELECT * FROM servers WHERE id = $1")
-        .bind(*guild_id as i64)
-        .fetch_optional(pool).await.unwrap();
-    if result.is_none() {
-        result = sqlx::query("INSERT INTO servers (id) VALUES ($1) ON CONFLICT DO NOTHING RETURNING *")
-            .bind(*guild_id as i64)
-            .fetch_optional(pool).await.unwrap();
+    if let Err(why) = client.start().await {
+        println!("An error occurred while running the client: {:?}", why);
     }
 
-    if let Some(result) = result {
-        let forest_rules = get_rules(pool, 1, guild_id).await;
-        let commands_rules = get_rules(pool, 2, guild_id).await;
-
-        let mut species = HashMap::new();
-        temp_species!(species, 1, "🌲", "Evergreen Tree", 0, -1, 1);
-        temp_species!(species, 2, "🌳", "Deciduous Tree", 12, 50, 1);
-        temp_species!(species, 3, "🌴", "Palm Tree", 15, 30, 1);
-        temp_species!(species, 4, "🌵", "Cactus", 25, 20, 2);
-        temp_species!(species, 5, "🎍", "Bamboo", 50, 10, 3);
-
-        let mut species_from_emojis = HashMap::new();
-        for species in species.values() {
-            species_from_emojis.insert(species.emoji.clone(), species.id);
-        }
-
-        Some(Server {
-            id: result.get("id"),
-            prefix: result.get("prefix"),
-            lang: result.get("lang"),
-            plant_cooldown: result.get("plant_cooldown"),
-
-            forest_rules,
-            commands_rules,
-
-            species,
-            species_from_emojis,
-
-            player_cache: ParentedOneDatabaseCache::new(*guild_id as u64, fetch_player),
-        })
-    } else {
-        None
-    }
+    Ok(())
 }
 
 lazy_static! {
-    static ref EMOJI_REGEX: Regex = Regex::new(r"^(?:(\p{Emoji_Presentation}|:\w+:|<:\w+:(\d{17,18})>) *)+$").unwrap();
+    static ref EMOJI_REGEX: Regex = Regex::new(
+        &format!(r"^(?:((?:{})|:\w+:|<a?:\w+:\d{{17,20}}>) *)+$", UNICODE_EMOJI_PATTERN)
+    ).unwrap();
 }
 
 #[hook]
@@ -240,64 +181,109 @@ async fn dynamic_prefix(ctx: &Context, message: &Message) -> Option<String> {
         return Some(server.prefix.clone());
     }
 
-    let pool = data.get::<DatabaseConnection>().unwrap();
-    if let Some(server) = server_cache.fetch(pool, &server_id).await {
-        quick_init!(ctx -> mut data ~data~ => sc:server_cache);
-        server_cache.insert(server_id, server);
-        return Some(server_cache.get(&server_id)?.prefix.clone());
+    let store = data.get::<DataStore>().unwrap().clone();
+    std::mem::drop(data);
+    match store.load_server(server_id).await {
+        Ok(Some(server)) => {
+            quick_init!(ctx -> mut data => sc:server_cache);
+            // another message of this server may have been faster
+            let server = server_cache.0.entry(server_id).or_insert(server);
+            Some(server.prefix.clone())
+        }
+        Ok(None) => None,
+        Err(why) => {
+            println!("Could not load server {}: {:?}", server_id, why);
+            None
+        }
     }
-    None
 }
 
-macro_rules! create_player {
-    ($ctx:ident, $data:ident, $server:ident, $message:ident $(, $end:tt)?) => {
-        if !$server.player_cache.1.contains_key(&$message.author.id.0) {
-            let user_id = $message.author.id.0;
-            let pool = $data.get::<DatabaseConnection>().unwrap();
-            if let Some(mut player) = $server.player_cache.fetch(pool, &user_id).await {
-                if player.is_new() {
-                    let server = $data.get::<ServerCache>().unwrap().get(&$message.guild_id.unwrap().0).unwrap();
-                    for species in server.species.values() {
-                        if species.default_qty == -1 || species.default_qty > 0 {
-                            player.give_item(ItemType::Seedling, species.id, species.default_qty);
-                            sqlx::query!(
-                            "INSERT INTO storage (player_id, item_type, item_id, amount) VALUES ($1, $2, $3, $4)
-                                ON CONFLICT(player_id, item_type, item_id) DO UPDATE SET amount = storage.amount + $4",
-                                player.id, ItemType::Seedling as i16, species.id, species.default_qty
-                            ).execute(pool).await.unwrap();
-                        }
-                    }
-                }
+/// Cache the message author if needed, new players get their default seedlings
+async fn cache_player(ctx: &Context, message: &Message) -> Result<()> {
+    let user_id = message.author.id.0;
+    if !cache_user(ctx, message, user_id).await? {
+        return Ok(());
+    }
 
-                quick_init!($ctx -> mut data ~$data~; $message => s:server);
-                server.player_cache.insert(user_id, player);
-            } else {
-                $( $end )?
-            }
-        }
-        else {
-            std::mem::drop($data);
-        }
-    };
+    // catch up on what happened while the player was offline
+    if let Err(why) = crate::time_check::run(ctx, message).await {
+        println!("Could not run the time check of player {}: {}", user_id, why);
+    }
+    Ok(())
+}
+
+/// Cache a player of the message server if needed, new players get their default seedlings
+/// Returns false if the player was already cached
+pub(crate) async fn cache_user(ctx: &Context, message: &Message, user_id: u64) -> Result<bool> {
+    quick_init!(ctx -> data => st:store; message => s:server);
+    if server.player_cache.get(&user_id).is_some() {
+        return Ok(false);
+    }
+
+    let mut player = store.load_player(server.id as u64, user_id).await?
+        .ok_or(Error::Missing("player"))?;
+    if player.is_new() {
+        let effects = game::play(server, &player, &Action::GrantDefaultSeedlings)
+            .expect("default seedlings are never refused");
+        store.save_effects(player.guild_id, user_id, player.id, &effects).await?;
+        game::apply(&mut player, &effects);
+    }
+
+    quick_init!(ctx -> mut data ~data~; message => s:server);
+    // another message of this player may have been faster
+    server.player_cache.1.entry(user_id).or_insert(player);
+    Ok(true)
 }
 
 #[hook]
 async fn before_hook(ctx: &Context, message: &Message, command: &str) -> bool {
+    match allow_command(ctx, message, command).await {
+        Ok(allowed) => allowed,
+        Err(why) => {
+            println!("Could not check command {} of user {} in channel {}: {}", command, message.author.id, message.channel_id, why);
+            false
+        }
+    }
+}
+
+async fn allow_command(ctx: &Context, message: &Message, command: &str) -> Result<bool> {
     quick_init!(ctx -> data; message => s:server);
     if !server.commands_rules.check(&message.channel_id.0) {
-        if command == "settings" && (message.member(&ctx.cache).await.unwrap()
-            .permissions(&ctx.cache).await.unwrap().manage_guild() || message.author.id.0 == 345259637513256960)
+        if command == "settings" && (message.member(&ctx.cache).await?
+            .permissions(&ctx.cache).await?.manage_guild() || message.author.id.0 == 345259637513256960)
         {
-            let _ = message.channel_id.say(&ctx.http, ":warning: *Commands are disabled in this channel but you are bypassing this rule as you have the required permission and trying to use an important command.*").await.unwrap();
-            return true;
+            message.channel_id.say(&ctx.http, tr!(server.lang => "commands_bypass")).await?;
+            return Ok(true);
         }
-        return false;
+        return Ok(false);
     }
+    std::mem::drop(data);
 
-    create_player!(ctx, data, server, message, { 
-        return false;
-    });
-    true
+    cache_player(ctx, message).await?;
+    Ok(true)
+}
+
+#[hook]
+async fn after_hook(ctx: &Context, message: &Message, command: &str, result: CommandResult) {
+    if let Err(why) = result {
+        println!("Command {} of user {} in channel {} failed: {}", command, message.author.id, message.channel_id, why);
+        if let Err(why) = report_error(ctx, message, command).await {
+            println!("Could not report the failure of command {} in channel {}: {}", command, message.channel_id, why);
+        }
+    }
+}
+
+/// Tell the user that a command failed, details are only logged
+async fn report_error(ctx: &Context, message: &Message, command: &str) -> Result<()> {
+    let data = ctx.data.read().await;
+    let lang = message.guild_id
+        .and_then(|guild_id| data.get::<ServerCache>().unwrap().get(&guild_id.0))
+        .map(|server| server.lang.clone())
+        .unwrap_or_else(|| i18n::DEFAULT_LOCALE.to_owned());
+    std::mem::drop(data);
+
+    error!(ctx, message.channel_id, lang => "command_failed", command = command);
+    Ok(())
 }
 
 #[hook]
@@ -307,80 +293,53 @@ async fn normal_message(ctx: &Context, message: &Message) {
     }
 
     if let Some(captures) = EMOJI_REGEX.captures(message.content.as_str()) {
-        let emoji = if let Some(custom_emoji_id) = captures.get(2) {
-            format!("{}>", custom_emoji_id.as_str())
-        } else { 
-            captures[1].to_owned()
-        };
-
-        quick_init!(ctx -> data; message => s:server);
-        if !server.forest_rules.check(&message.channel_id.0) {
-            return;
+        let emoji = EmojiKey::from(&captures[1]);
+        if let Err(why) = plant(ctx, message, emoji).await {
+            println!("Could not plant a tree for user {} in channel {}: {}", message.author.id, message.channel_id, why);
         }
+    }
+}
 
-        if let Some(species) = server.species_from_emojis.get(&emoji) {
-            let species_id = *species;
-            std::mem::drop(species);
-
-            let plant_cooldown = server.plant_cooldown;
-            create_player!(ctx, data, server, message);
-
-            let user_id = message.author.id.0;
-            if plant_cooldown > 0 {
-                quick_init!(ctx -> mut data => pc:pc);
-                
-                let now = Instant::now();
-                if match pc.get(&user_id) {
-                    Some(time) => {
-                        let elapsed = (now - *time).as_secs();
-                        elapsed <= plant_cooldown as u64
-                    }
-                    None => false
-                } {
-                    return;
-                }
-                pc.insert(user_id.clone(), now);
-            }
+async fn plant(ctx: &Context, message: &Message, emoji: EmojiKey) -> Result<()> {
+    quick_init!(ctx -> data; message => s:server);
+    if !server.forest_rules.check(&message.channel_id.0) {
+        return Ok(());
+    }
+    let species_id = match server.species_from_emojis.get(&emoji) {
+        Some(species_id) => *species_id,
+        None => return Ok(()),
+    };
+    std::mem::drop(data);
+    cache_player(ctx, message).await?;
+
+    quick_init!(ctx -> data => st:store pc:pc; message => s:server [player]);
+    let action = Action::Plant { channel_id: message.channel_id.0, species_id };
+    let effects = match game::play(server, player, &action) {
+        Ok(effects) => effects,
+        Err(_) => return Ok(()),
+    };
+    let (guild_id, user_id, player_id) = (player.guild_id, player.user_id, player.id);
 
-            quick_init!(ctx -> mut data; message => s:server [player]);
-            if let Some(amt) = player.storage.get_mut(&(ItemType::Seedling, species_id)) {
-                let amount = *amt;
-                if amount == -1 || amount > 0 {
-                    if amount != -1 {
-                        *amt -= 1;
-                    }
-
-                    let player_id = player.id;
-                    let coins = server.species.get(&species_id).unwrap().coins;
-                    player.coins += coins;
-
-                    quick_init!(ctx ~data~ => p:pool);
-                    sqlx::query!("
-                        UPDATE storage SET amount = amount - 1 
-                        WHERE player_id = $1 AND item_type = $2 AND item_id = $3 AND amount > 0;",
-                        player_id, ItemType::Seedling as i16, species_id
-                    ).execute(pool).await.unwrap();
-                    sqlx::query!("
-                        INSERT INTO trees (species, user_id, channel_id, guild_id) VALUES ($1, $2, $3, $4) 
-                        ON CONFLICT(species, user_id, channel_id) DO UPDATE SET count = trees.count + 1",
-                        species_id, user_id as i64, message.channel_id.0 as i64, message.guild_id.unwrap().0 as i64, 
-                    ).execute(pool).await.unwrap();
-                    if coins > 0 {
-                        sqlx::query!(
-                            "UPDATE players SET coins = coins + $2 WHERE id = $1",
-                            player_id, coins
-                        ).execute(pool).await.unwrap();
-                    }
-
-                    let _ = message.react(&ctx.http, ReactionType::Unicode("🌱".to_string())).await;
-                    return;
-                }
-            }
-            let _ = message.react(&ctx.http, ReactionType::Custom {
-                animated: false,
-                id: EmojiId(750012121475186760),
-                name: Some("missing_seedling".to_owned()),
-            }).await;
+    if let Some((cooldown_key, cooldown)) = effects.cooldown {
+        if !pc.try_start(cooldown_key, cooldown).await? {
+            return Ok(());
         }
     }
+
+    store.save_effects(guild_id, user_id, player_id, &effects).await?;
+    quick_init!(ctx -> mut data ~data~; message => s:server [player]);
+    game::apply(player, &effects);
+    std::mem::drop(data);
+
+    if effects.reaction == Some(Reaction::Planted) {
+        message.react(&ctx.http, ReactionType::Unicode("🌱".to_string())).await?;
+        achievements::run(ctx, message).await?;
+        return milestones::run(ctx, message).await;
+    }
+    message.react(&ctx.http, ReactionType::Custom {
+        animated: false,
+        id: EmojiId(750012121475186760),
+        name: Some("missing_seedling".to_owned()),
+    }).await?;
+    Ok(())
 }
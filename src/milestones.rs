@@ -0,0 +1,96 @@
+//! Milestones, celebrated when a channel forest or the server forest reaches one of the tree totals set by the server.
+//! Each milestone is recorded once it is reached so that it is never announced twice.
+
+use serenity::{
+    client::Context,
+    model::{channel::Message, id::ChannelId},
+};
+
+use crate::error::Result;
+use crate::prelude::*;
+
+/// Contributors listed in an announcement
+const TOP_CONTRIBUTORS: usize = 5;
+
+/// Milestones of a forest, channel 0 being the server forest, reached by a total but not recorded yet
+pub fn reached(server: &Server, channel_id: u64, total: i64) -> Vec<i64> {
+    server.milestones.iter()
+        .filter(|threshold| **threshold <= total && !server.reached_milestones.contains(&(channel_id, **threshold)))
+        .copied()
+        .collect()
+}
+
+/// Record the milestones reached by the forest of the message channel and by the server forest, and announce them
+pub async fn run(ctx: &Context, message: &Message) -> Result<()> {
+    quick_init!(ctx -> data => st:store; message => s:server);
+    if server.milestones.is_empty() {
+        return Ok(());
+    }
+    let guild_id = server.id as u64;
+    let channel_id = message.channel_id.0;
+    std::mem::drop(data);
+
+    let (channel_total, server_total) = store.forest_totals(guild_id, channel_id).await?;
+    for (forest_id, total) in [(channel_id, channel_total), (0, server_total)].iter() {
+        quick_init!(ctx -> data; message => s:server);
+        let thresholds = reached(server, *forest_id, *total);
+        let announcements = ChannelId(server.milestones_channel.unwrap_or(channel_id));
+        let lang = server.lang.clone();
+        std::mem::drop(data);
+        if thresholds.is_empty() {
+            continue;
+        }
+
+        let newly_reached = store.reach_milestones(guild_id, *forest_id, &thresholds).await?;
+        quick_init!(ctx -> mut data; message => s:server);
+        server.reached_milestones.extend(thresholds.iter().map(|threshold| (*forest_id, *threshold)));
+        std::mem::drop(data);
+
+        // milestones passed at once, or passed before being set, are celebrated by the highest one only
+        let threshold = match newly_reached.iter().max() {
+            Some(threshold) => *threshold,
+            None => continue,
+        };
+        let contributors = store.top_contributors(guild_id, *forest_id, TOP_CONTRIBUTORS).await?
+            .iter()
+            .enumerate()
+            .map(|(rank, (user_id, count))| tr!(
+                lang => "milestones.contributor", rank = rank + 1, user_id = *user_id, count = *count
+            ))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        announcements.send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(tr!(lang => "milestones.title"));
+                e.color(DEFAULT_COLOR);
+                e.description(match forest_id {
+                    0 => tr!(lang => "milestones.server", count = threshold),
+                    channel_id => tr!(lang => "milestones.channel", channel = *channel_id, count = threshold),
+                });
+                if !contributors.is_empty() {
+                    e.field(tr!(lang => "milestones.top_contributors"), &contributors, false);
+                }
+                e
+            })
+        }).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{server, CHANNEL_ID};
+
+    #[test]
+    fn milestones_are_reached_once() {
+        let mut server = server();
+        assert_eq!(reached(&server, CHANNEL_ID, 99), Vec::<i64>::new());
+        assert_eq!(reached(&server, CHANNEL_ID, 1000), vec![100, 1000]);
+
+        server.reached_milestones.insert((CHANNEL_ID, 100));
+        assert_eq!(reached(&server, CHANNEL_ID, 1000), vec![1000]);
+        assert_eq!(reached(&server, 0, 1000), vec![100, 1000]);
+    }
+}
@@ -1,7 +1,38 @@
-use std::collections::HashMap;
-use std::hash::Hash;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use crate::prelude::ParentedOneDatabaseCache;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::error::Error;
+use crate::prelude::ParentedCache;
+
+/// A single emoji grapheme: flags, keycaps, skin tones, tag sequences and ZWJ sequences
+pub const UNICODE_EMOJI_PATTERN: &str = concat!(
+    r"\p{Regional_Indicator}{2}|[0-9#*]\x{FE0F}?\x{20E3}|",
+    r"(?:\p{Extended_Pictographic}\p{Emoji_Modifier}|\p{Emoji_Presentation}\p{Emoji_Modifier}?|\p{Extended_Pictographic}\x{FE0F})",
+    r"(?:[\x{E0020}-\x{E007E}]+\x{E007F})?",
+    r"(?:\x{200D}(?:\p{Extended_Pictographic}\p{Emoji_Modifier}|\p{Emoji_Presentation}\p{Emoji_Modifier}?|\p{Extended_Pictographic}\x{FE0F}?))*",
+);
+
+lazy_static! {
+    static ref CUSTOM_EMOJI_REGEX: Regex = Regex::new(r"^<(a?):(\w+):(\d{17,20})>$").unwrap();
+    static ref UNICODE_EMOJI_REGEX: Regex = Regex::new(&format!("^(?:{})$", UNICODE_EMOJI_PATTERN)).unwrap();
+}
+
+/// Planting cooldown applying to a species in a channel, see `Server::cooldown_for`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResolvedCooldown {
+    /// Channel of the applied rule, 0 for all channels
+    pub channel_scope: u64,
+    /// Species of the applied rule, 0 for all species
+    pub species_scope: i16,
+    /// Cooldown in seconds, 0 to disable it
+    pub seconds: i16,
+}
 
 /// Per-channel boolean rules.
 #[derive(Debug)]
@@ -11,6 +42,23 @@ pub struct Rules {
 }
 
 impl Rules {
+    /// Build rules from (scope, allowance) pairs, scope 0 being the whole server
+    /// Allowed everywhere if no server rule is given
+    pub fn from_scopes<I: IntoIterator<Item = (u64, bool)>>(scopes: I) -> Rules {
+        let mut rules = Rules {
+            global: true,
+            channels: HashMap::new(),
+        };
+        for (scope, allowance) in scopes {
+            if scope == 0 {
+                rules.global = allowance;
+            } else {
+                rules.channels.insert(scope, allowance);
+            }
+        }
+        rules
+    }
+
     pub fn check(&self, channel_id: &u64) -> bool {
         if let Some(flag) = self.channels.get(channel_id) {
             *flag
@@ -27,25 +75,125 @@ pub struct Server {
     pub id: i64,
     /// Commands prefix
     pub prefix: String,
-    /// Language the bot should use in this server
-    /// Note: Only English is supported for now
+    /// Language the bot should use in this server, one of `i18n::LOCALES`
     pub lang: String,
 
     /// Channel rules where trees can be planted
     pub forest_rules: Rules,
     /// Cooldown between each tree planting
-    /// TODO: channel-based cooldown
     pub plant_cooldown: i16,
+    /// Cooldown overrides, mapped by (channel_id, species) where 0 means any
+    pub cooldown_overrides: HashMap<(u64, i16), i16>,
     /// Channel rules where commands can be executed
     pub commands_rules: Rules,
+    /// Maximum workers a player can hire
+    pub max_workers: i16,
+    /// Whether trees of this server count in global stats
+    pub global_forest: bool,
+    /// Channel milestones are announced in, None to announce them where they are reached
+    pub milestones_channel: Option<u64>,
+    /// Tree totals celebrated when a channel forest or the server forest reaches them, sorted
+    pub milestones: Vec<i64>,
+    /// Milestones already reached, as (channel_id, threshold) where channel 0 is the server forest
+    pub reached_milestones: HashSet<(u64, i64)>,
+    /// Coins a player can give away per day, 0 if coin gifts are disabled
+    pub daily_coin_gifts: i32,
+    /// Seedlings and pallets a player can give away per day, 0 if item gifts are disabled
+    pub daily_item_gifts: i32,
+    /// Rewards of the `daily` command, mapped by the streak day they are given from
+    /// Empty if daily rewards are disabled
+    pub daily_rewards: BTreeMap<i16, DailyReward>,
 
     /// All tree species, stored by id
     pub species: HashMap<i16, Species>,
     /// All tree species ids, stored by emoji
     /// Combine result with `species` to get the actual value if needed
-    pub species_from_emojis: HashMap<String, i16>,
+    pub species_from_emojis: HashMap<EmojiKey, i16>,
 
-    pub player_cache: ParentedOneDatabaseCache<u64, u64, Player>,
+    pub player_cache: ParentedCache<u64, u64, Player>,
+}
+
+impl Server {
+    /// Rebuild `species_from_emojis` from `species`
+    /// Has to be called after any species change, disabled species are left out
+    pub fn index_species(&mut self) {
+        self.species_from_emojis = self.species.values()
+            .filter(|species| species.enabled)
+            .map(|species| (species.emoji.clone(), species.id))
+            .collect();
+    }
+
+    /// Resolve the planting cooldown of a species in a channel
+    /// The most specific override wins: channel and species, then channel, then species
+    pub fn cooldown_for(&self, channel_id: u64, species_id: i16) -> ResolvedCooldown {
+        for &(channel_scope, species_scope) in [(channel_id, species_id), (channel_id, 0), (0, species_id)].iter() {
+            if let Some(seconds) = self.cooldown_overrides.get(&(channel_scope, species_scope)) {
+                return ResolvedCooldown { channel_scope, species_scope, seconds: *seconds };
+            }
+        }
+        ResolvedCooldown { channel_scope: 0, species_scope: 0, seconds: self.plant_cooldown }
+    }
+
+    /// Reward of a daily claim for a streak, the one of the closest streak day below
+    pub fn daily_reward(&self, streak: i32) -> Option<&DailyReward> {
+        self.daily_rewards.range(..=streak.min(i16::MAX as i32) as i16)
+            .next_back()
+            .map(|(_, reward)| reward)
+    }
+
+    /// Find a species from user input, being either its emoji, its id or its name
+    pub fn find_species(&self, query: &str) -> Option<&Species> {
+        let emoji = EmojiKey::from(query);
+        if let Some(species_id) = self.species.values()
+            .find(|species| species.emoji == emoji)
+            .map(|species| species.id)
+            .or_else(|| query.trim_start_matches('#').parse::<i16>().ok())
+        {
+            if let Some(species) = self.species.get(&species_id) {
+                return Some(species);
+            }
+        }
+        self.species.values().find(|species| species.name.eq_ignore_ascii_case(query))
+    }
+}
+
+/// Reward of the `daily` command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DailyReward {
+    /// Species of the given seedlings, None for coins
+    pub species: Option<i16>,
+    pub amount: i32,
+}
+
+/// Daily rewards of every new server, as (streak day, species or 0 for coins, amount)
+pub const DEFAULT_DAILY_REWARDS: [(i16, i16, i32); 7] = [
+    (1, 0, 10),
+    (2, 0, 15),
+    (3, 0, 20),
+    (4, 0, 25),
+    (5, 2, 5),
+    (6, 0, 30),
+    (7, 0, 50),
+];
+
+impl DailyReward {
+    /// Rewards given to every new server (see `DEFAULT_DAILY_REWARDS`)
+    pub fn defaults() -> BTreeMap<i16, DailyReward> {
+        DEFAULT_DAILY_REWARDS.iter()
+            .map(|(streak, species, amount)| (*streak, DailyReward {
+                species: if *species == 0 { None } else { Some(*species) },
+                amount: *amount,
+            }))
+            .collect()
+    }
+
+    pub fn describe(&self, server: &Server) -> String {
+        match self.species.map(|species_id| server.species.get(&species_id)) {
+            None => eformat!("**{}** /:coin/", self.amount),
+            Some(Some(species)) => format!("**{}** :seedling:{} {}", self.amount, species.emoji, species.name),
+            Some(None) => format!("**{}** :seedling:?", self.amount),
+        }
+    }
 }
 
 /// A tree species
@@ -53,28 +201,152 @@ pub struct Server {
 /// Note: `id` should be unsigned but is stored as i16 to use in queries without casting
 #[derive(Debug, Clone)]
 pub struct Species {
-    /// Incremental id (SERIAL type in Postgres)
+    /// Identifier unique per server (see trees.species)
     pub id: i16,
     /// Discord emoji representation
-    pub emoji: String,
+    pub emoji: EmojiKey,
     /// Displayable name
     pub name: String,
 
     /// Cost per unit in a pallet
     /// Set to 0 to disable purchase
     pub pallet_cost: i32,
+    /// Seedlings obtained by unpacking a pallet
+    pub pallet_qty: i32,
     /// Default quantity of seedlings a player will get by default
     /// Set to -1 to give infinite seedlings
     pub default_qty: i32,
     /// Coins given for each tree planted
     /// Set to 0 to disable (obviously)
     pub coins: i32,
+    /// Seedlings grown per hour in a greenhouse slot
+    /// Set to 0 to forbid growing this species in greenhouses
+    pub growth_rate: i32,
+    /// Disabled species can't be planted nor bought
+    /// but are kept around for existing trees and storage
+    pub enabled: bool,
+}
+
+/// Species given to every new server, as (id, emoji, name, pallet_cost, pallet_qty, default_qty, coins)
+pub const DEFAULT_SPECIES: [(i16, &str, &str, i32, i32, i32, i32); 5] = [
+    (1, "🌲", "Evergreen Tree", 0, 10, -1, 1),
+    (2, "🌳", "Deciduous Tree", 12, 10, 50, 1),
+    (3, "🌴", "Palm Tree", 15, 10, 30, 1),
+    (4, "🌵", "Cactus", 25, 10, 20, 2),
+    (5, "🎍", "Bamboo", 50, 10, 10, 3),
+];
+
+impl Species {
+    /// Species given to every new server (see `DEFAULT_SPECIES`)
+    pub fn defaults() -> Vec<Species> {
+        DEFAULT_SPECIES.iter()
+            .map(|(id, emoji, name, pallet_cost, pallet_qty, default_qty, coins)| Species {
+                id: *id,
+                emoji: EmojiKey::from(*emoji),
+                name: name.to_string(),
+                pallet_cost: *pallet_cost,
+                pallet_qty: *pallet_qty,
+                default_qty: *default_qty,
+                coins: *coins,
+                growth_rate: DEFAULT_GROWTH_RATE,
+                enabled: true,
+            })
+            .collect()
+    }
+
+    /// Whether an id is the one of a default species, the same in every server
+    /// Other ids are only meaningful in their server
+    pub fn is_default_id(species_id: i16) -> bool {
+        DEFAULT_SPECIES.iter().any(|(id, ..)| *id == species_id)
+    }
+}
+
+/// Growth rate of new species, matching the `species.growth_rate` column default
+pub const DEFAULT_GROWTH_RATE: i32 = 6;
+/// Seedlings in a pallet of new species, matching the `species.pallet_qty` column default
+pub const DEFAULT_PALLET_QTY: i32 = 10;
+
+/// A Discord emoji, either a unicode one or a guild custom emoji
+///
+/// Custom emojis are compared by id only, their name and animated flag
+/// are only kept to render them
+#[derive(Debug, Clone)]
+pub enum EmojiKey {
+    Unicode(String),
+    Custom {
+        id: u64,
+        name: String,
+        animated: bool,
+    },
+}
+
+impl EmojiKey {
+    /// Parse user input, returns None if it isn't a single emoji
+    pub fn parse(input: &str) -> Option<EmojiKey> {
+        if CUSTOM_EMOJI_REGEX.is_match(input) || UNICODE_EMOJI_REGEX.is_match(input) {
+            Some(EmojiKey::from(input))
+        } else {
+            None
+        }
+    }
+
+    pub fn custom_id(&self) -> Option<u64> {
+        match self {
+            EmojiKey::Custom { id, .. } => Some(*id),
+            EmojiKey::Unicode(_) => None,
+        }
+    }
+}
+
+impl From<&str> for EmojiKey {
+    fn from(value: &str) -> EmojiKey {
+        if let Some(captures) = CUSTOM_EMOJI_REGEX.captures(value) {
+            if let Ok(id) = captures[3].parse::<u64>() {
+                return EmojiKey::Custom {
+                    id,
+                    name: captures[2].to_owned(),
+                    animated: !captures[1].is_empty(),
+                };
+            }
+        }
+        EmojiKey::Unicode(value.to_owned())
+    }
+}
+
+impl PartialEq for EmojiKey {
+    fn eq(&self, other: &EmojiKey) -> bool {
+        match (self, other) {
+            (EmojiKey::Unicode(a), EmojiKey::Unicode(b)) => a == b,
+            (EmojiKey::Custom { id: a, .. }, EmojiKey::Custom { id: b, .. }) => a == b,
+            _ => false
+        }
+    }
+}
+
+impl Eq for EmojiKey {}
+
+impl Hash for EmojiKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            EmojiKey::Unicode(emoji) => emoji.hash(state),
+            EmojiKey::Custom { id, .. } => id.hash(state),
+        }
+    }
+}
+
+impl fmt::Display for EmojiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmojiKey::Unicode(emoji) => write!(f, "{}", emoji),
+            EmojiKey::Custom { id, name, animated } => write!(f, "<{}:{}:{}>", if *animated { "a" } else { "" }, name, id),
+        }
+    }
 }
 
 pub type Storage = HashMap<(ItemType, i16), i32>;
 
 /// A representation of a server player with all its linked data cached
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Player {
     /// Unique identifier (different per user and per server)
     pub id: i32,
@@ -85,24 +357,51 @@ pub struct Player {
 
     /// Player's wallet
     pub coins: i32,
+    /// Shed tier, starting at 1 (see `SHED_TIERS`)
+    pub storage_upgrade: i16,
     /// All items in storage
     /// Mapped by (type, id) and gives the amount
     /// Reminder: -1 = infinity
     pub storage: Storage,
 
+    /// Greenhouse tier, starting at 1 (see `GREENHOUSE_TIERS`)
+    pub greenhouse_upgrade: i16,
+    /// Filled greenhouse slots, mapped by slot number
+    pub greenhouse: Greenhouse,
+    /// Hired workers, ordered by id
+    pub workers: Vec<Worker>,
+    /// Last time time-based actions were computed
+    /// Matches `players.last_time_check` in the database
+    pub last_time_check: Instant,
+    /// Ids of unlocked achievements (see `achievements::ACHIEVEMENTS`)
+    pub achievements: HashSet<String>,
+    /// Consecutive days the daily reward has been claimed, as of the last claim
+    pub daily_streak: i32,
+    /// Day of the last daily claim (see `day`)
+    pub last_daily: Option<u64>,
+
     _newly_created: bool,
 }
 
 impl Player {
-    pub fn new(id: i32, user_id: u64, guild_id: u64, coins: i32, storage: Storage, new: bool) -> Player {
+    pub fn new(id: i32, user_id: u64, guild_id: u64, coins: i32, storage_upgrade: i16, storage: Storage, new: bool) -> Player {
         Player {
             id,
             user_id,
             guild_id,
             
             coins,
+            storage_upgrade,
             storage,
 
+            greenhouse_upgrade: 1,
+            greenhouse: HashMap::new(),
+            workers: Vec::new(),
+            last_time_check: Instant::now(),
+            achievements: HashSet::new(),
+            daily_streak: 0,
+            last_daily: None,
+
             _newly_created: new,
         }
     }
@@ -111,25 +410,527 @@ impl Player {
         self._newly_created
     }
 
-    pub fn give_item(&mut self, item_type: ItemType, item_id: i16, qty: i32) -> i32 {
-        let new_qty = *self.storage.get(&(item_type, item_id)).unwrap_or(&0) + qty;
+    pub fn shed(&self) -> &'static ShedTier {
+        let tier = (self.storage_upgrade.max(1) as usize - 1).min(SHED_TIERS.len() - 1);
+        &SHED_TIERS[tier]
+    }
+
+    /// Next shed tier the player can upgrade to, if any
+    pub fn next_shed(&self) -> Option<&'static ShedTier> {
+        SHED_TIERS.get(self.storage_upgrade.max(1) as usize)
+    }
+
+    /// Amount of stored items of a type taking space in the shed
+    /// Infinite amounts and empty pallets (id 0) are not counted
+    pub fn stored(&self, item_type: ItemType) -> i32 {
+        self.storage.iter()
+            .filter(|((kind, item_id), amount)| *kind == item_type && *item_id != 0 && **amount > 0)
+            .map(|(_, amount)| *amount)
+            .sum()
+    }
+
+    pub fn free_space(&self, item_type: ItemType) -> i32 {
+        let capacity = match item_type {
+            ItemType::Pallet => self.shed().max_pallets,
+            ItemType::Seedling => self.shed().max_seedlings,
+        };
+        (capacity - self.stored(item_type)).max(0)
+    }
+
+    /// Check whether some items can be added to the storage without exceeding the shed capacity
+    pub fn can_store(&self, item_type: ItemType, item_id: i16, qty: i32) -> Result<(), StorageFull> {
+        if qty <= 0 || item_id == 0 || self.storage.get(&(item_type, item_id)) == Some(&-1) {
+            return Ok(());
+        }
+        let free = self.free_space(item_type);
+        if qty > free {
+            Err(StorageFull { item_type, free })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Add (or remove with a negative quantity) items to the storage
+    /// Infinite (-1) amounts stay infinite, additions over the shed capacity are refused
+    pub fn give_item(&mut self, item_type: ItemType, item_id: i16, qty: i32) -> Result<i32, StorageFull> {
+        self.can_store(item_type, item_id, qty)?;
+        let new_qty = match *self.storage.get(&(item_type, item_id)).unwrap_or(&0) {
+            -1 => -1,
+            current => current + qty,
+        };
         self.storage.insert((item_type, item_id), new_qty);
-        new_qty
+        Ok(new_qty)
+    }
+
+    pub fn greenhouse_tier(&self) -> &'static GreenhouseTier {
+        let tier = (self.greenhouse_upgrade.max(1) as usize - 1).min(GREENHOUSE_TIERS.len() - 1);
+        &GREENHOUSE_TIERS[tier]
+    }
+
+    /// Next greenhouse tier the player can upgrade to, if any
+    pub fn next_greenhouse(&self) -> Option<&'static GreenhouseTier> {
+        GREENHOUSE_TIERS.get(self.greenhouse_upgrade.max(1) as usize)
+    }
+
+    /// First empty greenhouse slot number, if any
+    pub fn free_greenhouse_slot(&self) -> Option<i16> {
+        (1..=self.greenhouse_tier().slots).find(|slot| !self.greenhouse.contains_key(slot))
+    }
+
+    /// Cost of the next worker, each one being more expensive than the previous
+    pub fn worker_cost(&self) -> i32 {
+        WORKER_COST * (self.workers.len() as i32 + 1)
+    }
+
+    /// Compute time-based actions since the last time check
+    /// Storage and coins are updated, the returned report has to be persisted
+    ///
+    /// Note: Should be computed on a copy of the cached player, see `apply_time_check`
+    pub fn time_check(&mut self, species: &HashMap<i16, Species>, forest_rules: &Rules) -> TimeCheck {
+        self.time_check_at(Instant::now(), species, forest_rules)
+    }
+
+    fn time_check_at(&mut self, now: Instant, species: &HashMap<i16, Species>, forest_rules: &Rules) -> TimeCheck {
+        let elapsed = (now - self.last_time_check).as_secs_f64();
+        self.last_time_check = now;
+
+        for slot in self.greenhouse.values_mut() {
+            let growth_rate = species.get(&slot.species).map(|species| species.growth_rate).unwrap_or(0);
+            slot.grow(growth_rate, elapsed);
+        }
+
+        let mut report = TimeCheck {
+            elapsed,
+            planted: Vec::new(),
+            coins: 0,
+        };
+        for worker in self.workers.iter_mut() {
+            let channel_id = match worker.channel_id {
+                Some(channel_id) if forest_rules.check(&channel_id) => channel_id,
+                _ => {
+                    worker.progress = 0.0;
+                    continue;
+                }
+            };
+
+            // workers stop after a while when nobody checks on them
+            let worked = elapsed.min(WORKER_MAX_CATCH_UP as f64);
+            let progress = worker.progress as f64 + worked / WORKER_PLANT_INTERVAL as f64;
+            let trees = progress.floor() as i64;
+            worker.progress = (progress - trees as f64) as f32;
+            for _ in 0..trees {
+                // workers use the most abundant seedlings first, infinite ones are only for manual planting
+                let seedling = self.storage.iter()
+                    .filter(|((item_type, item_id), amount)| *item_type == ItemType::Seedling && **amount > 0
+                        && species.get(item_id).map_or(false, |species| species.enabled))
+                    .max_by_key(|(_, amount)| **amount)
+                    .map(|((_, item_id), amount)| (*item_id, *amount));
+                let species_id = match seedling {
+                    Some((species_id, amount)) => {
+                        self.storage.insert((ItemType::Seedling, species_id), amount - 1);
+                        species_id
+                    }
+                    None => {
+                        worker.progress = 0.0;
+                        break;
+                    }
+                };
+
+                let coins = species.get(&species_id).unwrap().coins;
+                self.coins += coins;
+                report.coins += coins;
+                match report.planted.iter_mut().find(|(c, s, _)| *c == channel_id && *s == species_id) {
+                    Some((_, _, count)) => *count += 1,
+                    None => report.planted.push((channel_id, species_id, 1)),
+                }
+            }
+        }
+        report
+    }
+
+    /// Apply a persisted time check computed on a copy of this player
+    /// Greenhouse slots and workers removed in the meantime are not brought back
+    pub fn apply_time_check(&mut self, report: &TimeCheck, checked: &Player) {
+        self.coins += report.coins;
+        for (_, species_id, count) in report.planted.iter() {
+            if let Some(amount) = self.storage.get_mut(&(ItemType::Seedling, *species_id)) {
+                if *amount > 0 {
+                    *amount = (*amount - count).max(0);
+                }
+            }
+        }
+        for (slot_id, slot) in checked.greenhouse.iter() {
+            if let Some(cached) = self.greenhouse.get_mut(slot_id).filter(|cached| cached.species == slot.species) {
+                *cached = slot.clone();
+            }
+        }
+        for worker in checked.workers.iter() {
+            if let Some(cached) = self.workers.iter_mut().find(|cached| cached.id == worker.id) {
+                cached.progress = worker.progress;
+            }
+        }
+    }
+}
+
+/// Result of a time check, see `Player::time_check`
+#[derive(Debug)]
+pub struct TimeCheck {
+    /// Elapsed time since the previous time check, in seconds
+    pub elapsed: f64,
+    /// Trees planted by workers, as (channel_id, species, count)
+    pub planted: Vec<(u64, i16, i32)>,
+    /// Coins earned by workers
+    pub coins: i32,
+}
+
+/// A worker hired by a player, planting trees in a forest channel
+#[derive(Debug, Clone)]
+pub struct Worker {
+    /// Incremental id (SERIAL type in Postgres)
+    pub id: i32,
+    /// Forest channel to plant trees in, unassigned workers don't do anything
+    pub channel_id: Option<u64>,
+    /// Progress towards the next planted tree, between 0 and 1
+    pub progress: f32,
+}
+
+/// Seconds needed by a worker to plant a tree
+pub const WORKER_PLANT_INTERVAL: u64 = 600;
+/// Longest absence, in seconds, workers keep planting during
+pub const WORKER_MAX_CATCH_UP: u64 = 86400;
+/// Coins needed to hire a worker, multiplied by the amount of workers once hired
+pub const WORKER_COST: i32 = 150;
+
+pub type Greenhouse = HashMap<i16, GreenhouseSlot>;
+
+/// A filled greenhouse slot, producing seedlings of a species
+#[derive(Debug, Clone)]
+pub struct GreenhouseSlot {
+    pub species: i16,
+    /// Seedlings still to grow
+    pub remaining: i32,
+    /// Grown seedlings waiting to be collected
+    pub grown: i32,
+    /// Progress towards the next seedling, between 0 and 1
+    pub progress: f32,
+}
+
+impl GreenhouseSlot {
+    /// Grow seedlings for the given duration
+    pub fn grow(&mut self, growth_rate: i32, elapsed_secs: f64) {
+        if growth_rate <= 0 || self.remaining <= 0 {
+            return;
+        }
+        let progress = self.progress as f64 + elapsed_secs * growth_rate as f64 / 3600.0;
+        let grown = (progress.floor() as i64).min(self.remaining as i64) as i32;
+        self.grown += grown;
+        self.remaining -= grown;
+        self.progress = if self.remaining > 0 {
+            (progress - grown as f64) as f32
+        } else {
+            0.0
+        };
+    }
+
+    /// Whether the slot has nothing left to grow nor to collect
+    pub fn is_done(&self) -> bool {
+        self.remaining <= 0 && self.grown <= 0
     }
 }
 
+/// Seedlings grown from a pallet in a greenhouse, as a multiplier of its unpacked quantity
+pub const GREENHOUSE_YIELD: i32 = 2;
+
+/// A greenhouse size, upgraded with the greenhouse command
+pub struct GreenhouseTier {
+    /// Name, displayed through the `tiers.<name>` message
+    pub name: &'static str,
+    pub slots: i16,
+    /// Coins needed to upgrade to this tier
+    pub cost: i32,
+}
+
+impl GreenhouseTier {
+    pub fn display_name(&self, lang: &str) -> String {
+        tr!(lang => &format!("tiers.{}", self.name))
+    }
+}
+
+pub const GREENHOUSE_TIERS: [GreenhouseTier; 4] = [
+    GreenhouseTier { name: "tiny", slots: 1, cost: 0 },
+    GreenhouseTier { name: "small", slots: 2, cost: 300 },
+    GreenhouseTier { name: "medium", slots: 3, cost: 900 },
+    GreenhouseTier { name: "large", slots: 4, cost: 2000 },
+];
+
+/// A shed size, players start with the first one and can upgrade it in the shop
+pub struct ShedTier {
+    /// Name, displayed through the `tiers.<name>` message
+    pub name: &'static str,
+    pub max_pallets: i32,
+    pub max_seedlings: i32,
+    /// Coins needed to upgrade to this tier
+    pub cost: i32,
+}
+
+impl ShedTier {
+    pub fn display_name(&self, lang: &str) -> String {
+        tr!(lang => &format!("tiers.{}", self.name))
+    }
+}
+
+pub const SHED_TIERS: [ShedTier; 4] = [
+    ShedTier { name: "small", max_pallets: 50, max_seedlings: 250, cost: 0 },
+    ShedTier { name: "medium", max_pallets: 100, max_seedlings: 500, cost: 400 },
+    ShedTier { name: "large", max_pallets: 200, max_seedlings: 1000, cost: 1200 },
+    ShedTier { name: "huge", max_pallets: 400, max_seedlings: 2500, cost: 3500 },
+];
+
+/// Refused storage addition
+#[derive(Debug)]
+pub struct StorageFull {
+    pub item_type: ItemType,
+    /// Space left in the shed for this item type
+    pub free: i32,
+}
+
+impl StorageFull {
+    /// Explain the refusal in the given locale
+    pub fn describe(&self, lang: &str) -> String {
+        tr!(lang => match self.item_type {
+            ItemType::Pallet => "storage.full.pallets",
+            ItemType::Seedling => "storage.full.seedlings",
+        }, count = self.free)
+    }
+}
+
+/// Why coins or items of a player changed, as recorded in the ledger
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// Balance of the player when the ledger has been created
+    Opening,
+    /// Default seedlings of new players
+    Starter,
+    Plant,
+    Purchase,
+    Reward,
+    Trade,
+    Gift,
+    /// Trees planted by workers
+    Work,
+    /// Items put in greenhouse slots and seedlings collected from them
+    Greenhouse,
+    Unpack,
+    /// Rewards of the `daily` command
+    Daily,
+    /// Changes made outside of the game rules
+    Admin,
+}
+
+impl Reason {
+    /// Name stored in `ledger.reason`, also used for translations
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::Opening => "opening",
+            Reason::Starter => "starter",
+            Reason::Plant => "plant",
+            Reason::Purchase => "purchase",
+            Reason::Reward => "reward",
+            Reason::Trade => "trade",
+            Reason::Gift => "gift",
+            Reason::Work => "work",
+            Reason::Greenhouse => "greenhouse",
+            Reason::Unpack => "unpack",
+            Reason::Daily => "daily",
+            Reason::Admin => "admin",
+        }
+    }
+}
+
+impl TryFrom<&str> for Reason {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Reason, Error> {
+        match value {
+            "opening" => Ok(Reason::Opening),
+            "starter" => Ok(Reason::Starter),
+            "plant" => Ok(Reason::Plant),
+            "purchase" => Ok(Reason::Purchase),
+            "reward" => Ok(Reason::Reward),
+            "trade" => Ok(Reason::Trade),
+            "gift" => Ok(Reason::Gift),
+            "work" => Ok(Reason::Work),
+            "greenhouse" => Ok(Reason::Greenhouse),
+            "unpack" => Ok(Reason::Unpack),
+            "daily" => Ok(Reason::Daily),
+            "admin" => Ok(Reason::Admin),
+            v => Err(Error::Corrupted(format!("unknown ledger reason {}", v))),
+        }
+    }
+}
+
+/// Change of the coins or items of a player, as recorded in the ledger
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerEntry {
+    /// Changed item as (item type, species), None for coins
+    pub item: Option<(ItemType, i16)>,
+    pub delta: i32,
+    pub reason: Reason,
+    pub created_at: SystemTime,
+}
+
+/// Days since the epoch, changing at midnight (UTC)
+pub fn day(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs() / 86400).unwrap_or(0)
+}
+
+/// Time window of stats, days being UTC days (see `day`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Today,
+    Week,
+    Month,
+    All,
+}
+
+impl Window {
+    /// Days included in the window, today being one of them, None for all-time stats
+    pub fn days(self) -> Option<u64> {
+        match self {
+            Window::Today => Some(1),
+            Window::Week => Some(7),
+            Window::Month => Some(30),
+            Window::All => None,
+        }
+    }
+}
+
+/// Forests trees are counted in for stats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeScope {
+    Channel(u64),
+    Server(u64),
+    /// Every server which didn't opt out of global stats, and the Official Forest channel if there is one
+    /// Ids of custom species are only meaningful in their server, so they are all counted as species 0
+    Global { official_forest: Option<u64> },
+}
+
+/// Planted trees counted in stats
+#[derive(Debug, Clone, Copy)]
+pub struct TreeFilter {
+    pub scope: TreeScope,
+    pub window: Window,
+    /// Only the trees planted by a user
+    pub user_id: Option<u64>,
+    /// Only the trees of a species
+    pub species: Option<i16>,
+}
+
+impl TreeFilter {
+    pub fn new(scope: TreeScope, window: Window) -> TreeFilter {
+        TreeFilter { scope, window, user_id: None, species: None }
+    }
+}
+
+/// Trees of a species in stats
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesCount {
+    pub species: i16,
+    pub count: i64,
+    /// Share of the counted trees, in percent rounded to two decimals
+    pub percent: f64,
+}
+
+/// A player ranked in a leaderboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranked {
+    /// Starting at 1
+    pub rank: usize,
+    pub user_id: u64,
+    /// Planted trees, or coins
+    pub total: i64,
+    /// Share of the counted trees, in percent rounded to two decimals (0 for coins)
+    pub percent: f64,
+    /// Species and channel the player planted the most trees of together, as (species, channel_id) (None for coins)
+    pub favorite: Option<(i16, u64)>,
+}
+
+/// Coins or items given by a player to another one, as recorded in the transfers ledger
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub giver_id: u64,
+    pub receiver_id: u64,
+    /// Given item as (item type, species), None for coins
+    pub item: Option<(ItemType, i16)>,
+    pub amount: i32,
+    pub created_at: SystemTime,
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum ItemType {
     Pallet = 1,
     Seedling,
 }
 
-impl ItemType {
-    pub fn from_i16(value: i16) -> ItemType {
+impl TryFrom<i16> for ItemType {
+    type Error = Error;
+
+    fn try_from(value: i16) -> Result<ItemType, Error> {
         match value {
-            1 => ItemType::Pallet,
-            2 => ItemType::Seedling,
-            v@_ => panic!("Unknown value: {}", v)            
+            1 => Ok(ItemType::Pallet),
+            2 => Ok(ItemType::Seedling),
+            v => Err(Error::Corrupted(format!("unknown item type {}", v))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn working_player(seedlings: i32) -> (Player, HashMap<i16, Species>) {
+        let species = Species::defaults().into_iter().map(|species| (species.id, species)).collect();
+        let storage = vec![((ItemType::Seedling, 1), seedlings)].into_iter().collect();
+        let mut player = Player::new(1, 1, 1, 0, 1, storage, false);
+        player.workers.push(Worker { id: 1, channel_id: Some(10), progress: 0.0 });
+        (player, species)
+    }
+
+    #[test]
+    fn workers_dont_plant_infinite_seedlings() {
+        let (mut player, species) = working_player(-1);
+        let now = player.last_time_check + Duration::from_secs(WORKER_PLANT_INTERVAL * 10);
+        let report = player.time_check_at(now, &species, &Rules::from_scopes(vec![]));
+        assert!(report.planted.is_empty());
+        assert_eq!(report.coins, 0);
+        assert_eq!(player.storage.get(&(ItemType::Seedling, 1)), Some(&-1));
+    }
+
+    #[test]
+    fn workers_catch_up_for_a_limited_time() {
+        let (mut player, species) = working_player(100_000);
+        let now = player.last_time_check + Duration::from_secs(30 * 86400);
+        let report = player.time_check_at(now, &species, &Rules::from_scopes(vec![]));
+        let trees = (WORKER_MAX_CATCH_UP / WORKER_PLANT_INTERVAL) as i32;
+        assert_eq!(report.planted, vec![(10, 1, trees)]);
+        assert_eq!(report.coins, trees * species[&1].coins);
+        assert_eq!(player.storage.get(&(ItemType::Seedling, 1)), Some(&(100_000 - trees)));
+    }
+
+    #[test]
+    fn single_emojis_are_parsed() {
+        for emoji in &["🌳", "👍🏽", "🇫🇷", "1️⃣", "☘️", "👩‍🌾", "🏳️‍🌈", "🏴󠁧󠁢󠁳󠁣󠁴󠁿"] {
+            assert_eq!(EmojiKey::parse(emoji), Some(EmojiKey::Unicode(emoji.to_string())), "{}", emoji);
+        }
+        assert!(EmojiKey::parse("<:oak:123456789012345678>").is_some());
+    }
+
+    #[test]
+    fn other_inputs_are_not_emojis() {
+        for input in &["", "a", "1", "🌳🌲", "🌳 ", "👩‍", "<:oak:12>"] {
+            assert_eq!(EmojiKey::parse(input), None, "{}", input);
         }
     }
 }
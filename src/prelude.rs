@@ -1,14 +1,14 @@
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::time::Instant;
+use std::sync::Arc;
 
-use futures::future::BoxFuture;
 use serenity::prelude::TypeMapKey;
-use sqlx::postgres::PgPool;
 
 use serenity::model::channel::ReactionType;
 
 pub use crate::models::*;
+pub use crate::cooldown::CooldownStore;
+pub use crate::store::Store;
 
 macro_rules! eformat {
     ($lit:expr) => {
@@ -22,36 +22,40 @@ macro_rules! eformat {
 macro_rules! quick_init {
     (
         $ctx:ident -> $data:ident $(~$to_drop:ident~)*
-        $(=> $(p:$pool:ident)? $(sc:$sc:ident)?)?
+        $(=> $(st:$st:ident)? $(sc:$sc:ident)? $(pc:$pc:ident)?)?
         $([<- $(bi:$bi:ident)?])?
         $(; $msg:ident => $(s:$server:ident $([$player:ident])?)?)?
     ) => {
         $( std::mem::drop($to_drop); )*
         let $data = $ctx.data.read().await;
         $(
-            $( let $pool = $data.get::<crate::prelude::DatabaseConnection>().unwrap(); )?
+            $( let $st = $data.get::<crate::prelude::DataStore>().unwrap().clone(); )?
             $( let $sc = $data.get::<crate::prelude::ServerCache>().unwrap(); )?
+            $( let $pc = $data.get::<crate::prelude::PlantCooldown>().unwrap(); )?
         )?
         $(
             $( let $bi = $ctx.cache.current_user_id().await; )?
         )?
         $(
             $( 
-                let $server = $data.get::<crate::prelude::ServerCache>().unwrap().get(&$msg.guild_id.unwrap().0).unwrap();
-                $( let $player = $server.player_cache.get(&$msg.author.id.0).unwrap(); )?
+                let $server = match $msg.guild_id {
+                    Some(guild_id) => $data.get::<crate::prelude::ServerCache>().unwrap().get(&guild_id.0),
+                    None => None,
+                }.ok_or(crate::error::Error::Missing("server"))?;
+                $( let $player = $server.player_cache.get(&$msg.author.id.0).ok_or(crate::error::Error::Missing("player"))?; )?
             )?
         )?
     };
     (
         $ctx:ident $(~$to_drop:ident~)*
-        $(=> $(p:$pool:ident)? $(sc:$sc:ident)?)?
+        $(=> $(st:$st:ident)? $(sc:$sc:ident)? $(pc:$pc:ident)?)?
         $([<- $(bi:$bi:ident)?])?
         $(; $msg:ident => $(s:$server:ident $([$player:ident])?)?)?
     ) => {
         quick_init!(
             $ctx -> _data $(~$to_drop~)*
             $(
-                => $(p:$pool)? $(sc:$sc)?
+                => $(st:$st)? $(sc:$sc)? $(pc:$pc)?
             )?
             $(
                 [<- $(bi:$bi)?]
@@ -63,64 +67,74 @@ macro_rules! quick_init {
     };
     (
         $ctx:ident -> mut $data:ident $(~$to_drop:ident~)*
-        $(=> $(sc:$sc:ident)? $(pc:$pc:ident)?)?
+        $(=> $(sc:$sc:ident)?)?
         $(; $msg:ident => $(s:$server:ident $([$player:ident])?)?)?
     ) => {
         $( std::mem::drop($to_drop); )*
         let mut $data = $ctx.data.write().await;
         $(
             $( let $sc = $data.get_mut::<crate::prelude::ServerCache>().unwrap(); )?
-            $( let $pc = $data.get_mut::<crate::prelude::PlantCooldown>().unwrap(); )?
         )?
         $(
             $( 
-                let $server = $data.get_mut::<crate::prelude::ServerCache>().unwrap().0.get_mut(&$msg.guild_id.unwrap().0).unwrap();
-                $( let $player = $server.player_cache.1.get_mut(&$msg.author.id.0).unwrap(); )?
+                let $server = match $msg.guild_id {
+                    Some(guild_id) => $data.get_mut::<crate::prelude::ServerCache>().unwrap().0.get_mut(&guild_id.0),
+                    None => None,
+                }.ok_or(crate::error::Error::Missing("server"))?;
+                $( let $player = $server.player_cache.1.get_mut(&$msg.author.id.0).ok_or(crate::error::Error::Missing("player"))?; )?
             )?
         )?
     };
 }
 
+/// Get a message from the catalog of a locale
+/// e.g. `tr!(server.lang => "settings.prefix.current", prefix = server.prefix)`
+macro_rules! tr {
+    ($lang:expr => $key:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        crate::i18n::translate(&$lang, $key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*])
+    };
+}
+
 macro_rules! success {
-    ($ctx:ident, $channel:expr => $msg:expr, $($arg:tt)*) => ({
+    ($ctx:ident, $channel:expr, $lang:expr => $key:expr $(, $name:ident = $value:expr)* $(,)?) => ({
+        let title = tr!($lang => "embed.success");
+        let description = format!(":white_check_mark: {}", tr!($lang => $key $(, $name = $value)*));
         let _ = $channel.send_message(&$ctx.http, |m| {
             m.embed(|e| e
-                .title("Success")
+                .title(title)
                 .color(0x4CAF50)
-                .description(format!(":white_check_mark: {}", eformat!($msg, $($arg)*)))
+                .description(description)
             )
         }).await?;
     });
 }
 
 macro_rules! info {
-    ($ctx:ident, $channel:expr => $msg:expr, $($arg:tt)*) => ({
+    ($ctx:ident, $channel:expr, $lang:expr => ($prefix:expr) $key:expr $(, $name:ident = $value:expr)* $(,)?) => ({
+        let title = tr!($lang => "embed.info");
+        let description = format!("{} {}", $prefix, tr!($lang => $key $(, $name = $value)*));
         let _ = $channel.send_message(&$ctx.http, |m| {
             m.embed(|e| e
-                .title("Information")
+                .title(title)
                 .color(0x2196F3)
-                .description(format!(":information_source: {}", eformat!($msg, $($arg)*)))
+                .description(description)
             )
         }).await?;
     });
-    ($ctx:ident, $channel:expr => ($prefix:expr) $msg:expr, $($arg:tt)*) => ({
-        let _ = $channel.send_message(&$ctx.http, |m| {
-            m.embed(|e| e
-                .title("Information")
-                .color(0x2196F3)
-                .description(format!("{} {}", $prefix, eformat!($msg, $($arg)*)))
-            )
-        }).await?;
+    ($ctx:ident, $channel:expr, $lang:expr => $key:expr $(, $name:ident = $value:expr)* $(,)?) => ({
+        info!($ctx, $channel, $lang => (":information_source:") $key $(, $name = $value)*);
     });
 }
 
 macro_rules! error {
-    ($ctx:ident, $channel:expr => $msg:expr, $($arg:tt)*) => ({
+    ($ctx:ident, $channel:expr, $lang:expr => $key:expr $(, $name:ident = $value:expr)* $(,)?) => ({
+        let title = tr!($lang => "embed.error");
+        let description = format!(":x: {}", tr!($lang => $key $(, $name = $value)*));
         let _ = $channel.send_message(&$ctx.http, |m| {
             m.embed(|e: &mut CreateEmbed| e
-                .title("Error")
+                .title(title)
                 .color(0xFF5722)
-                .description(format!(":x: {}", eformat!($msg, $($arg)*)))
+                .description(description)
             )
         }).await?;
     });
@@ -145,35 +159,42 @@ lazy_static::lazy_static! {
     };
 }
 
-pub struct DatabaseConnection;
-
-impl TypeMapKey for DatabaseConnection {
-    type Value = PgPool;
-}
-
 // servers and players can be cached as they will never be shared (for writing) across shards
+// cooldowns are the exception and go through a `CooldownStore` that can be shared
 pub struct ServerCache;
 
 impl TypeMapKey for ServerCache {
-    type Value = OneDatabaseCache<u64, Server>;
+    type Value = Cache<u64, Server>;
+}
+
+pub struct DataStore;
+
+impl TypeMapKey for DataStore {
+    type Value = Arc<dyn Store>;
 }
 
 pub struct PlantCooldown;
 
 impl TypeMapKey for PlantCooldown {
-    type Value = HashMap<u64, Instant>;
+    type Value = Arc<dyn CooldownStore>;
+}
+
+/// Channel of the Official Forest, whose trees always count in global stats
+pub struct OfficialForest;
+
+impl TypeMapKey for OfficialForest {
+    type Value = u64;
 }
 
-type OneFetchHook<K, V> = for<'fut> fn(_: &'fut PgPool, _: &'fut K) -> BoxFuture<'fut, Option<V>>;
-pub struct OneDatabaseCache<K, V>(pub HashMap<K, V>, OneFetchHook<K, V>);
+/// Cache of values loaded once from the `Store`
+pub struct Cache<K, V>(pub HashMap<K, V>);
 
-impl<K, V> OneDatabaseCache<K, V> where 
+impl<K, V> Cache<K, V> where 
     K: Hash + Eq + Clone
 {
-    pub fn new(fetch_hook: OneFetchHook<K, V>) -> OneDatabaseCache<K, V> {
-        OneDatabaseCache {
+    pub fn new() -> Cache<K, V> {
+        Cache {
             0: HashMap::new(),
-            1: fetch_hook,
         }
     }
 
@@ -181,26 +202,29 @@ impl<K, V> OneDatabaseCache<K, V> where
         self.0.get(key)
     }
 
-    pub async fn fetch(&self, pool: &PgPool, key: &K) -> Option<V> {
-        self.1(pool, key).await
-    }
-
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
         self.0.insert(key, value)
     }
 }
 
-type ParentedOneFetchHook<P, K, V> = for<'fut> fn(_: &'fut PgPool, _: &'fut K, _: &'fut P) -> BoxFuture<'fut, Option<V>>;
-pub struct ParentedOneDatabaseCache<P, K, V>(pub P, pub HashMap<K, V>, ParentedOneFetchHook<P, K, V>);
+impl<K, V> Default for Cache<K, V> where
+    K: Hash + Eq + Clone
+{
+    fn default() -> Cache<K, V> {
+        Cache::new()
+    }
+}
 
-impl<P, K, V> ParentedOneDatabaseCache<P, K, V> where 
+/// Same as `Cache`, with values all belonging to a parent (e.g. players of a guild)
+pub struct ParentedCache<P, K, V>(pub P, pub HashMap<K, V>);
+
+impl<P, K, V> ParentedCache<P, K, V> where 
     K: Hash + Eq + Clone
 {
-    pub fn new(parent: P, fetch_hook: ParentedOneFetchHook<P, K, V>) -> ParentedOneDatabaseCache<P, K, V> {
-        ParentedOneDatabaseCache {
+    pub fn new(parent: P) -> ParentedCache<P, K, V> {
+        ParentedCache {
             0: parent,
             1: HashMap::new(),
-            2: fetch_hook,
         }
     }
 
@@ -208,10 +232,6 @@ impl<P, K, V> ParentedOneDatabaseCache<P, K, V> where
         self.1.get(key)
     }
 
-    pub async fn fetch(&self, pool: &PgPool, key: &K) -> Option<V> {
-        self.2(pool, key, &self.0).await
-    }
-
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
         self.1.insert(key, value)
     }
@@ -0,0 +1,189 @@
+use image::{
+    imageops::{self, FilterType},
+    png::PngEncoder,
+    ColorType, ImageFormat, Rgba, RgbaImage,
+};
+use lazy_static::lazy_static;
+
+use crate::error::Result;
+use crate::models::{EmojiKey, Species};
+
+pub const WIDTH: u32 = 640;
+pub const HEIGHT: u32 = 360;
+/// Trees drawn at most, bigger forests are drawn proportionally
+pub const MAX_TREES: u64 = 500;
+/// A forest is drawn on at least this many cells so that small forests look sparse
+const MIN_CELLS: u64 = 48;
+/// Size of a sprite in the bundled assets, in pixels
+const SPRITE_SIZE: u32 = 16;
+
+lazy_static! {
+    static ref SPRITES: Vec<RgbaImage> = [
+        &include_bytes!("../assets/sprites/evergreen.png")[..],
+        &include_bytes!("../assets/sprites/deciduous.png")[..],
+        &include_bytes!("../assets/sprites/palm.png")[..],
+        &include_bytes!("../assets/sprites/cactus.png")[..],
+        &include_bytes!("../assets/sprites/bamboo.png")[..],
+        &include_bytes!("../assets/sprites/sapling.png")[..],
+    ].iter()
+        .map(|bytes| image::load_from_memory_with_format(bytes, ImageFormat::Png)
+            .expect("bundled sprites are valid PNG files")
+            .to_rgba8())
+        .collect();
+}
+
+/// Sprite drawn for a species, indexes `SPRITES`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sprite {
+    Evergreen = 0,
+    Deciduous,
+    Palm,
+    Cactus,
+    Bamboo,
+    /// Custom emojis and removed species
+    Sapling,
+}
+
+impl Sprite {
+    pub fn for_species(species: Option<&Species>) -> Sprite {
+        match species.map(|species| &species.emoji) {
+            Some(EmojiKey::Unicode(emoji)) => match emoji.as_str() {
+                "🌲" => Sprite::Evergreen,
+                "🌳" => Sprite::Deciduous,
+                "🌴" => Sprite::Palm,
+                "🌵" => Sprite::Cactus,
+                "🎍" => Sprite::Bamboo,
+                _ => Sprite::Sapling,
+            },
+            _ => Sprite::Sapling,
+        }
+    }
+}
+
+/// Deterministic pseudo-random generator (SplitMix64)
+/// Implemented here so that a forest keeps looking the same across dependency updates
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// Number of trees drawn per sprite, scaled down to `max` trees in total
+/// Rounding leftovers go to the biggest remainders so that the total is exactly `max`
+pub fn shares(counts: &[(Sprite, u64)], max: u64) -> Vec<(Sprite, u64)> {
+    let total: u64 = counts.iter().map(|(_, count)| count).sum();
+    if total <= max {
+        return counts.to_vec();
+    }
+
+    let mut shares: Vec<(Sprite, u64)> = counts.iter()
+        .map(|(sprite, count)| (*sprite, count * max / total))
+        .collect();
+    let mut remainders: Vec<(usize, u64)> = counts.iter()
+        .enumerate()
+        .map(|(i, (_, count))| (i, count * max % total))
+        .collect();
+    remainders.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let leftover = max - shares.iter().map(|(_, share)| share).sum::<u64>();
+    for (i, _) in remainders.into_iter().take(leftover as usize) {
+        shares[i].1 += 1;
+    }
+    shares
+}
+
+/// Render a forest as a PNG image
+/// The same seed and counts always give the same image
+pub fn render_forest(seed: u64, counts: &[(Sprite, u64)]) -> Result<Vec<u8>> {
+    let mut rng = Rng(seed);
+    let mut canvas = RgbaImage::new(WIDTH, HEIGHT);
+
+    // grass, in patches of slightly different shades
+    for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+        let patch = (x / 8 + y / 8 * (WIDTH / 8)) as u64;
+        let shade = (Rng(seed ^ patch.wrapping_mul(0x2545_F491_4F6C_DD1D)).below(24)) as u8;
+        *pixel = Rgba([76 + shade / 2, 140 + shade, 60 + shade / 3, 255]);
+    }
+
+    let mut trees: Vec<Sprite> = shares(counts, MAX_TREES).into_iter()
+        .flat_map(|(sprite, share)| std::iter::repeat(sprite).take(share as usize))
+        .collect();
+
+    // the more trees, the more cells and the smaller the sprites
+    let cells = (trees.len() as u64).max(MIN_CELLS);
+    let cols = ((cells * WIDTH as u64 / HEIGHT as u64) as f64).sqrt().ceil() as u64;
+    let rows = (cells + cols - 1) / cols;
+    let (cell_width, cell_height) = (WIDTH / cols as u32, HEIGHT / rows as u32);
+    let scale = (cell_width.min(cell_height) * 3 / 2 / SPRITE_SIZE).max(1).min(4);
+    let size = SPRITE_SIZE * scale;
+
+    // every tree takes a random free cell (partial Fisher-Yates shuffle)
+    let mut free: Vec<u64> = (0..cols * rows).collect();
+    for i in 0..trees.len() {
+        let j = i + rng.below((free.len() - i) as u64) as usize;
+        free.swap(i, j);
+    }
+    for i in (1..trees.len()).rev() {
+        trees.swap(i, rng.below(i as u64 + 1) as usize);
+    }
+
+    // bottom center of each tree, drawn from back to front
+    let mut placed: Vec<(u32, u32, Sprite)> = trees.into_iter()
+        .zip(free.into_iter())
+        .map(|(sprite, cell)| {
+            let x = (cell % cols) as u32 * cell_width + rng.below(cell_width as u64) as u32;
+            let y = (cell / cols) as u32 * cell_height + rng.below(cell_height as u64) as u32 + size / 2;
+            (x, y.min(HEIGHT), sprite)
+        })
+        .collect();
+    placed.sort_by_key(|(x, y, _)| (*y, *x));
+
+    let sprites: Vec<RgbaImage> = SPRITES.iter()
+        .map(|sprite| imageops::resize(sprite, size, size, FilterType::Nearest))
+        .collect();
+    for (x, y, sprite) in placed {
+        imageops::overlay(&mut canvas, &sprites[sprite as usize], x.saturating_sub(size / 2), y.saturating_sub(size));
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).encode(&canvas, WIDTH, HEIGHT, ColorType::Rgba8)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_forests_are_drawn_as_is() {
+        let counts = vec![(Sprite::Evergreen, 12), (Sprite::Cactus, 3)];
+        assert_eq!(shares(&counts, MAX_TREES), counts);
+    }
+
+    #[test]
+    fn big_forests_are_scaled_down() {
+        let counts = vec![(Sprite::Evergreen, 2000), (Sprite::Palm, 1000), (Sprite::Bamboo, 1)];
+        let shares = shares(&counts, 100);
+        assert_eq!(shares.iter().map(|(_, share)| share).sum::<u64>(), 100);
+        assert_eq!(shares[0], (Sprite::Evergreen, 67));
+        assert_eq!(shares[1], (Sprite::Palm, 33));
+    }
+
+    #[test]
+    fn rendering_is_deterministic() {
+        let counts = vec![(Sprite::Evergreen, 40), (Sprite::Deciduous, 25), (Sprite::Sapling, 5)];
+        let image = render_forest(42, &counts).unwrap();
+        assert_eq!(image, render_forest(42, &counts).unwrap());
+        assert_ne!(image, render_forest(43, &counts).unwrap());
+    }
+}
@@ -0,0 +1,985 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+use serenity::async_trait;
+
+use super::Store;
+use crate::error::{Error, Result};
+use crate::game::{Effects, GreenhouseDelta, StorageDelta, WorkerDelta};
+use crate::models::*;
+use crate::prelude::ParentedCache;
+
+struct ServerRecord {
+    prefix: String,
+    lang: String,
+    plant_cooldown: i16,
+    max_workers: i16,
+    global_forest: bool,
+    milestones_channel: Option<u64>,
+    milestones: Vec<i64>,
+    /// (channel_id, threshold), channel 0 being the server forest
+    reached_milestones: HashSet<(u64, i64)>,
+    daily_coin_gifts: i32,
+    daily_item_gifts: i32,
+    daily_rewards: BTreeMap<i16, DailyReward>,
+    /// Mapped by (kind, scope)
+    rules: HashMap<(u8, u64), bool>,
+    cooldown_overrides: HashMap<(u64, i16), i16>,
+    species: HashMap<i16, Species>,
+}
+
+#[derive(Clone)]
+struct PlayerRecord {
+    user_id: u64,
+    guild_id: u64,
+    coins: i32,
+    storage_upgrade: i16,
+    storage: Storage,
+    greenhouse_upgrade: i16,
+    greenhouse: Greenhouse,
+    workers: Vec<Worker>,
+    last_time_check: Instant,
+    achievements: HashSet<String>,
+    daily_streak: i32,
+    last_daily: Option<u64>,
+}
+
+#[derive(Default)]
+struct Tables {
+    servers: HashMap<u64, ServerRecord>,
+    /// Mapped by player id
+    players: HashMap<i32, PlayerRecord>,
+    /// Mapped by (species, user_id, channel_id), gives (guild_id, count)
+    trees: HashMap<(i16, u64, u64), (u64, i32)>,
+    /// Daily rollups of `trees`, mapped by (day, species, user_id, channel_id)
+    tree_days: HashMap<(u64, i16, u64, u64), (u64, i32)>,
+    /// Transfers ledger, as (guild_id, transfer)
+    transfers: Vec<(u64, Transfer)>,
+    /// Coins and items ledger, as (player_id, entry)
+    ledger: Vec<(i32, LedgerEntry)>,
+    last_player_id: i32,
+    last_worker_id: i32,
+}
+
+impl Tables {
+    fn player(&mut self, player_id: i32) -> Result<&mut PlayerRecord> {
+        self.players.get_mut(&player_id).ok_or(Error::Missing("player"))
+    }
+
+    fn plant(&mut self, guild_id: u64, user_id: u64, channel_id: u64, species_id: i16, count: i32) {
+        self.trees.entry((species_id, user_id, channel_id)).or_insert((guild_id, 0)).1 += count;
+        let today = day(SystemTime::now());
+        self.tree_days.entry((today, species_id, user_id, channel_id)).or_insert((guild_id, 0)).1 += count;
+    }
+
+    /// Trees matching a filter, as (species, user_id, channel_id, count), see `Store::species_counts`
+    fn forest(&self, filter: &TreeFilter) -> Vec<(i16, u64, u64, i64)> {
+        let trees: Box<dyn Iterator<Item = (i16, u64, u64, u64, i32)>> = match filter.window.days() {
+            None => Box::new(self.trees.iter()
+                .map(|((species, user_id, channel_id), (guild_id, count))| (*species, *user_id, *channel_id, *guild_id, *count))),
+            Some(days) => {
+                let first_day = (day(SystemTime::now()) + 1).saturating_sub(days);
+                Box::new(self.tree_days.iter()
+                    .filter(move |((day, ..), _)| *day >= first_day)
+                    .map(|((_, species, user_id, channel_id), (guild_id, count))| (*species, *user_id, *channel_id, *guild_id, *count)))
+            }
+        };
+
+        let mut forest: HashMap<(i16, u64, u64), i64> = HashMap::new();
+        for (species, user_id, channel_id, guild_id, count) in trees {
+            let (in_scope, species) = match filter.scope {
+                TreeScope::Channel(scope_id) => (channel_id == scope_id, species),
+                TreeScope::Server(scope_id) => (guild_id == scope_id, species),
+                TreeScope::Global { official_forest } => (
+                    official_forest == Some(channel_id)
+                        || self.servers.get(&guild_id).map_or(false, |server| server.global_forest),
+                    if Species::is_default_id(species) { species } else { 0 },
+                ),
+            };
+            if in_scope && filter.user_id.map_or(true, |id| id == user_id) && filter.species.map_or(true, |id| id == species) {
+                *forest.entry((species, user_id, channel_id)).or_insert(0) += count as i64;
+            }
+        }
+        forest.into_iter()
+            .map(|((species, user_id, channel_id), count)| (species, user_id, channel_id, count))
+            .collect()
+    }
+
+    /// Append a change of coins (item None) or finite items to the ledger
+    fn record(&mut self, player_id: i32, item: Option<(ItemType, i16)>, delta: i32, reason: Reason) {
+        if delta != 0 {
+            self.ledger.push((player_id, LedgerEntry { item, delta, reason, created_at: SystemTime::now() }));
+        }
+    }
+
+    /// See `Store::gifts_today`
+    fn gifts_today(&self, guild_id: u64, user_id: u64) -> (i64, i64) {
+        let today = day(SystemTime::now());
+        let mut gifts = (0, 0);
+        for (transfer_guild_id, transfer) in self.transfers.iter() {
+            if *transfer_guild_id == guild_id && transfer.giver_id == user_id && day(transfer.created_at) == today {
+                match transfer.item {
+                    Some(_) => gifts.1 += transfer.amount as i64,
+                    None => gifts.0 += transfer.amount as i64,
+                }
+            }
+        }
+        gifts
+    }
+
+    /// See `Store::save_effects`
+    fn apply_effects(&mut self, guild_id: u64, user_id: u64, player_id: i32, effects: &Effects) -> Result<()> {
+        let player = self.player(player_id)?;
+        // effects are applied to copies first so that nothing changes if one of them can't be
+        let mut storage = player.storage.clone();
+        for delta in effects.storage.iter() {
+            match delta {
+                StorageDelta::Add { item_type, item_id, qty } if *qty < 0 => {
+                    match storage.get_mut(&(*item_type, *item_id)) {
+                        Some(-1) => {}
+                        Some(amount) if *amount >= -qty => *amount += qty,
+                        _ => return Err(Error::OutOfDate),
+                    }
+                }
+                StorageDelta::Add { item_type, item_id, qty } => {
+                    let amount = storage.entry((*item_type, *item_id)).or_insert(0);
+                    if *amount != -1 {
+                        *amount += qty;
+                    }
+                }
+                StorageDelta::Infinite { item_type, item_id } => {
+                    storage.insert((*item_type, *item_id), -1);
+                }
+                StorageDelta::Take { item_type, item_id, qty } => {
+                    match storage.get_mut(&(*item_type, *item_id)) {
+                        Some(amount) if *amount != -1 && *amount >= *qty => *amount -= qty,
+                        _ => return Err(Error::OutOfDate),
+                    }
+                }
+            }
+        }
+        if player.coins + effects.coins < 0 {
+            return Err(Error::OutOfDate);
+        }
+        if let Some((day, _)) = effects.daily {
+            if player.last_daily >= Some(day) {
+                return Err(Error::OutOfDate);
+            }
+        }
+        let mut greenhouse = player.greenhouse.clone();
+        for delta in effects.greenhouse.iter() {
+            match delta {
+                GreenhouseDelta::Fill { slot_id, species_id, remaining } => {
+                    if greenhouse.contains_key(slot_id) {
+                        return Err(Error::OutOfDate);
+                    }
+                    greenhouse.insert(*slot_id, GreenhouseSlot {
+                        species: *species_id,
+                        remaining: *remaining,
+                        grown: 0,
+                        progress: 0.0,
+                    });
+                }
+                GreenhouseDelta::Collect { slot_id, qty } => {
+                    match greenhouse.get_mut(slot_id) {
+                        Some(slot) if slot.grown >= *qty => slot.grown -= qty,
+                        _ => return Err(Error::OutOfDate),
+                    }
+                    if greenhouse[slot_id].is_done() {
+                        greenhouse.remove(slot_id);
+                    }
+                }
+            }
+        }
+        let mut workers = player.workers.clone();
+        for delta in effects.workers.iter() {
+            match delta {
+                WorkerDelta::Hire { worker_id } => workers.push(Worker {
+                    id: *worker_id,
+                    channel_id: None,
+                    progress: 0.0,
+                }),
+                WorkerDelta::Assign { worker_id, channel_id } => {
+                    match workers.iter_mut().find(|worker| worker.id == *worker_id) {
+                        Some(worker) => worker.channel_id = Some(*channel_id),
+                        None => return Err(Error::OutOfDate),
+                    }
+                }
+                WorkerDelta::Fire { worker_id } => {
+                    if !workers.iter().any(|worker| worker.id == *worker_id) {
+                        return Err(Error::OutOfDate);
+                    }
+                    workers.retain(|worker| worker.id != *worker_id);
+                }
+            }
+        }
+
+        // infinite (-1) amounts are not recorded
+        let recorded = effects.storage.iter()
+            .filter_map(|delta| match delta {
+                StorageDelta::Add { item_type, item_id, qty } => Some((*item_type, *item_id, *qty)),
+                StorageDelta::Take { item_type, item_id, qty } => Some((*item_type, *item_id, -qty)),
+                StorageDelta::Infinite { .. } => None,
+            })
+            .filter(|(item_type, item_id, _)| storage.get(&(*item_type, *item_id)) != Some(&-1))
+            .collect::<Vec<(ItemType, i16, i32)>>();
+        player.storage = storage;
+        player.coins += effects.coins;
+        if let Some(storage_upgrade) = effects.storage_upgrade {
+            player.storage_upgrade = storage_upgrade;
+        }
+        player.greenhouse = greenhouse;
+        if let Some(greenhouse_upgrade) = effects.greenhouse_upgrade {
+            player.greenhouse_upgrade = greenhouse_upgrade;
+        }
+        player.workers = workers;
+        if let Some((day, streak)) = effects.daily {
+            player.last_daily = Some(day);
+            player.daily_streak = streak;
+        }
+
+        for (item_type, item_id, qty) in recorded {
+            self.record(player_id, Some((item_type, item_id)), qty, effects.reason);
+        }
+        self.record(player_id, None, effects.coins, effects.reason);
+
+        for (channel_id, species_id, count) in effects.trees.iter() {
+            self.plant(guild_id, user_id, *channel_id, *species_id, *count);
+        }
+        Ok(())
+    }
+}
+
+/// Store keeping everything in memory, for tests and local demos
+/// Everything is lost when the process exits
+#[derive(Default)]
+pub struct MemoryStore(Mutex<Tables>);
+
+/// Share of a total, in percent rounded to 2 decimals
+fn percent(count: i64, total: i64) -> f64 {
+    (count as f64 / total as f64 * 10000.0).round() / 100.0
+}
+
+/// Ranks rows by total then user id, and keeps a page and the row of a user, along with the count of ranked rows
+fn page(mut ranked: Vec<Ranked>, user_id: u64, offset: usize, limit: usize) -> (Vec<Ranked>, usize) {
+    ranked.sort_by(|a, b| b.total.cmp(&a.total).then(a.user_id.cmp(&b.user_id)));
+    let count = ranked.len();
+    let page = ranked.into_iter()
+        .enumerate()
+        .map(|(index, row)| Ranked { rank: index + 1, ..row })
+        .filter(|row| (row.rank > offset && row.rank <= offset + limit) || row.user_id == user_id)
+        .collect();
+    (page, count)
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn load_server(&self, guild_id: u64) -> Result<Option<Server>> {
+        let mut tables = self.0.lock().unwrap();
+        let record = tables.servers.entry(guild_id).or_insert_with(|| ServerRecord {
+            prefix: "f-".to_owned(),
+            lang: crate::i18n::DEFAULT_LOCALE.to_owned(),
+            plant_cooldown: 60,
+            max_workers: 3,
+            global_forest: true,
+            milestones_channel: None,
+            milestones: vec![100, 1000, 10000],
+            reached_milestones: HashSet::new(),
+            daily_coin_gifts: 500,
+            daily_item_gifts: 100,
+            daily_rewards: DailyReward::defaults(),
+            rules: HashMap::new(),
+            cooldown_overrides: HashMap::new(),
+            species: Species::defaults().into_iter().map(|species| (species.id, species)).collect(),
+        });
+
+        let rules = |kind| Rules::from_scopes(record.rules.iter()
+            .filter(|((rule_kind, _), _)| *rule_kind == kind)
+            .map(|((_, scope), allowance)| (*scope, *allowance)));
+        let mut server = Server {
+            id: guild_id as i64,
+            prefix: record.prefix.clone(),
+            lang: record.lang.clone(),
+            plant_cooldown: record.plant_cooldown,
+            cooldown_overrides: record.cooldown_overrides.clone(),
+            max_workers: record.max_workers,
+            global_forest: record.global_forest,
+            milestones_channel: record.milestones_channel,
+            milestones: record.milestones.clone(),
+            reached_milestones: record.reached_milestones.clone(),
+            daily_coin_gifts: record.daily_coin_gifts,
+            daily_item_gifts: record.daily_item_gifts,
+            daily_rewards: record.daily_rewards.clone(),
+
+            forest_rules: rules(1),
+            commands_rules: rules(2),
+
+            species: record.species.clone(),
+            species_from_emojis: HashMap::new(),
+
+            player_cache: ParentedCache::new(guild_id),
+        };
+        server.index_species();
+        Ok(Some(server))
+    }
+
+    async fn save_settings(&self, server: &Server) -> Result<()> {
+        let mut tables = self.0.lock().unwrap();
+        let record = tables.servers.get_mut(&(server.id as u64)).ok_or(Error::Missing("server"))?;
+        record.prefix = server.prefix.clone();
+        record.lang = server.lang.clone();
+        record.plant_cooldown = server.plant_cooldown;
+        record.max_workers = server.max_workers;
+        record.global_forest = server.global_forest;
+        record.milestones_channel = server.milestones_channel;
+        record.milestones = server.milestones.clone();
+        record.daily_coin_gifts = server.daily_coin_gifts;
+        record.daily_item_gifts = server.daily_item_gifts;
+        Ok(())
+    }
+
+    async fn save_rule(&self, guild_id: u64, kind: u8, scope: u64, allowance: Option<bool>) -> Result<()> {
+        let mut tables = self.0.lock().unwrap();
+        let record = tables.servers.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+        match allowance {
+            Some(allowance) => record.rules.insert((kind, scope), allowance),
+            None => record.rules.remove(&(kind, scope)),
+        };
+        Ok(())
+    }
+
+    async fn save_cooldown_override(
+        &self, guild_id: u64, channel_id: u64, species_id: i16, cooldown: Option<i16>
+    ) -> Result<()> {
+        let mut tables = self.0.lock().unwrap();
+        let record = tables.servers.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+        match cooldown {
+            Some(cooldown) => record.cooldown_overrides.insert((channel_id, species_id), cooldown),
+            None => record.cooldown_overrides.remove(&(channel_id, species_id)),
+        };
+        Ok(())
+    }
+
+    async fn save_daily_reward(&self, guild_id: u64, streak: i16, reward: Option<DailyReward>) -> Result<()> {
+        let mut tables = self.0.lock().unwrap();
+        let record = tables.servers.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+        match reward {
+            Some(reward) => record.daily_rewards.insert(streak, reward),
+            None => record.daily_rewards.remove(&streak),
+        };
+        Ok(())
+    }
+
+    async fn next_species_id(&self, guild_id: u64) -> Result<i16> {
+        let tables = self.0.lock().unwrap();
+        let species = tables.servers.get(&guild_id)
+            .and_then(|record| record.species.keys().max().copied());
+        let trees = tables.trees.iter()
+            .filter(|(_, (tree_guild_id, _))| *tree_guild_id == guild_id)
+            .map(|((species_id, _, _), _)| *species_id)
+            .max();
+        Ok(species.max(trees).unwrap_or(0) + 1)
+    }
+
+    async fn next_worker_id(&self) -> Result<i32> {
+        let mut tables = self.0.lock().unwrap();
+        tables.last_worker_id += 1;
+        Ok(tables.last_worker_id)
+    }
+
+    async fn save_species(&self, guild_id: u64, species: &Species) -> Result<()> {
+        let mut tables = self.0.lock().unwrap();
+        let record = tables.servers.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+        record.species.insert(species.id, species.clone());
+        Ok(())
+    }
+
+    async fn remove_species(&self, guild_id: u64, species_id: i16) -> Result<()> {
+        let mut guard = self.0.lock().unwrap();
+        let tables = &mut *guard;
+        let record = tables.servers.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+        record.species.remove(&species_id);
+        record.cooldown_overrides.retain(|(_, species), _| *species != species_id);
+        record.daily_rewards.retain(|_, reward| reward.species != Some(species_id));
+        let mut removed = Vec::new();
+        for (player_id, player) in tables.players.iter_mut().filter(|(_, player)| player.guild_id == guild_id) {
+            for item_type in [ItemType::Pallet, ItemType::Seedling].iter() {
+                match player.storage.remove(&(*item_type, species_id)) {
+                    Some(amount) if amount > 0 => removed.push((*player_id, *item_type, amount)),
+                    _ => {}
+                }
+            }
+            player.greenhouse.retain(|_, slot| slot.species != species_id);
+        }
+        for (player_id, item_type, amount) in removed {
+            tables.record(player_id, Some((item_type, species_id)), -amount, Reason::Admin);
+        }
+        Ok(())
+    }
+
+    async fn load_player(&self, guild_id: u64, user_id: u64) -> Result<Option<Player>> {
+        let mut tables = self.0.lock().unwrap();
+        let existing = tables.players.iter()
+            .find(|(_, player)| player.guild_id == guild_id && player.user_id == user_id)
+            .map(|(id, _)| *id);
+        let (player_id, new) = match existing {
+            Some(player_id) => (player_id, false),
+            None => {
+                tables.last_player_id += 1;
+                let player_id = tables.last_player_id;
+                tables.players.insert(player_id, PlayerRecord {
+                    user_id,
+                    guild_id,
+                    coins: 0,
+                    storage_upgrade: 1,
+                    storage: HashMap::new(),
+                    greenhouse_upgrade: 1,
+                    greenhouse: HashMap::new(),
+                    workers: Vec::new(),
+                    last_time_check: Instant::now(),
+                    achievements: HashSet::new(),
+                    daily_streak: 0,
+                    last_daily: None,
+                });
+                (player_id, true)
+            }
+        };
+
+        let record = tables.player(player_id)?;
+        let mut player = Player::new(player_id, user_id, guild_id, record.coins, record.storage_upgrade, record.storage.clone(), new);
+        player.greenhouse_upgrade = record.greenhouse_upgrade;
+        player.greenhouse = record.greenhouse.clone();
+        player.workers = record.workers.clone();
+        player.last_time_check = record.last_time_check;
+        player.achievements = record.achievements.clone();
+        player.daily_streak = record.daily_streak;
+        player.last_daily = record.last_daily;
+        Ok(Some(player))
+    }
+
+    async fn save_effects(
+        &self, guild_id: u64, user_id: u64, player_id: i32, effects: &Effects
+    ) -> Result<()> {
+        self.0.lock().unwrap().apply_effects(guild_id, user_id, player_id, effects)
+    }
+
+    async fn save_time_check(
+        &self, guild_id: u64, user_id: u64, player_id: i32,
+        report: &TimeCheck, greenhouse: &Greenhouse, workers: &[Worker]
+    ) -> Result<()> {
+        let mut tables = self.0.lock().unwrap();
+        let player = tables.player(player_id)?;
+        // a species may have been planted in several channels
+        let mut used: HashMap<i16, i32> = HashMap::new();
+        for (_, species_id, count) in report.planted.iter() {
+            *used.entry(*species_id).or_insert(0) += count;
+        }
+        for (species_id, count) in used.iter() {
+            match player.storage.get(&(ItemType::Seedling, *species_id)) {
+                Some(amount) if *amount != -1 && amount >= count => {}
+                _ => return Err(Error::OutOfDate),
+            }
+        }
+        player.last_time_check = Instant::now();
+        player.coins += report.coins;
+        for (slot_id, slot) in greenhouse.iter() {
+            if let Some(stored) = player.greenhouse.get_mut(slot_id) {
+                *stored = slot.clone();
+            }
+        }
+        for worker in workers.iter() {
+            if let Some(stored) = player.workers.iter_mut().find(|stored| stored.id == worker.id) {
+                stored.progress = worker.progress;
+            }
+        }
+
+        for (species_id, count) in used.iter() {
+            if let Some(amount) = player.storage.get_mut(&(ItemType::Seedling, *species_id)) {
+                *amount -= count;
+            }
+        }
+        tables.record(player_id, None, report.coins, Reason::Work);
+        for (channel_id, species_id, count) in report.planted.iter() {
+            tables.record(player_id, Some((ItemType::Seedling, *species_id)), -count, Reason::Work);
+            tables.plant(guild_id, user_id, *channel_id, *species_id, *count);
+        }
+        Ok(())
+    }
+
+    async fn player_trees(&self, guild_id: u64, user_id: u64) -> Result<Vec<(u64, i16, i64)>> {
+        let tables = self.0.lock().unwrap();
+        Ok(tables.trees.iter()
+            .filter(|((_, tree_user_id, _), (tree_guild_id, _))| *tree_user_id == user_id && *tree_guild_id == guild_id)
+            .map(|((species_id, _, channel_id), (_, count))| (*channel_id, *species_id, *count as i64))
+            .collect())
+    }
+
+    async fn unlock_achievement(
+        &self, guild_id: u64, user_id: u64, player_id: i32, achievement: &str, reward: &Effects
+    ) -> Result<bool> {
+        let mut tables = self.0.lock().unwrap();
+        if tables.player(player_id)?.achievements.contains(achievement) {
+            return Ok(false);
+        }
+        tables.apply_effects(guild_id, user_id, player_id, reward)?;
+        tables.player(player_id)?.achievements.insert(achievement.to_owned());
+        Ok(true)
+    }
+
+    async fn save_trade(
+        &self, guild_id: u64, first: (u64, i32, &Effects), second: (u64, i32, &Effects)
+    ) -> Result<()> {
+        let mut tables = self.0.lock().unwrap();
+        let (first_user_id, first_player_id, first_effects) = first;
+        let (second_user_id, second_player_id, second_effects) = second;
+        // the first side is restored if the second one can't be applied
+        let backup = tables.player(first_player_id)?.clone();
+        let recorded = tables.ledger.len();
+        tables.apply_effects(guild_id, first_user_id, first_player_id, first_effects)?;
+        if let Err(why) = tables.apply_effects(guild_id, second_user_id, second_player_id, second_effects) {
+            tables.players.insert(first_player_id, backup);
+            tables.ledger.truncate(recorded);
+            return Err(why);
+        }
+        Ok(())
+    }
+
+    async fn gifts_today(&self, guild_id: u64, user_id: u64) -> Result<(i64, i64)> {
+        Ok(self.0.lock().unwrap().gifts_today(guild_id, user_id))
+    }
+
+    async fn save_gift(
+        &self, guild_id: u64, giver: (u64, i32, &Effects), receiver: (u64, i32, &Effects), transfer: &Transfer, daily_cap: i32
+    ) -> Result<bool> {
+        let mut tables = self.0.lock().unwrap();
+        let (coins, items) = tables.gifts_today(guild_id, transfer.giver_id);
+        let given = if transfer.item.is_some() { items } else { coins };
+        if given + transfer.amount as i64 > daily_cap as i64 {
+            return Ok(false);
+        }
+
+        let (giver_user_id, giver_id, giver_effects) = giver;
+        let (receiver_user_id, receiver_id, receiver_effects) = receiver;
+        // the giver is restored if the receiver can't be given the gift
+        let backup = tables.player(giver_id)?.clone();
+        let recorded = tables.ledger.len();
+        tables.apply_effects(guild_id, giver_user_id, giver_id, giver_effects)?;
+        if let Err(why) = tables.apply_effects(guild_id, receiver_user_id, receiver_id, receiver_effects) {
+            tables.players.insert(giver_id, backup);
+            tables.ledger.truncate(recorded);
+            return Err(why);
+        }
+        tables.transfers.push((guild_id, transfer.clone()));
+        Ok(true)
+    }
+
+    async fn transfers(&self, guild_id: u64, user_id: Option<u64>, limit: usize) -> Result<Vec<Transfer>> {
+        let tables = self.0.lock().unwrap();
+        Ok(tables.transfers.iter()
+            .rev()
+            .filter(|(transfer_guild_id, transfer)| *transfer_guild_id == guild_id && match user_id {
+                Some(user_id) => transfer.giver_id == user_id || transfer.receiver_id == user_id,
+                None => true,
+            })
+            .take(limit)
+            .map(|(_, transfer)| transfer.clone())
+            .collect())
+    }
+
+    async fn forest_totals(&self, guild_id: u64, channel_id: u64) -> Result<(i64, i64)> {
+        let tables = self.0.lock().unwrap();
+        let mut totals = (0, 0);
+        for ((_, _, tree_channel_id), (tree_guild_id, count)) in tables.trees.iter() {
+            if *tree_guild_id == guild_id {
+                totals.1 += *count as i64;
+                if *tree_channel_id == channel_id {
+                    totals.0 += *count as i64;
+                }
+            }
+        }
+        Ok(totals)
+    }
+
+    async fn reach_milestones(&self, guild_id: u64, channel_id: u64, thresholds: &[i64]) -> Result<Vec<i64>> {
+        let mut tables = self.0.lock().unwrap();
+        let record = tables.servers.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+        Ok(thresholds.iter()
+            .filter(|threshold| record.reached_milestones.insert((channel_id, **threshold)))
+            .copied()
+            .collect())
+    }
+
+    async fn top_contributors(&self, guild_id: u64, channel_id: u64, limit: usize) -> Result<Vec<(u64, i64)>> {
+        let tables = self.0.lock().unwrap();
+        let mut contributors: HashMap<u64, i64> = HashMap::new();
+        for ((_, user_id, tree_channel_id), (tree_guild_id, count)) in tables.trees.iter() {
+            if *tree_guild_id == guild_id && (channel_id == 0 || *tree_channel_id == channel_id) {
+                *contributors.entry(*user_id).or_insert(0) += *count as i64;
+            }
+        }
+        let mut contributors: Vec<(u64, i64)> = contributors.into_iter().collect();
+        contributors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        contributors.truncate(limit);
+        Ok(contributors)
+    }
+
+    async fn species_counts(&self, filter: &TreeFilter, limit: Option<usize>) -> Result<Vec<SpeciesCount>> {
+        let forest = self.0.lock().unwrap().forest(filter);
+        let mut totals: HashMap<i16, i64> = HashMap::new();
+        for (species, _, _, count) in forest.iter() {
+            *totals.entry(*species).or_insert(0) += count;
+        }
+        let total = totals.values().sum::<i64>();
+        let mut counts = totals.into_iter()
+            .map(|(species, count)| SpeciesCount { species, count, percent: percent(count, total) })
+            .collect::<Vec<SpeciesCount>>();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.species.cmp(&b.species)));
+        if let Some(limit) = limit {
+            counts.truncate(limit);
+        }
+        Ok(counts)
+    }
+
+    async fn channel_counts(&self, filter: &TreeFilter, limit: usize) -> Result<Vec<(u64, i64)>> {
+        let forest = self.0.lock().unwrap().forest(filter);
+        let mut totals: HashMap<u64, i64> = HashMap::new();
+        for (_, _, channel_id, count) in forest.iter() {
+            *totals.entry(*channel_id).or_insert(0) += count;
+        }
+        let mut totals: Vec<(u64, i64)> = totals.into_iter().collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        totals.truncate(limit);
+        Ok(totals)
+    }
+
+    async fn tree_leaderboard(
+        &self, filter: &TreeFilter, user_id: u64, offset: usize, limit: usize
+    ) -> Result<(Vec<Ranked>, usize)> {
+        let forest = self.0.lock().unwrap().forest(filter);
+        let total = forest.iter().map(|(.., count)| count).sum::<i64>();
+        // (total, favorite (species, channel_id) and its count) by user
+        let mut planters: HashMap<u64, (i64, (i16, u64), i64)> = HashMap::new();
+        for (species, planter_id, channel_id, count) in forest {
+            let planter = planters.entry(planter_id).or_insert((0, (species, channel_id), count));
+            planter.0 += count;
+            // the biggest forest, ties going to the lowest species then channel as in PgStore
+            if count > planter.2 || (count == planter.2 && (species, channel_id) < planter.1) {
+                planter.1 = (species, channel_id);
+                planter.2 = count;
+            }
+        }
+        let ranked = planters.into_iter()
+            .map(|(planter_id, (planter_total, favorite, _))| Ranked {
+                rank: 0,
+                user_id: planter_id,
+                total: planter_total,
+                percent: percent(planter_total, total),
+                favorite: Some(favorite),
+            })
+            .collect();
+        Ok(page(ranked, user_id, offset, limit))
+    }
+
+    async fn coin_leaderboard(
+        &self, guild_id: u64, user_id: u64, offset: usize, limit: usize
+    ) -> Result<(Vec<Ranked>, usize)> {
+        let tables = self.0.lock().unwrap();
+        let ranked = tables.players.values()
+            .filter(|player| player.guild_id == guild_id)
+            .map(|player| Ranked {
+                rank: 0,
+                user_id: player.user_id,
+                total: player.coins as i64,
+                percent: 0.0,
+                favorite: None,
+            })
+            .collect();
+        Ok(page(ranked, user_id, offset, limit))
+    }
+
+    async fn ledger(&self, player_id: i32, offset: usize, limit: usize) -> Result<(Vec<LedgerEntry>, usize)> {
+        let tables = self.0.lock().unwrap();
+        let entries = tables.ledger.iter()
+            .filter(|(entry_player_id, _)| *entry_player_id == player_id)
+            .map(|(_, entry)| entry)
+            .collect::<Vec<&LedgerEntry>>();
+        Ok((
+            entries.iter().rev().skip(offset).take(limit).map(|entry| (*entry).clone()).collect(),
+            entries.len(),
+        ))
+    }
+
+    async fn reconcile(&self, guild_id: u64) -> Result<Vec<(u64, i32, i64)>> {
+        let tables = self.0.lock().unwrap();
+        let mut mismatches = tables.players.iter()
+            .filter(|(_, player)| player.guild_id == guild_id)
+            .map(|(player_id, player)| {
+                let sum = tables.ledger.iter()
+                    .filter(|(entry_player_id, entry)| entry_player_id == player_id && entry.item.is_none())
+                    .map(|(_, entry)| entry.delta as i64)
+                    .sum::<i64>();
+                (player.user_id, player.coins, sum)
+            })
+            .filter(|(_, coins, sum)| *coins as i64 != *sum)
+            .collect::<Vec<(u64, i32, i64)>>();
+        mismatches.sort_unstable();
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD_ID: u64 = 1;
+    const USER_ID: u64 = 2;
+
+    #[tokio::test]
+    async fn new_server_gets_default_species() {
+        let store = MemoryStore::default();
+        let server = store.load_server(GUILD_ID).await.unwrap().unwrap();
+        assert_eq!(server.species.len(), DEFAULT_SPECIES.len());
+        assert!(server.forest_rules.check(&3));
+
+        store.save_rule(GUILD_ID, 1, 3, Some(false)).await.unwrap();
+        let server = store.load_server(GUILD_ID).await.unwrap().unwrap();
+        assert!(!server.forest_rules.check(&3));
+    }
+
+    #[tokio::test]
+    async fn players_are_loaded_back_with_their_effects() {
+        let store = MemoryStore::default();
+        let player = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        assert!(player.is_new());
+
+        let effects = Effects {
+            storage: vec![
+                StorageDelta::Infinite { item_type: ItemType::Seedling, item_id: 1 },
+                StorageDelta::Add { item_type: ItemType::Seedling, item_id: 1, qty: -1 },
+                StorageDelta::Add { item_type: ItemType::Pallet, item_id: 2, qty: 3 },
+            ],
+            coins: 5,
+            trees: vec![(3, 1, 1)],
+            ..Effects::new(Reason::Admin)
+        };
+        store.save_effects(GUILD_ID, USER_ID, player.id, &effects).await.unwrap();
+
+        let loaded = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        assert!(!loaded.is_new());
+        assert_eq!(loaded.id, player.id);
+        assert_eq!(loaded.coins, 5);
+        assert_eq!(loaded.storage.get(&(ItemType::Seedling, 1)), Some(&-1));
+        assert_eq!(loaded.storage.get(&(ItemType::Pallet, 2)), Some(&3));
+    }
+
+    #[tokio::test]
+    async fn failed_effects_change_nothing() {
+        let store = MemoryStore::default();
+        let player = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let effects = Effects {
+            storage: vec![
+                StorageDelta::Add { item_type: ItemType::Pallet, item_id: 2, qty: 1 },
+                StorageDelta::Add { item_type: ItemType::Seedling, item_id: 2, qty: -1 },
+            ],
+            coins: 1,
+            ..Effects::new(Reason::Admin)
+        };
+        assert!(store.save_effects(GUILD_ID, USER_ID, player.id, &effects).await.is_err());
+
+        let spend = Effects {
+            coins: -1,
+            ..Effects::new(Reason::Admin)
+        };
+        assert!(store.save_effects(GUILD_ID, USER_ID, player.id, &spend).await.is_err());
+
+        let loaded = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        assert_eq!(loaded.coins, 0);
+        assert!(loaded.storage.is_empty());
+    }
+
+    #[tokio::test]
+    async fn species_ids_are_not_reused() {
+        let store = MemoryStore::default();
+        store.load_server(GUILD_ID).await.unwrap();
+        let player = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let species_id = store.next_species_id(GUILD_ID).await.unwrap();
+        let effects = Effects {
+            trees: vec![(3, species_id, 1)],
+            ..Effects::new(Reason::Plant)
+        };
+        store.save_effects(GUILD_ID, USER_ID, player.id, &effects).await.unwrap();
+
+        assert_eq!(store.next_species_id(GUILD_ID).await.unwrap(), species_id + 1);
+    }
+
+    #[tokio::test]
+    async fn achievements_are_rewarded_once() {
+        let store = MemoryStore::default();
+        let player = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let reward = Effects { coins: 10, ..Effects::new(Reason::Reward) };
+
+        assert!(store.unlock_achievement(GUILD_ID, USER_ID, player.id, "first_tree", &reward).await.unwrap());
+        assert!(!store.unlock_achievement(GUILD_ID, USER_ID, player.id, "first_tree", &reward).await.unwrap());
+
+        let loaded = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        assert_eq!(loaded.coins, 10);
+        assert!(loaded.achievements.contains("first_tree"));
+    }
+
+    #[tokio::test]
+    async fn failed_trades_change_nothing() {
+        let store = MemoryStore::default();
+        let first = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let second = store.load_player(GUILD_ID, USER_ID + 1).await.unwrap().unwrap();
+        store.save_effects(GUILD_ID, USER_ID, first.id, &Effects { coins: 10, ..Effects::new(Reason::Trade) }).await.unwrap();
+
+        let pay = Effects { coins: -10, ..Effects::new(Reason::Trade) };
+        let take = Effects {
+            coins: 10,
+            storage: vec![StorageDelta::Take { item_type: ItemType::Pallet, item_id: 2, qty: 1 }],
+            ..Effects::new(Reason::Trade)
+        };
+        assert!(store.save_trade(GUILD_ID, (USER_ID, first.id, &pay), (USER_ID + 1, second.id, &take)).await.is_err());
+        assert_eq!(store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap().coins, 10);
+
+        let take = Effects { coins: 10, ..Effects::new(Reason::Trade) };
+        store.save_trade(GUILD_ID, (USER_ID, first.id, &pay), (USER_ID + 1, second.id, &take)).await.unwrap();
+        assert_eq!(store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap().coins, 0);
+        assert_eq!(store.load_player(GUILD_ID, USER_ID + 1).await.unwrap().unwrap().coins, 10);
+    }
+
+    #[tokio::test]
+    async fn gifts_are_capped_per_day() {
+        let store = MemoryStore::default();
+        let giver = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let receiver = store.load_player(GUILD_ID, USER_ID + 1).await.unwrap().unwrap();
+        store.save_effects(GUILD_ID, USER_ID, giver.id, &Effects { coins: 100, ..Effects::new(Reason::Gift) }).await.unwrap();
+
+        let pay = Effects { coins: -40, ..Effects::new(Reason::Gift) };
+        let receive = Effects { coins: 40, ..Effects::new(Reason::Gift) };
+        let transfer = Transfer { giver_id: USER_ID, receiver_id: USER_ID + 1, item: None, amount: 40, created_at: SystemTime::now() };
+        for _ in 0..2 {
+            assert!(store.save_gift(GUILD_ID, (USER_ID, giver.id, &pay), (USER_ID + 1, receiver.id, &receive), &transfer, 100).await.unwrap());
+        }
+        assert!(!store.save_gift(GUILD_ID, (USER_ID, giver.id, &pay), (USER_ID + 1, receiver.id, &receive), &transfer, 100).await.unwrap());
+
+        assert_eq!(store.gifts_today(GUILD_ID, USER_ID).await.unwrap(), (80, 0));
+        assert_eq!(store.transfers(GUILD_ID, Some(USER_ID + 1), 10).await.unwrap().len(), 2);
+        assert_eq!(store.load_player(GUILD_ID, USER_ID + 1).await.unwrap().unwrap().coins, 80);
+    }
+
+    #[tokio::test]
+    async fn ledger_matches_coins() {
+        let store = MemoryStore::default();
+        let player = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let effects = Effects {
+            storage: vec![
+                StorageDelta::Infinite { item_type: ItemType::Seedling, item_id: 1 },
+                StorageDelta::Add { item_type: ItemType::Seedling, item_id: 1, qty: -1 },
+                StorageDelta::Add { item_type: ItemType::Pallet, item_id: 2, qty: 3 },
+            ],
+            coins: 20,
+            ..Effects::new(Reason::Plant)
+        };
+        store.save_effects(GUILD_ID, USER_ID, player.id, &effects).await.unwrap();
+        let worker_id = store.next_worker_id().await.unwrap();
+        assert_eq!(worker_id, 1);
+        let hire = Effects { coins: -15, workers: vec![WorkerDelta::Hire { worker_id }], ..Effects::new(Reason::Purchase) };
+        store.save_effects(GUILD_ID, USER_ID, player.id, &hire).await.unwrap();
+
+        let (entries, total) = store.ledger(player.id, 0, 10).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(entries[0].delta, -15);
+        assert_eq!(entries[0].reason, Reason::Purchase);
+        assert_eq!(entries[2].item, Some((ItemType::Pallet, 2)));
+        assert_eq!(store.ledger(player.id, 2, 10).await.unwrap().0.len(), 1);
+        assert!(store.reconcile(GUILD_ID).await.unwrap().is_empty());
+
+        store.0.lock().unwrap().player(player.id).unwrap().coins += 1;
+        assert_eq!(store.reconcile(GUILD_ID).await.unwrap(), vec![(USER_ID, 6, 5)]);
+    }
+
+    #[tokio::test]
+    async fn greenhouse_and_workers_changes_are_checked() {
+        let store = MemoryStore::default();
+        let player = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let fill = Effects {
+            greenhouse: vec![GreenhouseDelta::Fill { slot_id: 1, species_id: 1, remaining: 10 }],
+            ..Effects::new(Reason::Greenhouse)
+        };
+        store.save_effects(GUILD_ID, USER_ID, player.id, &fill).await.unwrap();
+        assert!(matches!(store.save_effects(GUILD_ID, USER_ID, player.id, &fill).await, Err(Error::OutOfDate)));
+
+        let collect = Effects {
+            greenhouse: vec![GreenhouseDelta::Collect { slot_id: 1, qty: 1 }],
+            ..Effects::new(Reason::Greenhouse)
+        };
+        assert!(matches!(store.save_effects(GUILD_ID, USER_ID, player.id, &collect).await, Err(Error::OutOfDate)));
+
+        let fire = Effects { workers: vec![WorkerDelta::Fire { worker_id: 1 }], ..Effects::new(Reason::Work) };
+        assert!(matches!(store.save_effects(GUILD_ID, USER_ID, player.id, &fire).await, Err(Error::OutOfDate)));
+        assert_eq!(store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap().greenhouse[&1].remaining, 10);
+    }
+
+    #[tokio::test]
+    async fn leaderboards_rank_planters() {
+        let store = MemoryStore::default();
+        store.load_server(GUILD_ID).await.unwrap();
+        let first = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let second = store.load_player(GUILD_ID, USER_ID + 1).await.unwrap().unwrap();
+        let species_id = store.next_species_id(GUILD_ID).await.unwrap();
+        let trees = Effects { trees: vec![(3, 1, 1), (3, 1, 1), (4, 2, 1)], ..Effects::new(Reason::Plant) };
+        store.save_effects(GUILD_ID, USER_ID, first.id, &trees).await.unwrap();
+        let trees = Effects { trees: vec![(3, species_id, 1)], ..Effects::new(Reason::Plant) };
+        store.save_effects(GUILD_ID, USER_ID + 1, second.id, &trees).await.unwrap();
+
+        let filter = TreeFilter::new(TreeScope::Server(GUILD_ID), Window::Today);
+        let (ranked, count) = store.tree_leaderboard(&filter, USER_ID + 1, 0, 1).await.unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(ranked.len(), 2);
+        assert_eq!((ranked[0].rank, ranked[0].user_id, ranked[0].total), (1, USER_ID, 3));
+        assert_eq!(ranked[0].favorite, Some((1, 3)));
+        assert_eq!(ranked[1].percent, 25.0);
+        assert_eq!(store.channel_counts(&filter, 10).await.unwrap(), vec![(3, 3), (4, 1)]);
+
+        let filter = TreeFilter::new(TreeScope::Global { official_forest: None }, Window::All);
+        let species = store.species_counts(&filter, None).await.unwrap();
+        assert_eq!(species.iter().map(|count| (count.species, count.count)).collect::<Vec<_>>(), vec![(1, 2), (0, 1), (2, 1)]);
+    }
+
+    #[tokio::test]
+    async fn time_checks_only_plant_owned_seedlings() {
+        let store = MemoryStore::default();
+        let player = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        let seedlings = Effects {
+            storage: vec![StorageDelta::Add { item_type: ItemType::Seedling, item_id: 1, qty: 3 }],
+            ..Effects::new(Reason::Admin)
+        };
+        store.save_effects(GUILD_ID, USER_ID, player.id, &seedlings).await.unwrap();
+
+        let report = TimeCheck { elapsed: 0.0, planted: vec![(3, 1, 2), (4, 1, 2)], coins: 4 };
+        assert!(store.save_time_check(GUILD_ID, USER_ID, player.id, &report, &Greenhouse::new(), &[]).await.is_err());
+        let loaded = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        assert_eq!((loaded.coins, loaded.storage.get(&(ItemType::Seedling, 1))), (0, Some(&3)));
+
+        let report = TimeCheck { elapsed: 0.0, planted: vec![(3, 1, 2), (4, 1, 1)], coins: 3 };
+        store.save_time_check(GUILD_ID, USER_ID, player.id, &report, &Greenhouse::new(), &[]).await.unwrap();
+        let loaded = store.load_player(GUILD_ID, USER_ID).await.unwrap().unwrap();
+        assert_eq!((loaded.coins, loaded.storage.get(&(ItemType::Seedling, 1))), (3, Some(&0)));
+        assert!(store.reconcile(GUILD_ID).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn milestones_are_reached_once() {
+        let store = MemoryStore::default();
+        store.load_server(GUILD_ID).await.unwrap();
+
+        assert_eq!(store.reach_milestones(GUILD_ID, 3, &[100, 1000]).await.unwrap(), vec![100, 1000]);
+        assert_eq!(store.reach_milestones(GUILD_ID, 3, &[100, 1000, 10000]).await.unwrap(), vec![10000]);
+        assert_eq!(store.reach_milestones(GUILD_ID, 0, &[100]).await.unwrap(), vec![100]);
+
+        let server = store.load_server(GUILD_ID).await.unwrap().unwrap();
+        assert!(server.reached_milestones.contains(&(3, 1000)));
+    }
+}
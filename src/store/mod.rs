@@ -0,0 +1,131 @@
+use serenity::async_trait;
+
+use crate::error::Result;
+use crate::game::Effects;
+use crate::models::*;
+
+mod memory;
+mod postgres;
+
+pub use memory::MemoryStore;
+pub use postgres::PgStore;
+
+/// Persistence of servers, rules, players, storage and trees
+///
+/// Servers and players are loaded once and then cached (see `ServerCache`),
+/// so every write goes through the store after the game rules accepted it
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Load a server with its rules, cooldown overrides and species
+    /// If the server is new, it gets inserted with default values and species
+    async fn load_server(&self, guild_id: u64) -> Result<Option<Server>>;
+
+    /// Persist the general settings of a server: prefix, language, plant cooldown, max workers,
+    /// global forest participation, milestones and gift caps
+    async fn save_settings(&self, server: &Server) -> Result<()>;
+
+    /// Set a channel rule (kind 1 for forest rules, 2 for commands rules), scope 0 being the whole server
+    /// `None` removes the rule so that the channel inherits the server one
+    async fn save_rule(&self, guild_id: u64, kind: u8, scope: u64, allowance: Option<bool>) -> Result<()>;
+
+    /// Set a cooldown override, `None` removes it
+    async fn save_cooldown_override(
+        &self, guild_id: u64, channel_id: u64, species_id: i16, cooldown: Option<i16>
+    ) -> Result<()>;
+
+    /// Set the daily reward given from a streak day, `None` removes it
+    async fn save_daily_reward(&self, guild_id: u64, streak: i16, reward: Option<DailyReward>) -> Result<()>;
+
+    /// Id for a new species
+    /// Ids are never reused so that trees of a removed species don't resolve to a new one
+    async fn next_species_id(&self, guild_id: u64) -> Result<i16>;
+
+    /// Insert or update a species
+    async fn save_species(&self, guild_id: u64, species: &Species) -> Result<()>;
+
+    /// Remove a species, planted trees are kept
+    /// but pallets, seedlings, greenhouse slots and cooldown overrides of this species are removed
+    async fn remove_species(&self, guild_id: u64, species_id: i16) -> Result<()>;
+
+    /// Load a player with its storage, greenhouse, workers and achievements
+    /// If the player is new, it gets inserted with default values
+    async fn load_player(&self, guild_id: u64, user_id: u64) -> Result<Option<Player>>;
+
+    /// Allocate the id of a worker about to be hired (see `Action::HireWorker`)
+    async fn next_worker_id(&self) -> Result<i32>;
+
+    /// Persist the effects of a game action: storage, coins, upgrades, greenhouse, workers and planted trees
+    /// Either everything is saved or nothing is, it fails with `Error::OutOfDate` if an item to remove is missing,
+    /// if coins would become negative, or if the greenhouse slots or workers changed in the meantime
+    async fn save_effects(
+        &self, guild_id: u64, user_id: u64, player_id: i32, effects: &Effects
+    ) -> Result<()>;
+
+    /// Persist a time check along with the greenhouse and workers state it has been computed to
+    /// Nothing is saved if the player doesn't own the seedlings planted by workers anymore, it fails with `Error::OutOfDate`
+    async fn save_time_check(
+        &self, guild_id: u64, user_id: u64, player_id: i32,
+        report: &TimeCheck, greenhouse: &Greenhouse, workers: &[Worker]
+    ) -> Result<()>;
+
+    /// Trees planted by a player in a server, as (channel_id, species, count)
+    async fn player_trees(&self, guild_id: u64, user_id: u64) -> Result<Vec<(u64, i16, i64)>>;
+
+    /// Persist an unlocked achievement along with the effects of its reward
+    /// Returns false, without giving the reward, if the achievement was already unlocked
+    async fn unlock_achievement(
+        &self, guild_id: u64, user_id: u64, player_id: i32, achievement: &str, reward: &Effects
+    ) -> Result<bool>;
+
+    /// Persist both sides of a trade, as (user_id, player_id, effects)
+    /// Either both are saved or none is, it fails with `Error::OutOfDate` like `save_effects`
+    async fn save_trade(
+        &self, guild_id: u64, first: (u64, i32, &Effects), second: (u64, i32, &Effects)
+    ) -> Result<()>;
+
+    /// Coins and items given by a player since midnight (UTC), as (coins, items)
+    async fn gifts_today(&self, guild_id: u64, user_id: u64) -> Result<(i64, i64)>;
+
+    /// Persist both sides of a gift, as (user_id, player_id, effects), and record it in the transfers ledger
+    /// Returns false, saving nothing, if the giver would go over the daily cap of the given kind (coins or items)
+    async fn save_gift(
+        &self, guild_id: u64, giver: (u64, i32, &Effects), receiver: (u64, i32, &Effects), transfer: &Transfer, daily_cap: i32
+    ) -> Result<bool>;
+
+    /// Latest transfers of a server, only the ones involving a user if one is given
+    async fn transfers(&self, guild_id: u64, user_id: Option<u64>, limit: usize) -> Result<Vec<Transfer>>;
+
+    /// Trees planted in a channel and in the whole server, as (channel total, server total)
+    async fn forest_totals(&self, guild_id: u64, channel_id: u64) -> Result<(i64, i64)>;
+
+    /// Record milestones reached by a channel forest, channel 0 being the server forest
+    /// Returns the thresholds that weren't reached before
+    async fn reach_milestones(&self, guild_id: u64, channel_id: u64, thresholds: &[i64]) -> Result<Vec<i64>>;
+
+    /// Players who planted the most trees in a channel, or in the whole server for channel 0, as (user_id, count)
+    async fn top_contributors(&self, guild_id: u64, channel_id: u64, limit: usize) -> Result<Vec<(u64, i64)>>;
+
+    /// Trees matching a filter per species, the most planted first
+    /// Every species is returned if no limit is given
+    async fn species_counts(&self, filter: &TreeFilter, limit: Option<usize>) -> Result<Vec<SpeciesCount>>;
+
+    /// Trees matching a filter per channel, the biggest forest first, as (channel_id, count)
+    async fn channel_counts(&self, filter: &TreeFilter, limit: usize) -> Result<Vec<(u64, i64)>>;
+
+    /// A page of the players ranked by trees matching a filter, along with the total number of ranked players
+    /// The row of `user_id` is always returned as well, wherever they are ranked
+    async fn tree_leaderboard(
+        &self, filter: &TreeFilter, user_id: u64, offset: usize, limit: usize
+    ) -> Result<(Vec<Ranked>, usize)>;
+
+    /// Same as `tree_leaderboard`, with the players of a server ranked by coins
+    async fn coin_leaderboard(
+        &self, guild_id: u64, user_id: u64, offset: usize, limit: usize
+    ) -> Result<(Vec<Ranked>, usize)>;
+
+    /// A page of the ledger entries of a player, latest first, along with the total number of entries
+    async fn ledger(&self, player_id: i32, offset: usize, limit: usize) -> Result<(Vec<LedgerEntry>, usize)>;
+
+    /// Players of a server whose coins don't match the sum of their ledger, as (user_id, coins, ledger sum)
+    async fn reconcile(&self, guild_id: u64) -> Result<Vec<(u64, i32, i64)>>;
+}
@@ -0,0 +1,880 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use serenity::async_trait;
+use sqlx::Row;
+use sqlx::{Postgres, Transaction};
+use sqlx::postgres::{PgPool, PgRow};
+
+use super::Store;
+use crate::error::{Error, Result};
+use crate::game::{Effects, GreenhouseDelta, StorageDelta, WorkerDelta};
+use crate::models::*;
+use crate::prelude::ParentedCache;
+
+/// Store backed by a Postgres database, see the migrations for the schema
+pub struct PgStore(pub PgPool);
+
+/// Count planted trees, both all-time and in today's rollup
+async fn add_trees(
+    transaction: &mut Transaction<'_, Postgres>,
+    guild_id: u64, user_id: u64, channel_id: u64, species_id: i16, count: i32
+) -> Result<()> {
+    sqlx::query!("
+        INSERT INTO trees (species, user_id, channel_id, guild_id, count) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT(species, user_id, channel_id) DO UPDATE SET count = trees.count + $5",
+        species_id, user_id as i64, channel_id as i64, guild_id as i64, count
+    ).execute(&mut *transaction).await?;
+    sqlx::query!("
+        INSERT INTO tree_days (day, species, user_id, channel_id, guild_id, count)
+        VALUES ((now() AT TIME ZONE 'utc')::date, $1, $2, $3, $4, $5)
+        ON CONFLICT(day, species, user_id, channel_id) DO UPDATE SET count = tree_days.count + $5",
+        species_id, user_id as i64, channel_id as i64, guild_id as i64, count
+    ).execute(&mut *transaction).await?;
+    Ok(())
+}
+
+/// `forest` CTE with the trees matching a filter, in the same shape as the `trees` table
+/// `$1` is bound to the scope id, `$2` to the user and `$3` to the species, both nullable
+fn forest(filter: &TreeFilter) -> String {
+    // windows only go back a fixed number of days, the count isn't user input
+    let source = match filter.window.days() {
+        None => "SELECT species, user_id, channel_id, guild_id, count FROM trees".to_owned(),
+        Some(days) => format!(
+            "SELECT species, user_id, channel_id, guild_id, SUM(count)::int AS count FROM tree_days
+            WHERE day > (now() AT TIME ZONE 'utc')::date - {} GROUP BY species, user_id, channel_id, guild_id",
+            days
+        ),
+    };
+    let (species, scope) = match filter.scope {
+        TreeScope::Channel(_) => ("species".to_owned(), "channel_id = $1"),
+        TreeScope::Server(_) => ("species".to_owned(), "guild_id = $1"),
+        TreeScope::Global { .. } => (
+            format!(
+                "CASE WHEN species IN ({}) THEN species ELSE 0 END",
+                DEFAULT_SPECIES.iter().map(|(id, ..)| id.to_string()).collect::<Vec<String>>().join(", ")
+            ),
+            "(guild_id IN (SELECT id FROM servers WHERE global_forest) OR channel_id = $1)",
+        ),
+    };
+    format!("
+        WITH forest AS (
+            SELECT * FROM (
+                SELECT {species} AS species, user_id, channel_id, guild_id, count FROM ({source}) source WHERE {scope}
+            ) scoped
+            WHERE ($2::bigint IS NULL OR user_id = $2) AND ($3::smallint IS NULL OR species = $3)
+        )",
+        species = species, source = source, scope = scope
+    )
+}
+
+/// Id bound to the scope condition of `forest`, the Official Forest channel for the global scope (0 if there is none)
+fn scope_id(scope: TreeScope) -> i64 {
+    match scope {
+        TreeScope::Channel(channel_id) => channel_id as i64,
+        TreeScope::Server(guild_id) => guild_id as i64,
+        TreeScope::Global { official_forest } => official_forest.unwrap_or(0) as i64,
+    }
+}
+
+/// Rows of a ranking query along with the number of ranked players,
+/// `extra` reads the share of trees and the favorite (species, channel) of a row
+fn ranking<F>(rows: Vec<PgRow>, extra: F) -> Result<(Vec<Ranked>, usize)>
+    where F: Fn(&PgRow) -> Result<(f64, Option<(i16, u64)>)>
+{
+    let ranked = match rows.first() {
+        Some(row) => row.try_get::<i64, _>("ranked")? as usize,
+        None => 0,
+    };
+    let rows = rows.iter()
+        .map(|row| {
+            let (percent, favorite) = extra(row)?;
+            Ok(Ranked {
+                rank: row.try_get::<i64, _>("rank")? as usize,
+                user_id: row.try_get::<i64, _>("user_id")? as u64,
+                total: row.try_get("total")?,
+                percent,
+                favorite,
+            })
+        })
+        .collect::<Result<Vec<Ranked>>>()?;
+    Ok((rows, ranked))
+}
+
+/// Append a change of coins (item None) or items to the ledger
+async fn record(
+    transaction: &mut Transaction<'_, Postgres>,
+    player_id: i32, item: Option<(ItemType, i16)>, delta: i32, reason: Reason
+) -> Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+    sqlx::query!(
+        "INSERT INTO ledger (player_id, item_type, item_id, delta, reason) VALUES ($1, $2, $3, $4, $5)",
+        player_id, item.map(|(item_type, _)| item_type as i16), item.map(|(_, item_id)| item_id), delta, reason.as_str()
+    ).execute(&mut *transaction).await?;
+    Ok(())
+}
+
+/// See `Store::save_effects`
+async fn apply_effects(
+    transaction: &mut Transaction<'_, Postgres>,
+    guild_id: u64, user_id: u64, player_id: i32, effects: &Effects
+) -> Result<()> {
+    for delta in effects.storage.iter() {
+        match delta {
+            StorageDelta::Add { item_type, item_id, qty } if *qty < 0 => {
+                // infinite (-1) amounts are left untouched
+                let row = sqlx::query!(
+                    "UPDATE storage SET amount = CASE WHEN amount = -1 THEN -1 ELSE amount + $4 END
+                    WHERE player_id = $1 AND item_type = $2 AND item_id = $3 AND (amount = -1 OR amount >= -$4)
+                    RETURNING amount",
+                    player_id, *item_type as i16, *item_id, *qty
+                ).fetch_optional(&mut *transaction).await?;
+                // the cache is out of date, the whole action is rolled back
+                if row.ok_or(Error::OutOfDate)?.amount != -1 {
+                    record(transaction, player_id, Some((*item_type, *item_id)), *qty, effects.reason).await?;
+                }
+            }
+            StorageDelta::Add { item_type, item_id, qty } => {
+                let row = sqlx::query!(
+                    "INSERT INTO storage (player_id, item_type, item_id, amount) VALUES ($1, $2, $3, $4)
+                    ON CONFLICT(player_id, item_type, item_id) DO UPDATE SET amount = CASE
+                        WHEN storage.amount = -1 THEN -1
+                        ELSE storage.amount + $4
+                    END
+                    RETURNING amount",
+                    player_id, *item_type as i16, *item_id, *qty
+                ).fetch_one(&mut *transaction).await?;
+                if row.amount != -1 {
+                    record(transaction, player_id, Some((*item_type, *item_id)), *qty, effects.reason).await?;
+                }
+            }
+            StorageDelta::Infinite { item_type, item_id } => {
+                sqlx::query!(
+                    "INSERT INTO storage (player_id, item_type, item_id, amount) VALUES ($1, $2, $3, -1)
+                    ON CONFLICT(player_id, item_type, item_id) DO UPDATE SET amount = -1",
+                    player_id, *item_type as i16, *item_id
+                ).execute(&mut *transaction).await?;
+            }
+            StorageDelta::Take { item_type, item_id, qty } => {
+                // infinite (-1) amounts never match
+                let done = sqlx::query!(
+                    "UPDATE storage SET amount = amount - $4
+                    WHERE player_id = $1 AND item_type = $2 AND item_id = $3 AND amount >= $4",
+                    player_id, *item_type as i16, *item_id, *qty
+                ).execute(&mut *transaction).await?;
+                if done.rows_affected() == 0 {
+                    return Err(Error::OutOfDate);
+                }
+                record(transaction, player_id, Some((*item_type, *item_id)), -qty, effects.reason).await?;
+            }
+        }
+    }
+
+    if effects.coins != 0 || effects.storage_upgrade.is_some() || effects.greenhouse_upgrade.is_some() {
+        let done = sqlx::query!(
+            "UPDATE players SET coins = coins + $2, storage_upgrade = COALESCE($3, storage_upgrade),
+                greenhouse_upgrade = COALESCE($4, greenhouse_upgrade)
+            WHERE id = $1 AND coins + $2 >= 0",
+            player_id, effects.coins, effects.storage_upgrade, effects.greenhouse_upgrade
+        ).execute(&mut *transaction).await?;
+        if done.rows_affected() == 0 {
+            return Err(Error::OutOfDate);
+        }
+        record(transaction, player_id, None, effects.coins, effects.reason).await?;
+    }
+
+    for delta in effects.greenhouse.iter() {
+        let done = match delta {
+            GreenhouseDelta::Fill { slot_id, species_id, remaining } => sqlx::query!(
+                "INSERT INTO greenhouse_slots (player_id, slot, species, remaining) VALUES ($1, $2, $3, $4)
+                ON CONFLICT(player_id, slot) DO NOTHING",
+                player_id, *slot_id, *species_id, *remaining
+            ).execute(&mut *transaction).await?,
+            GreenhouseDelta::Collect { slot_id, qty } => sqlx::query!(
+                "UPDATE greenhouse_slots SET grown = grown - $3 WHERE player_id = $1 AND slot = $2 AND grown >= $3",
+                player_id, *slot_id, *qty
+            ).execute(&mut *transaction).await?,
+        };
+        if done.rows_affected() == 0 {
+            return Err(Error::OutOfDate);
+        }
+        if let GreenhouseDelta::Collect { slot_id, .. } = delta {
+            sqlx::query!(
+                "DELETE FROM greenhouse_slots WHERE player_id = $1 AND slot = $2 AND remaining <= 0 AND grown <= 0",
+                player_id, *slot_id
+            ).execute(&mut *transaction).await?;
+        }
+    }
+
+    for delta in effects.workers.iter() {
+        let done = match delta {
+            WorkerDelta::Hire { worker_id } => sqlx::query!(
+                "INSERT INTO workers (id, player_id) VALUES ($1, $2)",
+                *worker_id, player_id
+            ).execute(&mut *transaction).await?,
+            WorkerDelta::Assign { worker_id, channel_id } => sqlx::query!(
+                "UPDATE workers SET channel_id = $3 WHERE id = $1 AND player_id = $2",
+                *worker_id, player_id, *channel_id as i64
+            ).execute(&mut *transaction).await?,
+            WorkerDelta::Fire { worker_id } => sqlx::query!(
+                "DELETE FROM workers WHERE id = $1 AND player_id = $2",
+                *worker_id, player_id
+            ).execute(&mut *transaction).await?,
+        };
+        if done.rows_affected() == 0 {
+            return Err(Error::OutOfDate);
+        }
+    }
+
+    if let Some((day, streak)) = effects.daily {
+        // the reward can only be claimed once per day, even by concurrent messages
+        let done = sqlx::query!(
+            "UPDATE players SET daily_streak = $2, last_daily = DATE '1970-01-01' + $3::int
+            WHERE id = $1 AND (last_daily IS NULL OR last_daily < DATE '1970-01-01' + $3::int)",
+            player_id, streak, day as i32
+        ).execute(&mut *transaction).await?;
+        if done.rows_affected() == 0 {
+            return Err(Error::OutOfDate);
+        }
+    }
+
+    for (channel_id, species_id, count) in effects.trees.iter() {
+        add_trees(transaction, guild_id, user_id, *channel_id, *species_id, *count).await?;
+    }
+    Ok(())
+}
+
+impl PgStore {
+    async fn get_rules(&self, kind: u8, guild_id: u64) -> Result<Rules> {
+        let rows = sqlx::query!(
+            "SELECT scope, allowance FROM rules WHERE guild_id = $1 AND kind = $2",
+            guild_id as i64, kind as i16
+        ).fetch_all(&self.0).await?;
+
+        Ok(Rules::from_scopes(rows.into_iter().map(|rule| (rule.scope as u64, rule.allowance))))
+    }
+
+    async fn get_cooldown_overrides(&self, guild_id: u64) -> Result<HashMap<(u64, i16), i16>> {
+        let rows = sqlx::query!(
+            "SELECT channel_id, species, cooldown FROM cooldown_rules WHERE guild_id = $1",
+            guild_id as i64
+        ).fetch_all(&self.0).await?;
+
+        Ok(rows.iter()
+            .map(|rule| ((rule.channel_id as u64, rule.species), rule.cooldown))
+            .collect())
+    }
+
+    async fn get_reached_milestones(&self, guild_id: u64) -> Result<HashSet<(u64, i64)>> {
+        let rows = sqlx::query!(
+            "SELECT channel_id, threshold FROM milestones WHERE guild_id = $1",
+            guild_id as i64
+        ).fetch_all(&self.0).await?;
+
+        Ok(rows.iter()
+            .map(|milestone| (milestone.channel_id as u64, milestone.threshold))
+            .collect())
+    }
+
+    async fn get_daily_rewards(&self, guild_id: u64) -> Result<BTreeMap<i16, DailyReward>> {
+        let rows = sqlx::query!(
+            "SELECT streak, species, amount FROM daily_rewards WHERE guild_id = $1",
+            guild_id as i64
+        ).fetch_all(&self.0).await?;
+
+        Ok(rows.iter()
+            .map(|reward| (reward.streak, DailyReward {
+                species: if reward.species == 0 { None } else { Some(reward.species) },
+                amount: reward.amount,
+            }))
+            .collect())
+    }
+
+    async fn get_species(&self, guild_id: u64) -> Result<HashMap<i16, Species>> {
+        let rows = sqlx::query!(
+            "SELECT id, emoji, name, pallet_cost, pallet_qty, default_qty, coins, growth_rate, enabled FROM species WHERE guild_id = $1",
+            guild_id as i64
+        ).fetch_all(&self.0).await?;
+
+        Ok(rows.into_iter()
+            .map(|row| (row.id, Species {
+                id: row.id,
+                emoji: EmojiKey::from(row.emoji.as_str()),
+                name: row.name,
+                pallet_cost: row.pallet_cost,
+                pallet_qty: row.pallet_qty,
+                default_qty: row.default_qty,
+                coins: row.coins,
+                growth_rate: row.growth_rate,
+                enabled: row.enabled,
+            }))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn load_server(&self, guild_id: u64) -> Result<Option<Server>> {
+        let mut result = sqlx::query("SELECT * FROM servers WHERE id = $1")
+            .bind(guild_id as i64)
+            .fetch_optional(&self.0).await?;
+        if result.is_none() {
+            let mut transaction = self.0.begin().await?;
+            result = sqlx::query("INSERT INTO servers (id) VALUES ($1) ON CONFLICT DO NOTHING RETURNING *")
+                .bind(guild_id as i64)
+                .fetch_optional(&mut transaction).await?;
+            if result.is_some() {
+                for (id, emoji, name, pallet_cost, pallet_qty, default_qty, coins) in DEFAULT_SPECIES.iter() {
+                    sqlx::query!(
+                        "INSERT INTO species (id, guild_id, emoji, name, pallet_cost, pallet_qty, default_qty, coins) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                        ON CONFLICT DO NOTHING",
+                        *id, guild_id as i64, *emoji, *name, *pallet_cost, *pallet_qty, *default_qty, *coins
+                    ).execute(&mut transaction).await?;
+                }
+                for (streak, species, amount) in DEFAULT_DAILY_REWARDS.iter() {
+                    sqlx::query!(
+                        "INSERT INTO daily_rewards (guild_id, streak, species, amount) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+                        guild_id as i64, *streak, *species, *amount
+                    ).execute(&mut transaction).await?;
+                }
+            }
+            transaction.commit().await?;
+        }
+
+        let result = match result {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+        let mut server = Server {
+            id: result.get("id"),
+            prefix: result.get("prefix"),
+            lang: result.get("lang"),
+            plant_cooldown: result.get("plant_cooldown"),
+            cooldown_overrides: self.get_cooldown_overrides(guild_id).await?,
+            max_workers: result.get("max_workers"),
+            global_forest: result.get("global_forest"),
+            milestones_channel: result.get::<Option<i64>, _>("milestones_channel").map(|channel_id| channel_id as u64),
+            milestones: result.get("milestones"),
+            reached_milestones: self.get_reached_milestones(guild_id).await?,
+            daily_coin_gifts: result.get("daily_coin_gifts"),
+            daily_item_gifts: result.get("daily_item_gifts"),
+            daily_rewards: self.get_daily_rewards(guild_id).await?,
+
+            forest_rules: self.get_rules(1, guild_id).await?,
+            commands_rules: self.get_rules(2, guild_id).await?,
+
+            species: self.get_species(guild_id).await?,
+            species_from_emojis: HashMap::new(),
+
+            player_cache: ParentedCache::new(guild_id),
+        };
+        server.index_species();
+        Ok(Some(server))
+    }
+
+    async fn save_settings(&self, server: &Server) -> Result<()> {
+        sqlx::query!(
+            "UPDATE servers SET prefix = $2, lang = $3, plant_cooldown = $4, max_workers = $5, global_forest = $6,
+                milestones_channel = $7, milestones = $8, daily_coin_gifts = $9, daily_item_gifts = $10
+            WHERE id = $1",
+            server.id, server.prefix, server.lang, server.plant_cooldown, server.max_workers, server.global_forest,
+            server.milestones_channel.map(|channel_id| channel_id as i64), &server.milestones,
+            server.daily_coin_gifts, server.daily_item_gifts
+        ).execute(&self.0).await?;
+        Ok(())
+    }
+
+    async fn save_rule(&self, guild_id: u64, kind: u8, scope: u64, allowance: Option<bool>) -> Result<()> {
+        match allowance {
+            Some(allowance) => sqlx::query!(
+                "INSERT INTO rules (guild_id, kind, scope, allowance) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (guild_id, kind, scope) DO UPDATE SET allowance = $4",
+                guild_id as i64, kind as i16, scope as i64, allowance
+            ).execute(&self.0).await?,
+            None => sqlx::query!(
+                "DELETE FROM rules WHERE guild_id = $1 AND kind = $2 AND scope = $3",
+                guild_id as i64, kind as i16, scope as i64
+            ).execute(&self.0).await?,
+        };
+        Ok(())
+    }
+
+    async fn save_cooldown_override(
+        &self, guild_id: u64, channel_id: u64, species_id: i16, cooldown: Option<i16>
+    ) -> Result<()> {
+        match cooldown {
+            Some(cooldown) => sqlx::query!(
+                "INSERT INTO cooldown_rules (guild_id, channel_id, species, cooldown) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (guild_id, channel_id, species) DO UPDATE SET cooldown = $4",
+                guild_id as i64, channel_id as i64, species_id, cooldown
+            ).execute(&self.0).await?,
+            None => sqlx::query!(
+                "DELETE FROM cooldown_rules WHERE guild_id = $1 AND channel_id = $2 AND species = $3",
+                guild_id as i64, channel_id as i64, species_id
+            ).execute(&self.0).await?,
+        };
+        Ok(())
+    }
+
+    async fn save_daily_reward(&self, guild_id: u64, streak: i16, reward: Option<DailyReward>) -> Result<()> {
+        match reward {
+            Some(reward) => sqlx::query!(
+                "INSERT INTO daily_rewards (guild_id, streak, species, amount) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (guild_id, streak) DO UPDATE SET species = $3, amount = $4",
+                guild_id as i64, streak, reward.species.unwrap_or(0), reward.amount
+            ).execute(&self.0).await?,
+            None => sqlx::query!(
+                "DELETE FROM daily_rewards WHERE guild_id = $1 AND streak = $2",
+                guild_id as i64, streak
+            ).execute(&self.0).await?,
+        };
+        Ok(())
+    }
+
+    async fn next_species_id(&self, guild_id: u64) -> Result<i16> {
+        let row = sqlx::query!(
+            "SELECT GREATEST(
+                (SELECT MAX(id) FROM species WHERE guild_id = $1),
+                (SELECT MAX(species) FROM trees WHERE guild_id = $1)
+            ) AS id",
+            guild_id as i64
+        ).fetch_one(&self.0).await?;
+        Ok(row.id.unwrap_or(0) + 1)
+    }
+
+    async fn next_worker_id(&self) -> Result<i32> {
+        let row = sqlx::query!("SELECT nextval('workers_id_seq') AS id").fetch_one(&self.0).await?;
+        Ok(row.id.ok_or(Error::Missing("worker"))? as i32)
+    }
+
+    async fn save_species(&self, guild_id: u64, species: &Species) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO species (id, guild_id, emoji, name, pallet_cost, pallet_qty, default_qty, coins, growth_rate, enabled)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (guild_id, id) DO UPDATE SET
+                emoji = $3, name = $4, pallet_cost = $5, pallet_qty = $6, default_qty = $7, coins = $8, growth_rate = $9, enabled = $10",
+            species.id, guild_id as i64, species.emoji.to_string(), species.name,
+            species.pallet_cost, species.pallet_qty, species.default_qty, species.coins, species.growth_rate, species.enabled
+        ).execute(&self.0).await?;
+        Ok(())
+    }
+
+    async fn remove_species(&self, guild_id: u64, species_id: i16) -> Result<()> {
+        let mut transaction = self.0.begin().await?;
+        sqlx::query!("DELETE FROM species WHERE guild_id = $1 AND id = $2", guild_id as i64, species_id)
+            .execute(&mut transaction).await?;
+        sqlx::query!(
+            "INSERT INTO ledger (player_id, item_type, item_id, delta, reason)
+            SELECT storage.player_id, storage.item_type, storage.item_id, -storage.amount, $5 FROM storage, players
+            WHERE storage.player_id = players.id AND players.guild_id = $1 AND storage.item_id = $2 AND storage.item_type IN ($3, $4)
+                AND storage.amount > 0",
+            guild_id as i64, species_id, ItemType::Pallet as i16, ItemType::Seedling as i16, Reason::Admin.as_str()
+        ).execute(&mut transaction).await?;
+        sqlx::query!(
+            "DELETE FROM storage USING players
+            WHERE storage.player_id = players.id AND players.guild_id = $1 AND storage.item_id = $2 AND storage.item_type IN ($3, $4)",
+            guild_id as i64, species_id, ItemType::Pallet as i16, ItemType::Seedling as i16
+        ).execute(&mut transaction).await?;
+        sqlx::query!("DELETE FROM cooldown_rules WHERE guild_id = $1 AND species = $2", guild_id as i64, species_id)
+            .execute(&mut transaction).await?;
+        sqlx::query!("DELETE FROM daily_rewards WHERE guild_id = $1 AND species = $2", guild_id as i64, species_id)
+            .execute(&mut transaction).await?;
+        sqlx::query!(
+            "DELETE FROM greenhouse_slots USING players
+            WHERE greenhouse_slots.player_id = players.id AND players.guild_id = $1 AND greenhouse_slots.species = $2",
+            guild_id as i64, species_id
+        ).execute(&mut transaction).await?;
+        Ok(transaction.commit().await?)
+    }
+
+    async fn load_player(&self, guild_id: u64, user_id: u64) -> Result<Option<Player>> {
+        let mut result = sqlx::query("
+            SELECT *, EXTRACT(EPOCH FROM CURRENT_TIMESTAMP - last_time_check)::float8 AS elapsed,
+                last_daily - DATE '1970-01-01' AS last_daily_day
+            FROM players WHERE user_id = $1 AND guild_id = $2")
+            .bind(user_id as i64)
+            .bind(guild_id as i64)
+            .fetch_optional(&self.0).await?;
+        let new = result.is_none();
+        if result.is_none() {
+            result = sqlx::query("INSERT INTO players (user_id, guild_id) VALUES ($1, $2) ON CONFLICT DO NOTHING RETURNING *, 0::float8 AS elapsed, NULL::int AS last_daily_day")
+                .bind(user_id as i64)
+                .bind(guild_id as i64)
+                .fetch_optional(&self.0).await?;
+        }
+
+        let result = match result {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+        let player_id: i32 = result.get("id");
+
+        let storage = sqlx::query!(
+            "SELECT item_type, item_id, amount FROM storage WHERE player_id = $1",
+            player_id
+        ).fetch_all(&self.0).await?
+            .into_iter()
+            .map(|item| Ok(((ItemType::try_from(item.item_type)?, item.item_id), item.amount)))
+            .collect::<Result<Storage>>()?;
+
+        let greenhouse = sqlx::query!(
+            "SELECT slot, species, remaining, grown, progress FROM greenhouse_slots WHERE player_id = $1",
+            player_id
+        ).fetch_all(&self.0).await?
+            .into_iter()
+            .map(|slot| (slot.slot, GreenhouseSlot {
+                species: slot.species,
+                remaining: slot.remaining,
+                grown: slot.grown,
+                progress: slot.progress,
+            }))
+            .collect();
+
+        let workers = sqlx::query!(
+            "SELECT id, channel_id, progress FROM workers WHERE player_id = $1 ORDER BY id",
+            player_id
+        ).fetch_all(&self.0).await?
+            .into_iter()
+            .map(|worker| Worker {
+                id: worker.id,
+                channel_id: worker.channel_id.map(|channel_id| channel_id as u64),
+                progress: worker.progress,
+            })
+            .collect();
+
+        let mut player = Player::new(player_id, user_id, guild_id, result.get("coins"), result.get("storage_upgrade"), storage, new);
+        player.greenhouse_upgrade = result.get("greenhouse_upgrade");
+        player.daily_streak = result.get("daily_streak");
+        player.last_daily = result.get::<Option<i32>, _>("last_daily_day").map(|day| day as u64);
+        player.greenhouse = greenhouse;
+        player.workers = workers;
+        player.achievements = sqlx::query!("SELECT achievement FROM achievements WHERE player_id = $1", player_id)
+            .fetch_all(&self.0).await?
+            .into_iter()
+            .map(|row| row.achievement)
+            .collect();
+        // time-based actions are computed lazily, the time spent offline is caught up on the next time check
+        let elapsed = Duration::from_secs_f64(result.get::<f64, _>("elapsed").max(0.0));
+        player.last_time_check = Instant::now().checked_sub(elapsed).unwrap_or_else(Instant::now);
+        Ok(Some(player))
+    }
+
+    async fn save_effects(
+        &self, guild_id: u64, user_id: u64, player_id: i32, effects: &Effects
+    ) -> Result<()> {
+        if effects.is_noop() {
+            return Ok(());
+        }
+
+        let mut transaction = self.0.begin().await?;
+        apply_effects(&mut transaction, guild_id, user_id, player_id, effects).await?;
+        Ok(transaction.commit().await?)
+    }
+
+    async fn save_time_check(
+        &self, guild_id: u64, user_id: u64, player_id: i32,
+        report: &TimeCheck, greenhouse: &Greenhouse, workers: &[Worker]
+    ) -> Result<()> {
+        let mut transaction = self.0.begin().await?;
+        sqlx::query!(
+            "UPDATE players SET last_time_check = CURRENT_TIMESTAMP, coins = coins + $2 WHERE id = $1",
+            player_id, report.coins
+        ).execute(&mut transaction).await?;
+        record(&mut transaction, player_id, None, report.coins, Reason::Work).await?;
+
+        for (slot_id, slot) in greenhouse.iter() {
+            sqlx::query!(
+                "UPDATE greenhouse_slots SET remaining = $3, grown = $4, progress = $5 WHERE player_id = $1 AND slot = $2",
+                player_id, *slot_id, slot.remaining, slot.grown, slot.progress
+            ).execute(&mut transaction).await?;
+        }
+        for worker in workers.iter() {
+            sqlx::query!("UPDATE workers SET progress = $2 WHERE id = $1", worker.id, worker.progress)
+                .execute(&mut transaction).await?;
+        }
+
+        for (channel_id, species_id, count) in report.planted.iter() {
+            // workers only plant finite seedlings, infinite (-1) amounts never match
+            let done = sqlx::query!(
+                "UPDATE storage SET amount = amount - $4
+                WHERE player_id = $1 AND item_type = $2 AND item_id = $3 AND amount >= $4",
+                player_id, ItemType::Seedling as i16, *species_id, *count
+            ).execute(&mut transaction).await?;
+            if done.rows_affected() == 0 {
+                return Err(Error::OutOfDate);
+            }
+            record(&mut transaction, player_id, Some((ItemType::Seedling, *species_id)), -count, Reason::Work).await?;
+            add_trees(&mut transaction, guild_id, user_id, *channel_id, *species_id, *count).await?;
+        }
+        Ok(transaction.commit().await?)
+    }
+
+    async fn player_trees(&self, guild_id: u64, user_id: u64) -> Result<Vec<(u64, i16, i64)>> {
+        let rows = sqlx::query!(
+            "SELECT channel_id, species, count FROM trees WHERE guild_id = $1 AND user_id = $2",
+            guild_id as i64, user_id as i64
+        ).fetch_all(&self.0).await?;
+        Ok(rows.into_iter()
+            .map(|tree| (tree.channel_id as u64, tree.species, tree.count as i64))
+            .collect())
+    }
+
+    async fn unlock_achievement(
+        &self, guild_id: u64, user_id: u64, player_id: i32, achievement: &str, reward: &Effects
+    ) -> Result<bool> {
+        let mut transaction = self.0.begin().await?;
+        let done = sqlx::query!(
+            "INSERT INTO achievements (player_id, achievement) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            player_id, achievement
+        ).execute(&mut transaction).await?;
+        if done.rows_affected() == 0 {
+            return Ok(false);
+        }
+        apply_effects(&mut transaction, guild_id, user_id, player_id, reward).await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    async fn save_trade(
+        &self, guild_id: u64, first: (u64, i32, &Effects), second: (u64, i32, &Effects)
+    ) -> Result<()> {
+        let mut transaction = self.0.begin().await?;
+        for (user_id, player_id, effects) in [first, second].iter() {
+            apply_effects(&mut transaction, guild_id, *user_id, *player_id, effects).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn gifts_today(&self, guild_id: u64, user_id: u64) -> Result<(i64, i64)> {
+        let gifts = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(amount) FILTER (WHERE item_type IS NULL), 0)::bigint AS "coins!",
+                COALESCE(SUM(amount) FILTER (WHERE item_type IS NOT NULL), 0)::bigint AS "items!"
+            FROM transfers
+            WHERE guild_id = $1 AND giver_id = $2 AND created_at >= date_trunc('day', now() AT TIME ZONE 'utc') AT TIME ZONE 'utc'"#,
+            guild_id as i64, user_id as i64
+        ).fetch_one(&self.0).await?;
+        Ok((gifts.coins, gifts.items))
+    }
+
+    async fn save_gift(
+        &self, guild_id: u64, giver: (u64, i32, &Effects), receiver: (u64, i32, &Effects), transfer: &Transfer, daily_cap: i32
+    ) -> Result<bool> {
+        let (giver_user_id, giver_id, giver_effects) = giver;
+        let (receiver_user_id, receiver_id, receiver_effects) = receiver;
+        let mut transaction = self.0.begin().await?;
+        // gifts of a player are serialized so that concurrent ones can't go over the cap together
+        sqlx::query!("SELECT id FROM players WHERE id = $1 FOR UPDATE", giver_id)
+            .fetch_one(&mut transaction).await?;
+        let given = sqlx::query!(
+            r#"SELECT COALESCE(SUM(amount), 0)::bigint AS "given!" FROM transfers
+            WHERE guild_id = $1 AND giver_id = $2 AND (item_type IS NULL) = $3
+                AND created_at >= date_trunc('day', now() AT TIME ZONE 'utc') AT TIME ZONE 'utc'"#,
+            guild_id as i64, transfer.giver_id as i64, transfer.item.is_none()
+        ).fetch_one(&mut transaction).await?.given;
+        if given + transfer.amount as i64 > daily_cap as i64 {
+            return Ok(false);
+        }
+
+        apply_effects(&mut transaction, guild_id, giver_user_id, giver_id, giver_effects).await?;
+        apply_effects(&mut transaction, guild_id, receiver_user_id, receiver_id, receiver_effects).await?;
+        sqlx::query!(
+            "INSERT INTO transfers (guild_id, giver_id, receiver_id, item_type, item_id, amount) VALUES ($1, $2, $3, $4, $5, $6)",
+            guild_id as i64, transfer.giver_id as i64, transfer.receiver_id as i64,
+            transfer.item.map(|(item_type, _)| item_type as i16), transfer.item.map(|(_, item_id)| item_id), transfer.amount
+        ).execute(&mut transaction).await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    async fn transfers(&self, guild_id: u64, user_id: Option<u64>, limit: usize) -> Result<Vec<Transfer>> {
+        let rows = sqlx::query!(
+            r#"SELECT giver_id, receiver_id, item_type, item_id, amount, EXTRACT(EPOCH FROM created_at)::float8 AS "created_at!"
+            FROM transfers
+            WHERE guild_id = $1 AND ($2::bigint IS NULL OR giver_id = $2 OR receiver_id = $2)
+            ORDER BY id DESC LIMIT $3"#,
+            guild_id as i64, user_id.map(|user_id| user_id as i64), limit as i64
+        ).fetch_all(&self.0).await?;
+
+        rows.into_iter()
+            .map(|row| Ok(Transfer {
+                giver_id: row.giver_id as u64,
+                receiver_id: row.receiver_id as u64,
+                item: match (row.item_type, row.item_id) {
+                    (Some(item_type), Some(item_id)) => Some((ItemType::try_from(item_type)?, item_id)),
+                    _ => None,
+                },
+                amount: row.amount,
+                created_at: UNIX_EPOCH + Duration::from_secs_f64(row.created_at),
+            }))
+            .collect()
+    }
+
+    async fn forest_totals(&self, guild_id: u64, channel_id: u64) -> Result<(i64, i64)> {
+        let totals = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(count) FILTER (WHERE channel_id = $2), 0)::bigint AS "channel_total!",
+                COALESCE(SUM(count), 0)::bigint AS "server_total!"
+            FROM trees WHERE guild_id = $1"#,
+            guild_id as i64, channel_id as i64
+        ).fetch_one(&self.0).await?;
+        Ok((totals.channel_total, totals.server_total))
+    }
+
+    async fn reach_milestones(&self, guild_id: u64, channel_id: u64, thresholds: &[i64]) -> Result<Vec<i64>> {
+        let rows = sqlx::query!(
+            "INSERT INTO milestones (guild_id, channel_id, threshold) SELECT $1, $2, threshold FROM unnest($3::bigint[]) AS threshold
+            ON CONFLICT DO NOTHING RETURNING threshold",
+            guild_id as i64, channel_id as i64, thresholds
+        ).fetch_all(&self.0).await?;
+        Ok(rows.into_iter().map(|row| row.threshold).collect())
+    }
+
+    async fn top_contributors(&self, guild_id: u64, channel_id: u64, limit: usize) -> Result<Vec<(u64, i64)>> {
+        let rows = sqlx::query!(
+            r#"SELECT user_id, SUM(count)::bigint AS "count!" FROM trees
+            WHERE guild_id = $1 AND ($2 = 0 OR channel_id = $2)
+            GROUP BY user_id ORDER BY 2 DESC, user_id LIMIT $3"#,
+            guild_id as i64, channel_id as i64, limit as i64
+        ).fetch_all(&self.0).await?;
+        Ok(rows.into_iter().map(|row| (row.user_id as u64, row.count)).collect())
+    }
+
+    async fn species_counts(&self, filter: &TreeFilter, limit: Option<usize>) -> Result<Vec<SpeciesCount>> {
+        let rows = sqlx::query(&format!("{}
+            SELECT species, SUM(count)::bigint AS total,
+                ROUND(SUM(count)::float / (SELECT SUM(count) FROM forest) * 10000) / 100 AS percent
+            FROM forest GROUP BY species
+            ORDER BY total DESC, species LIMIT $4
+        ", forest(filter)))
+            .bind(scope_id(filter.scope))
+            .bind(filter.user_id.map(|user_id| user_id as i64))
+            .bind(filter.species)
+            .bind(limit.map(|limit| limit as i64))
+            .fetch_all(&self.0).await?;
+        rows.into_iter()
+            .map(|row| Ok(SpeciesCount {
+                species: row.try_get("species")?,
+                count: row.try_get("total")?,
+                percent: row.try_get("percent")?,
+            }))
+            .collect::<Result<Vec<SpeciesCount>>>()
+    }
+
+    async fn channel_counts(&self, filter: &TreeFilter, limit: usize) -> Result<Vec<(u64, i64)>> {
+        let rows = sqlx::query(&format!("{}
+            SELECT channel_id, SUM(count)::bigint AS total FROM forest
+            GROUP BY channel_id ORDER BY total DESC, channel_id LIMIT $4
+        ", forest(filter)))
+            .bind(scope_id(filter.scope))
+            .bind(filter.user_id.map(|user_id| user_id as i64))
+            .bind(filter.species)
+            .bind(limit as i64)
+            .fetch_all(&self.0).await?;
+        rows.into_iter()
+            .map(|row| Ok((row.try_get::<i64, _>("channel_id")? as u64, row.try_get("total")?)))
+            .collect::<Result<Vec<(u64, i64)>>>()
+    }
+
+    async fn tree_leaderboard(
+        &self, filter: &TreeFilter, user_id: u64, offset: usize, limit: usize
+    ) -> Result<(Vec<Ranked>, usize)> {
+        // ranks every planter, then keeps a page and the row of user $4
+        let rows = sqlx::query(&format!("{},
+            total AS (SELECT SUM(count) AS total FROM forest),
+            ranking AS (
+                SELECT a.user_id, b.total,
+                    ROUND(b.total::float / (SELECT total FROM total) * 10000) / 100 AS percent,
+                    a.species AS fav_species, a.channel_id AS fav_channel,
+                    ROW_NUMBER() OVER (ORDER BY b.total DESC, a.user_id) AS rank,
+                    COUNT(*) OVER () AS ranked
+                FROM (
+                    SELECT DISTINCT ON (user_id) user_id, species, channel_id
+                    FROM forest ORDER BY user_id, count DESC, species, channel_id
+                ) a
+                INNER JOIN (
+                    SELECT user_id, SUM(count)::bigint total
+                    FROM forest GROUP BY user_id
+                ) b
+                ON a.user_id = b.user_id
+            )
+            SELECT * FROM ranking
+            WHERE (rank > $5 AND rank <= $5 + $6) OR user_id = $4
+            ORDER BY rank
+        ", forest(filter)))
+            .bind(scope_id(filter.scope))
+            .bind(filter.user_id.map(|user_id| user_id as i64))
+            .bind(filter.species)
+            .bind(user_id as i64)
+            .bind(offset as i64)
+            .bind(limit as i64)
+            .fetch_all(&self.0).await?;
+        ranking(rows, |row| Ok((
+            row.try_get("percent")?,
+            Some((row.try_get("fav_species")?, row.try_get::<i64, _>("fav_channel")? as u64)),
+        )))
+    }
+
+    async fn coin_leaderboard(
+        &self, guild_id: u64, user_id: u64, offset: usize, limit: usize
+    ) -> Result<(Vec<Ranked>, usize)> {
+        let rows = sqlx::query("
+            WITH ranking AS (
+                SELECT user_id, coins::bigint AS total,
+                    ROW_NUMBER() OVER (ORDER BY coins DESC, user_id) AS rank,
+                    COUNT(*) OVER () AS ranked
+                FROM players WHERE guild_id = $1
+            )
+            SELECT * FROM ranking
+            WHERE (rank > $3 AND rank <= $3 + $4) OR user_id = $2
+            ORDER BY rank
+        ")
+            .bind(guild_id as i64)
+            .bind(user_id as i64)
+            .bind(offset as i64)
+            .bind(limit as i64)
+            .fetch_all(&self.0).await?;
+        ranking(rows, |_| Ok((0.0, None)))
+    }
+
+    async fn ledger(&self, player_id: i32, offset: usize, limit: usize) -> Result<(Vec<LedgerEntry>, usize)> {
+        let total = sqlx::query!(r#"SELECT COUNT(*) AS "total!" FROM ledger WHERE player_id = $1"#, player_id)
+            .fetch_one(&self.0).await?.total;
+        let rows = sqlx::query!(
+            r#"SELECT item_type, item_id, delta, reason, EXTRACT(EPOCH FROM created_at)::float8 AS "created_at!"
+            FROM ledger WHERE player_id = $1
+            ORDER BY id DESC OFFSET $2 LIMIT $3"#,
+            player_id, offset as i64, limit as i64
+        ).fetch_all(&self.0).await?;
+
+        let entries = rows.into_iter()
+            .map(|row| Ok(LedgerEntry {
+                item: match (row.item_type, row.item_id) {
+                    (Some(item_type), Some(item_id)) => Some((ItemType::try_from(item_type)?, item_id)),
+                    _ => None,
+                },
+                delta: row.delta,
+                reason: Reason::try_from(row.reason.as_str())?,
+                created_at: UNIX_EPOCH + Duration::from_secs_f64(row.created_at),
+            }))
+            .collect::<Result<Vec<LedgerEntry>>>()?;
+        Ok((entries, total as usize))
+    }
+
+    async fn reconcile(&self, guild_id: u64) -> Result<Vec<(u64, i32, i64)>> {
+        let rows = sqlx::query!(
+            r#"SELECT players.user_id, players.coins, COALESCE(SUM(ledger.delta), 0)::bigint AS "ledger!"
+            FROM players LEFT JOIN ledger ON ledger.player_id = players.id AND ledger.item_type IS NULL
+            WHERE players.guild_id = $1
+            GROUP BY players.id, players.user_id, players.coins
+            HAVING players.coins <> COALESCE(SUM(ledger.delta), 0)
+            ORDER BY players.user_id"#,
+            guild_id as i64
+        ).fetch_all(&self.0).await?;
+        Ok(rows.into_iter().map(|row| (row.user_id as u64, row.coins, row.ledger)).collect())
+    }
+}
@@ -0,0 +1,55 @@
+use serenity::{
+    client::Context,
+    model::channel::Message,
+    prelude::{RwLock, TypeMap},
+};
+
+use crate::error::{Error, Result};
+use crate::prelude::*;
+
+/// Compute and persist time-based actions (greenhouse growth and workers) of the message author.
+/// The player must already be cached, it is only updated once the time check has been saved.
+pub async fn run(ctx: &Context, message: &Message) -> Result<()> {
+    let guild_id = message.guild_id.ok_or(Error::Missing("server"))?.0;
+    run_for(&ctx.data, guild_id, message.author.id.0).await
+}
+
+/// Same as `run`, for any cached player
+pub async fn run_for(data: &RwLock<TypeMap>, guild_id: u64, user_id: u64) -> Result<()> {
+    let mut cache = data.write().await;
+    let store = cache.get::<DataStore>().unwrap().clone();
+    let server = cache.get_mut::<ServerCache>().unwrap().0.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+    let player = server.player_cache.1.get_mut(&user_id).ok_or(Error::Missing("player"))?;
+    let mut checked = player.clone();
+    let report = checked.time_check(&server.species, &server.forest_rules);
+    // claimed right away so that concurrent time checks don't count the same time twice
+    let previous_check = player.last_time_check;
+    player.last_time_check = checked.last_time_check;
+    std::mem::drop(cache);
+
+    let saved = store.save_time_check(
+        guild_id, user_id, checked.id,
+        &report, &checked.greenhouse, &checked.workers
+    ).await;
+
+    let mut cache = data.write().await;
+    let server = cache.get_mut::<ServerCache>().unwrap().0.get_mut(&guild_id).ok_or(Error::Missing("server"))?;
+    let player = server.player_cache.1.get_mut(&user_id).ok_or(Error::Missing("player"))?;
+    match &saved {
+        Ok(()) => player.apply_time_check(&report, &checked),
+        // the elapsed time is left to the next time check
+        Err(_) if player.last_time_check == checked.last_time_check => player.last_time_check = previous_check,
+        Err(_) => {}
+    }
+    saved
+}
+
+/// Cached players whose workers or greenhouse keep going without them, as (guild_id, user_id)
+pub async fn busy_players(data: &RwLock<TypeMap>) -> Vec<(u64, u64)> {
+    let cache = data.read().await;
+    cache.get::<ServerCache>().unwrap().0.iter()
+        .flat_map(|(guild_id, server)| server.player_cache.1.values()
+            .filter(|player| !player.workers.is_empty() || !player.greenhouse.is_empty())
+            .map(move |player| (*guild_id, player.user_id)))
+        .collect()
+}